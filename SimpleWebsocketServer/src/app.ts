@@ -1,49 +1,662 @@
 import WebSocket, { WebSocketServer } from 'ws';
+import http from 'http';
+import crypto from 'crypto';
+import {
+    ConnectionCounter,
+    ErrorReply,
+    FloodGuard,
+    MAX_FRAME_BYTES,
+    MAX_MESSAGE_LENGTH,
+    MESSAGE_BURST,
+    MESSAGES_PER_SECOND,
+} from './limits';
+import { HEARTBEAT_MS, PongTracker } from './heartbeat';
 
 const PORT = process.env.PORT ? parseInt(process.env.PORT) : 8080;
+// Bumped whenever a frame changes in a way older clients would misread.
+const PROTOCOL_VERSION = 1;
+const SERVER_NAME = process.env.SERVER_NAME || 'YewChat';
+// The message of the day, shown to everyone as they connect.
+const MOTD = process.env.MOTD ?? '';
+// Uploads and search can be switched off with `UPLOADS=off` and `SEARCH=off`.
+const FEATURES = {
+    rooms: false,
+    uploads: process.env.UPLOADS !== 'off',
+    search: process.env.SEARCH !== 'off',
+};
+
+type Presence = 'online' | 'away' | 'dnd';
+const PRESENCES: Presence[] = ['online', 'away', 'dnd'];
+
+interface Profile {
+    statusText: string;
+    statusEmoji: string;
+    pronouns: string;
+    title: string;
+    timezone: string;
+}
+const PROFILE_FIELDS: (keyof Profile)[] = ['statusText', 'statusEmoji', 'pronouns', 'title', 'timezone'];
+const MAX_PROFILE_FIELD_LENGTH = 80;
+const MAX_MENTIONS = 20;
+const MAX_CLIENT_ID_LENGTH = 64;
+const HISTORY_SIZE = 5000;
+// Messages per history frame; clients page further back with `history` requests.
+const HISTORY_PAGE = 50;
+// Older messages sent along with one a client jumps to, so it shows in context.
+const JUMP_CONTEXT = 10;
+const SEARCH_RESULTS = 50;
+const MAX_PINS = 50;
+const MAX_TOPIC_LENGTH = 250;
+const DAY_MS = 24 * 60 * 60 * 1000;
+// Offline users are listed until they have been away this long, most recent first up to the cap.
+const OFFLINE_RETENTION_MS = 30 * DAY_MS;
+const MAX_OFFLINE_USERS = 200;
+
+const AVATAR_TYPES = ['image/png', 'image/jpeg', 'image/gif', 'image/webp'];
+const MAX_AVATAR_BYTES = 256 * 1024;
+const MAX_AVATARS = 500;
+
+const WEBHOOK_TIMEOUT_MS = 5000;
+const MAX_WEBHOOK_RESPONSE_BYTES = 64 * 1024;
+// Outgoing webhooks may only call services on this machine.
+const WEBHOOK_HOSTS = ['localhost', '127.0.0.1', '[::1]'];
+const MAX_WEBHOOK_PAYLOAD_BYTES = 64 * 1024;
+const MAX_WEBHOOK_USERNAME_LENGTH = 40;
+// Everyone is in #general for now; webhooks name the room so they keep working once there are more.
+const ROOMS = ['general'];
+
+type Role = 'member' | 'moderator' | 'admin';
+const ROLES: Role[] = ['member', 'moderator', 'admin'];
+// Sent in a register frame's `dataArray` to claim a role from `ROLES`.
+const KEY_PREFIX = 'key:';
+// Closes the socket of someone kicked or banned, so their client knows not to come back.
+const CLOSE_REMOVED = 4000;
+// Closes a connection refused for being one too many from its address, so the client waits a while.
+const CLOSE_TOO_MANY_CONNECTIONS = 4001;
+const MAX_MUTE_MINUTES = 7 * 24 * 60;
+const MAX_AUDIT_ENTRIES = 500;
+// Entries sent in answer to an `audit` request.
+const AUDIT_PAGE = 50;
+
+interface Avatar {
+    contentType: string;
+    data: Buffer;
+}
+
 interface User {
     ws: WebSocket;
     nick: String;
-    isAlive: boolean;
+    presence: Presence;
+    profile: Profile;
+    avatar?: string;
+    // Registered with `bot` in `dataArray`.
+    bot: boolean;
+    role: Role;
+    ip: string;
+}
+
+interface OfflineUser {
+    nick: String;
+    lastSeen: number;
+    profile: Profile;
+    avatar?: string;
+    bot: boolean;
+    role: Role;
+    ip: string;
 }
 
 interface Message {
     messageType: String;
     data: String;
     dataArray: String[];
+    clientId?: unknown;
+}
+
+interface ChatMessage {
+    id: number;
+    from: String;
+    message: String;
+    mentions: String[];
+    time: number;
+    // The sender's own ID for the message, used to acknowledge it and to spot resends.
+    clientId?: string;
+    edited?: boolean;
+    // Posted by a bot account or a webhook.
+    bot: boolean;
+    // Shown instead of the sender's avatar: the path of an uploaded avatar.
+    avatar?: string;
+}
+
+interface OutgoingWebhook {
+    // Replies are posted under this name.
+    name: string;
+    // Messages starting with this are POSTed to `url`.
+    trigger: string;
+    url: string;
+}
+
+interface Pin {
+    message: ChatMessage;
+    pinnedBy: String;
+    // Where `pinnedBy` was connected from; never sent to clients.
+    pinnedFrom: string;
+}
+
+interface RoomTopic {
+    text: string;
+    setBy?: String;
+}
+
+// Read the same way as the client's `search::Query`.
+interface SearchQuery {
+    // Lowercased words that must all appear in the text.
+    words: string[];
+    from?: string;
+    room?: string;
+    before?: number;
+    after?: number;
+    hasImage: boolean;
+}
+
+interface RoleGrant {
+    role: Role;
+    // Sent as `key:<key>` in the register frame's `dataArray`.
+    key: string;
+}
+
+interface AuditEntry {
+    time: number;
+    actor: String;
+    action: string;
+    target: String;
+    detail?: string;
+}
+
+interface IncomingWebhook {
+    room: string;
+    // Sent as `Authorization: Bearer <token>`.
+    token: string;
+    // Posts under this name unless the payload gives a `username`.
+    name: string;
 }
 
 let users: User[] = [];
+let offlineUsers: OfflineUser[] = [];
+// Keyed by server path, e.g. `/avatars/3f2a...`.
+const avatars = new Map<string, Avatar>();
+let history: ChatMessage[] = [];
+// nick -> conversation -> ID of the newest message they have read.
+const readMarkers = new Map<String, Map<String, number>>();
+// nick -> ID of the newest message they have acknowledged seeing.
+const receipts = new Map<String, number>();
+// Oldest pin first.
+let pins: Pin[] = [];
+let topic: RoomTopic = { text: '' };
+// Message ID -> the IP address it was sent from. Only the sender may change a message,
+// and a nick alone doesn't make someone the sender.
+const senders = new Map<number, string>();
+// IP address -> when the mute on it runs out; a new nick or connection doesn't lift it.
+const mutes = new Map<string, number>();
+// IP address -> the nick whose ban covers it, so unbanning them lifts it too.
+const bannedIps = new Map<string, String>();
+// Oldest first.
+let auditLog: AuditEntry[] = [];
+const connections = new ConnectionCounter();
+const pongs = new PongTracker<WebSocket>();
+// Seeded from the clock so IDs keep increasing across restarts.
+let nextMessageId = Date.now();
+
+const server = http.createServer((req, res) => {
+    res.setHeader('Access-Control-Allow-Origin', '*');
+    res.setHeader('Access-Control-Allow-Headers', 'Content-Type');
+    if (req.method === 'OPTIONS') {
+        res.writeHead(204).end();
+    } else if (req.method === 'POST' && req.url === '/avatars' && !FEATURES.uploads) {
+        res.writeHead(403).end('uploads are disabled');
+    } else if (req.method === 'POST' && req.url === '/avatars') {
+        uploadAvatar(req, res);
+    } else if (req.method === 'POST' && req.url === '/webhooks') {
+        receiveWebhook(req, res);
+    } else if (req.method === 'GET' && req.url && avatars.has(req.url)) {
+        const avatar = avatars.get(req.url)!;
+        // Only ever the image type it was uploaded as; never sniffed as, say, HTML.
+        res.writeHead(200, {
+            'Content-Type': avatar.contentType,
+            'Cache-Control': 'public, max-age=31536000, immutable',
+            'X-Content-Type-Options': 'nosniff',
+        }).end(avatar.data);
+    } else {
+        res.writeHead(404).end('not found');
+    }
+});
+
+const uploadAvatar = (req: http.IncomingMessage, res: http.ServerResponse) => {
+    const contentType = req.headers['content-type'] ?? '';
+    if (!AVATAR_TYPES.includes(contentType)) {
+        res.writeHead(415).end('unsupported image type');
+        return;
+    }
+    const chunks: Buffer[] = [];
+    let size = 0;
+    req.on('data', (chunk: Buffer) => {
+        size += chunk.length;
+        if (size > MAX_AVATAR_BYTES) {
+            res.writeHead(413, { Connection: 'close' }).end('image too large');
+            req.destroy();
+            return;
+        }
+        chunks.push(chunk);
+    });
+    req.on('end', () => {
+        if (size > MAX_AVATAR_BYTES) {
+            return;
+        }
+        const path = `/avatars/${crypto.randomBytes(12).toString('hex')}`;
+        avatars.set(path, { contentType, data: Buffer.concat(chunks) });
+        if (avatars.size > MAX_AVATARS) {
+            avatars.delete(avatars.keys().next().value!);
+        }
+        res.writeHead(201, { 'Content-Type': 'text/plain' }).end(path);
+    });
+};
+
+// `INCOMING_WEBHOOKS` holds a JSON array of `{ room, token, name }`: one entry per token, so
+// each room can have its own and one can be revoked without touching the others.
+const parseIncomingWebhooks = (parsed: unknown): IncomingWebhook[] =>
+    (Array.isArray(parsed) ? parsed : []).filter((hook) => {
+        const valid = ROOMS.includes(hook.room) && isText(hook.token) && isText(hook.name);
+        if (!valid) {
+            console.log('Ignoring incoming webhook for room', hook.room);
+        }
+        return valid;
+    });
+
+// Compares hashes so the time taken doesn't give away how much of a token matched.
+const tokenMatches = (given: string, expected: string) => {
+    const digest = (token: string) => crypto.createHash('sha256').update(token).digest();
+    return crypto.timingSafeEqual(digest(given), digest(expected));
+};
+
+// Avatar overrides must be one of our uploaded avatars. Anywhere else, every client
+// showing the message would fetch whatever a webhook pointed them at.
+const parseAvatarOverride = (raw: unknown): string | undefined =>
+    typeof raw === 'string' && avatars.has(raw) ? raw : undefined;
+
+// Posts `{ text, room, username?, avatar? }` to `room` as a bot message.
+const receiveWebhook = (req: http.IncomingMessage, res: http.ServerResponse) => {
+    const auth = req.headers.authorization ?? '';
+    const token = auth.startsWith('Bearer ') ? auth.slice('Bearer '.length) : '';
+    const hooks = incomingWebhooks.filter((hook) => tokenMatches(token, hook.token));
+    if (hooks.length === 0) {
+        res.writeHead(401).end('unknown token');
+        return;
+    }
+    const chunks: Buffer[] = [];
+    let size = 0;
+    req.on('data', (chunk: Buffer) => {
+        size += chunk.length;
+        if (size > MAX_WEBHOOK_PAYLOAD_BYTES) {
+            res.writeHead(413, { Connection: 'close' }).end('payload too large');
+            req.destroy();
+            return;
+        }
+        chunks.push(chunk);
+    });
+    req.on('end', () => {
+        if (size > MAX_WEBHOOK_PAYLOAD_BYTES) {
+            return;
+        }
+        let payload: any;
+        try {
+            payload = JSON.parse(Buffer.concat(chunks).toString());
+        } catch {
+            res.writeHead(400).end('payload is not JSON');
+            return;
+        }
+        if (!payload || !isText(payload.text)) {
+            res.writeHead(400).end('text is required');
+            return;
+        }
+        if (!ROOMS.includes(payload.room)) {
+            res.writeHead(404).end('no such room');
+            return;
+        }
+        const hook = hooks.find((h) => h.room === payload.room);
+        if (!hook) {
+            res.writeHead(403).end('token is not for this room');
+            return;
+        }
+        const username = isText(payload.username) ? payload.username.slice(0, MAX_WEBHOOK_USERNAME_LENGTH) : hook.name;
+        const message = postMessage(username, payload.text, [], true, undefined, parseAvatarOverride(payload.avatar));
+        res.writeHead(201, { 'Content-Type': 'application/json' }).end(JSON.stringify({ id: message.id }));
+    });
+};
 
 console.log(`Listening on port ${PORT}`);
-const wss = new WebSocketServer({ port: PORT });
+// Anything past the frame limit gets an error reply; far past it, the socket is simply closed.
+const wss = new WebSocketServer({ server, maxPayload: 4 * MAX_FRAME_BYTES });
+server.listen(PORT);
 
-wss.on('connection', (ws: WebSocket) => {
+wss.on('connection', (ws: WebSocket, req: http.IncomingMessage) => {
     console.log('ws connected');
+    const ip = req.socket.remoteAddress ?? '';
+    if (bannedIps.has(ip)) {
+        ws.send(moderationFrame({ action: 'banned' }));
+        ws.close(CLOSE_REMOVED, 'banned');
+        return;
+    }
+    if (!connections.open(ip)) {
+        ws.send(errorFrame({ code: 'too_many_connections', message: 'Too many connections from your address.' }));
+        ws.close(CLOSE_TOO_MANY_CONNECTIONS, 'too many connections');
+        return;
+    }
+    ws.on('close', () => {
+        connections.close(ip);
+        pongs.forget(ws);
+    });
+    // Browsers answer protocol pings themselves; anything at all from the client counts too.
+    pongs.heard(ws);
+    ws.on('pong', () => pongs.heard(ws));
+    ws.send(helloFrame());
+    const guard = new FloodGuard();
+    // Answers a rejected frame, and hangs up on a client that won't stop.
+    const reject = (reply: ErrorReply) => {
+        ws.send(errorFrame(reply));
+        if (guard.exhausted) {
+            console.log('Dropping flooding client from', ip);
+            ws.close();
+        }
+    };
 
     ws.on('message', (data) => {
+        pongs.heard(ws);
         const raw_data = data.toString();
+        const frameError = guard.frame(Buffer.byteLength(raw_data));
+        if (frameError) {
+            reject(frameError);
+            return;
+        }
         try {
             const parsed_data: Message = JSON.parse(raw_data);
+            // Chat messages and edits are accepted by `guard.message`, if they pass it.
+            if (parsed_data.messageType !== 'message' && parsed_data.messageType !== 'edit') {
+                guard.accept();
+            }
             switch (parsed_data.messageType) {
-                case 'register':
-                    users.push({ ws, nick: parsed_data.data, isAlive: true });
-                    broadcast(JSON.stringify({ messageType: 'users', dataArray: users.map((u) => u.nick) }));
+                case 'register': {
+                    // Still connected from an address banned since.
+                    if (bannedIps.has(ip)) {
+                        ws.send(moderationFrame({ action: 'banned' }));
+                        ws.close(CLOSE_REMOVED, 'banned');
+                        break;
+                    }
+                    // Nicks are unique among open sockets; a socket may register again as itself.
+                    const holder = users.find((u) => u.nick === parsed_data.data && u.ws !== ws);
+                    if (holder && holder.ws.readyState === WebSocket.OPEN) {
+                        ws.send(errorFrame({ code: 'nick_taken', message: `${parsed_data.data} is already taken.` }));
+                        break;
+                    }
+                    const flags = Array.isArray(parsed_data.dataArray) ? parsed_data.dataArray : [];
+                    const key = flags.find((f) => typeof f === 'string' && f.startsWith(KEY_PREFIX));
+                    const role = key ? claimRole(parsed_data.data, key.slice(KEY_PREFIX.length)) : 'member';
+                    if (key && role === 'member') {
+                        ws.send(moderationFrame({ action: 'denied', reason: 'That role key is not valid for this nickname; you joined as a member.' }));
+                    }
+                    // A client re-registers after reconnecting; never list one socket twice.
+                    users = users.filter((u) => u.ws !== ws);
+                    users.push({
+                        ws,
+                        nick: parsed_data.data,
+                        presence: 'online',
+                        profile: emptyProfile(),
+                        bot: flags.includes('bot'),
+                        role,
+                        ip,
+                    });
+                    offlineUsers = offlineUsers.filter((u) => u.nick !== parsed_data.data);
+                    broadcastUsers();
+                    readMarkers.get(parsed_data.data)?.forEach((lastRead, conversation) => {
+                        ws.send(JSON.stringify({ messageType: 'marker', data: JSON.stringify({ conversation, lastRead }) }));
+                    });
+                    ws.send(JSON.stringify({ messageType: 'history', data: JSON.stringify(history.slice(-HISTORY_PAGE)) }));
+                    ws.send(pinsFrame());
+                    ws.send(topicFrame());
+                    ws.send(
+                        JSON.stringify({
+                            messageType: 'receipts',
+                            data: JSON.stringify(Array.from(receipts, ([nick, id]) => ({ nick, id }))),
+                        })
+                    );
                     break;
-                case 'message':
-                    const sender = users.find((u) => u.ws === ws);
-                    if (sender) {
-                        broadcast(
+                }
+                case 'history': {
+                    const before = Number(parsed_data.data);
+                    // An ID in `dataArray` asks for everything back to that message.
+                    const until = Number(parsed_data.dataArray?.[0]);
+                    if (Number.isSafeInteger(before)) {
+                        const older = history.filter((m) => m.id < before);
+                        const target = Number.isSafeInteger(until) ? older.findIndex((m) => m.id >= until) : -1;
+                        const start = Math.min(
+                            older.length - HISTORY_PAGE,
+                            target >= 0 ? target - JUMP_CONTEXT : older.length
+                        );
+                        const page = older.slice(Math.max(0, start));
+                        ws.send(JSON.stringify({ messageType: 'history', data: JSON.stringify(page) }));
+                    }
+                    break;
+                }
+                case 'pin': {
+                    const user = users.find((u) => u.ws === ws);
+                    const id = Number(parsed_data.data);
+                    const message = history.find((m) => m.id === id);
+                    const until = user && mutedUntil(user.ip);
+                    if (until) {
+                        ws.send(moderationFrame({ action: 'muted', until }));
+                        break;
+                    }
+                    if (user && message && pins.length < MAX_PINS && !pins.some((p) => p.message.id === id)) {
+                        pins.push({ message, pinnedBy: user.nick, pinnedFrom: user.ip });
+                        broadcast(pinsFrame());
+                    }
+                    break;
+                }
+                case 'unpin': {
+                    // Whoever pinned a message may take it down again; anyone else's pins
+                    // are for moderators.
+                    const user = users.find((u) => u.ws === ws);
+                    const id = Number(parsed_data.data);
+                    const pin = pins.find((p) => p.message.id === id);
+                    if (!user || !pin) {
+                        break;
+                    }
+                    const until = mutedUntil(user.ip);
+                    if (until) {
+                        ws.send(moderationFrame({ action: 'muted', until }));
+                        break;
+                    }
+                    const own = pin.pinnedBy === user.nick && pin.pinnedFrom === user.ip;
+                    if (!own && !isPrivileged(user.role)) {
+                        ws.send(moderationFrame({ action: 'denied', reason: 'Only moderators and admins can unpin messages pinned by someone else.' }));
+                        break;
+                    }
+                    pins = pins.filter((p) => p.message.id !== id);
+                    broadcast(pinsFrame());
+                    break;
+                }
+                case 'topic': {
+                    // An empty topic clears it.
+                    const user = users.find((u) => u.ws === ws);
+                    const until = user && mutedUntil(user.ip);
+                    if (until) {
+                        ws.send(moderationFrame({ action: 'muted', until }));
+                        break;
+                    }
+                    if (user && !isPrivileged(user.role)) {
+                        ws.send(moderationFrame({ action: 'denied', reason: 'Only moderators and admins can change the topic.' }));
+                        break;
+                    }
+                    if (user && typeof parsed_data.data === 'string') {
+                        const text = parsed_data.data.trim().slice(0, MAX_TOPIC_LENGTH);
+                        topic = text ? { text, setBy: user.nick } : { text: '' };
+                        broadcast(topicFrame());
+                    }
+                    break;
+                }
+                case 'moderate': {
+                    const actor = users.find((u) => u.ws === ws);
+                    if (actor) {
+                        moderate(actor, JSON.parse(parsed_data.data.toString()));
+                    }
+                    break;
+                }
+                case 'audit': {
+                    const user = users.find((u) => u.ws === ws);
+                    if (user && isPrivileged(user.role)) {
+                        ws.send(JSON.stringify({ messageType: 'audit', data: JSON.stringify(auditLog.slice(-AUDIT_PAGE)) }));
+                    } else if (user) {
+                        ws.send(moderationFrame({ action: 'denied', reason: 'Only moderators and admins can see the moderation log.' }));
+                    }
+                    break;
+                }
+                case 'search': {
+                    if (!FEATURES.search) {
+                        reject({ code: 'feature_disabled', message: 'Search is switched off on this server.' });
+                    } else if (isText(parsed_data.data)) {
+                        const query = parseSearch(parsed_data.data);
+                        const results = history
+                            .filter((m) => matchesSearch(query, m))
+                            .slice(-SEARCH_RESULTS)
+                            .reverse();
+                        ws.send(
                             JSON.stringify({
-                                messageType: 'message',
-                                data: JSON.stringify({
-                                    from: sender.nick,
-                                    message: parsed_data.data,
-                                    time: Date.now(),
-                                }),
+                                messageType: 'search',
+                                data: JSON.stringify({ query: parsed_data.data, results }),
                             })
                         );
                     }
+                    break;
+                }
+                case 'read': {
+                    const user = users.find((u) => u.ws === ws);
+                    const id = Number(parsed_data.data);
+                    if (user && Number.isSafeInteger(id) && id > (receipts.get(user.nick) ?? 0)) {
+                        receipts.set(user.nick, id);
+                        broadcast(JSON.stringify({ messageType: 'read', data: JSON.stringify({ nick: user.nick, id }) }));
+                    }
+                    break;
+                }
+                case 'marker': {
+                    const user = users.find((u) => u.ws === ws);
+                    const marker = JSON.parse(parsed_data.data.toString());
+                    if (user && typeof marker.conversation === 'string' && Number.isSafeInteger(marker.lastRead)) {
+                        if (!readMarkers.has(user.nick)) {
+                            readMarkers.set(user.nick, new Map());
+                        }
+                        readMarkers.get(user.nick)!.set(marker.conversation, marker.lastRead);
+                    }
+                    break;
+                }
+                case 'profile': {
+                    const user = users.find((u) => u.ws === ws);
+                    if (user) {
+                        user.profile = parseProfile(parsed_data.data);
+                        broadcastUsers();
+                    }
+                    break;
+                }
+                case 'avatar': {
+                    const user = users.find((u) => u.ws === ws);
+                    const path = parsed_data.data.toString();
+                    if (user && (path === '' || avatars.has(path))) {
+                        user.avatar = path || undefined;
+                        broadcastUsers();
+                    }
+                    break;
+                }
+                // Browsers can't see protocol pings, so clients ask for an answer of their own.
+                case 'ping': {
+                    ws.send(JSON.stringify({ messageType: 'pong' }));
+                    break;
+                }
+                case 'presence': {
+                    const user = users.find((u) => u.ws === ws);
+                    const presence = PRESENCES.find((p) => p === parsed_data.data);
+                    if (user && presence && user.presence !== presence) {
+                        user.presence = presence;
+                        broadcastUsers();
+                    }
+                    break;
+                }
+                case 'message': {
+                    const sender = users.find((u) => u.ws === ws);
+                    if (!sender || !isText(parsed_data.data)) {
+                        break;
+                    }
+                    const clientId =
+                        typeof parsed_data.clientId === 'string'
+                            ? parsed_data.clientId.slice(0, MAX_CLIENT_ID_LENGTH)
+                            : undefined;
+                    // A resend of something we already have (the ack got lost): just ack again.
+                    const existing = clientId && history.find((m) => m.from === sender.nick && m.clientId === clientId);
+                    if (existing) {
+                        ws.send(ack(existing));
+                        break;
+                    }
+                    const until = mutedUntil(sender.ip);
+                    if (until) {
+                        ws.send(moderationFrame({ action: 'muted', until, clientId }));
+                        break;
+                    }
+                    const messageError = guard.message(parsed_data.data, clientId);
+                    if (messageError) {
+                        reject(messageError);
+                        break;
+                    }
+                    const message = postMessage(
+                        sender.nick,
+                        parsed_data.data,
+                        parseMentions(parsed_data.dataArray),
+                        sender.bot,
+                        clientId
+                    );
+                    senders.set(message.id, sender.ip);
+                    if (clientId) {
+                        ws.send(ack(message));
+                    }
+                    break;
+                }
+                case 'edit': {
+                    // Only the sender may edit a message, and only its text and mentions.
+                    const sender = users.find((u) => u.ws === ws);
+                    const edit = JSON.parse(parsed_data.data.toString());
+                    const until = sender && mutedUntil(sender.ip);
+                    if (until) {
+                        ws.send(moderationFrame({ action: 'muted', until }));
+                        break;
+                    }
+                    const editError = isText(edit.message) ? guard.message(edit.message) : undefined;
+                    if (editError) {
+                        reject(editError);
+                        break;
+                    }
+                    const index = history.findIndex((m) => m.id === edit.id && sender !== undefined && isOwn(sender, m));
+                    if (index >= 0 && isText(edit.message)) {
+                        const message: ChatMessage = {
+                            ...history[index],
+                            message: edit.message,
+                            mentions: parseMentions(parsed_data.dataArray),
+                            edited: true,
+                        };
+                        history[index] = message;
+                        broadcast(JSON.stringify({ messageType: 'edit', data: JSON.stringify(message) }));
+                        const pin = pins.find((p) => p.message.id === message.id);
+                        if (pin) {
+                            pin.message = message;
+                            broadcast(pinsFrame());
+                        }
+                    }
+                    break;
+                }
             }
         } catch (e) {
             console.log('Error in message', e);
@@ -51,15 +664,488 @@ wss.on('connection', (ws: WebSocket) => {
     });
 });
 
-const interval = setInterval(function ping() {
+// Drops clients that have stopped answering; their socket may never report closing.
+const heartbeat = setInterval(() => {
+    wss.clients.forEach((ws) => {
+        if (!pongs.ping(ws)) {
+            console.log('Dropping unresponsive client');
+            ws.terminate();
+            return;
+        }
+        ws.ping();
+    });
+}, HEARTBEAT_MS);
+
+const sweep = setInterval(function sweepDisconnected() {
+    const now = Date.now();
     const current_clients = Array.from(wss.clients);
     const updated_users = users.filter((u) => current_clients.includes(u.ws));
+    const forgotten = forgetOfflineUsers(now);
     if (updated_users.length !== users.length) {
+        users
+            .filter((u) => !updated_users.includes(u))
+            .filter((u) => !updated_users.some((o) => o.nick === u.nick))
+            .forEach((u) => {
+                offlineUsers = offlineUsers.filter((o) => o.nick !== u.nick);
+                offlineUsers.push({
+                    nick: u.nick,
+                    lastSeen: now,
+                    profile: u.profile,
+                    avatar: u.avatar,
+                    bot: u.bot,
+                    role: u.role,
+                    ip: u.ip,
+                });
+            });
         users = updated_users;
-        broadcast(JSON.stringify({ messageType: 'users', dataArray: users.map((u) => u.nick) }));
+        forgetOfflineUsers(now);
+        broadcastUsers();
+    } else if (forgotten) {
+        broadcastUsers();
     }
 }, 5000);
 
+wss.on('close', () => {
+    clearInterval(heartbeat);
+    clearInterval(sweep);
+});
+
+// Drops offline users who have been away too long or fall past the cap; returns whether any went.
+const forgetOfflineUsers = (now: number): boolean => {
+    const kept = offlineUsers
+        .filter((u) => now - u.lastSeen < OFFLINE_RETENTION_MS)
+        .sort((a, b) => a.lastSeen - b.lastSeen)
+        .slice(-MAX_OFFLINE_USERS);
+    const forgotten = kept.length !== offlineUsers.length;
+    offlineUsers = kept;
+    return forgotten;
+};
+
+const emptyProfile = (): Profile => ({ statusText: '', statusEmoji: '', pronouns: '', title: '', timezone: '' });
+
+// Only known string fields are kept, and each is capped in length.
+const parseProfile = (raw: String): Profile => {
+    const parsed = JSON.parse(raw.toString());
+    const profile = emptyProfile();
+    PROFILE_FIELDS.forEach((field) => {
+        if (typeof parsed[field] === 'string') {
+            profile[field] = parsed[field].slice(0, MAX_PROFILE_FIELD_LENGTH);
+        }
+    });
+    return profile;
+};
+
+// Message text must be a string with something other than whitespace in it.
+const isText = (raw: unknown): raw is string => typeof raw === 'string' && raw.trim() !== '';
+
+// Midnight UTC at the start of a `YYYY-MM-DD` day.
+const parseDay = (date: string): number | undefined => {
+    const match = /^(\d{4})-(\d{2})-(\d{2})$/.exec(date);
+    if (!match) {
+        return undefined;
+    }
+    const [year, month, day] = match.slice(1).map(Number);
+    const time = Date.UTC(year, month - 1, day);
+    // Date.UTC rolls 2023-02-30 over into March; a day that doesn't exist is no date.
+    const rolled = new Date(time);
+    return rolled.getUTCMonth() === month - 1 && rolled.getUTCDate() === day ? time : undefined;
+};
+
+// Filters are `key:value` tokens; anything else, including a filter we can't read, is a word.
+const parseSearch = (raw: string): SearchQuery => {
+    const query: SearchQuery = { words: [], hasImage: false };
+    raw.split(/\s+/)
+        .filter((token) => token !== '')
+        .forEach((token) => {
+            const colon = token.indexOf(':');
+            const key = colon >= 0 ? token.slice(0, colon) : '';
+            const value = token.slice(colon + 1);
+            const day = parseDay(value);
+            if (key === 'from' && value !== '') {
+                query.from = value.replace(/^@+/, '').toLowerCase();
+            } else if (key === 'in' && value !== '') {
+                query.room = value.replace(/^#+/, '').toLowerCase();
+            } else if (key === 'before' && day !== undefined) {
+                query.before = day;
+            } else if (key === 'after' && day !== undefined) {
+                // `after:` a day means from the start of the next one.
+                query.after = day + DAY_MS;
+            } else if (key === 'has' && value === 'image') {
+                query.hasImage = true;
+            } else {
+                query.words.push(token.toLowerCase());
+            }
+        });
+    return query;
+};
+
+const matchesSearch = (query: SearchQuery, message: ChatMessage) => {
+    const text = message.message.toLowerCase();
+    return (
+        query.words.every((word) => text.includes(word)) &&
+        (query.from === undefined || message.from.toLowerCase() === query.from) &&
+        (query.room === undefined || ROOMS.includes(query.room)) &&
+        (query.before === undefined || message.time < query.before) &&
+        (query.after === undefined || message.time >= query.after) &&
+        (!query.hasImage || message.message.endsWith('.gif'))
+    );
+};
+
+// Mentioned nicks come in `dataArray`; anything that isn't a string is dropped.
+const parseMentions = (raw: unknown): String[] =>
+    Array.isArray(raw) ? raw.filter((m) => typeof m === 'string').slice(0, MAX_MENTIONS) : [];
+
+// Online users first, then everyone we have seen leave, most recent first.
+const userList = () => [
+    ...users.map((u) => ({
+        nick: u.nick,
+        presence: u.presence,
+        profile: u.profile,
+        avatar: u.avatar,
+        bot: u.bot,
+        role: u.role,
+        mutedUntil: mutedUntil(u.ip),
+    })),
+    ...offlineUsers
+        .slice()
+        .sort((a, b) => b.lastSeen - a.lastSeen)
+        .map((u) => ({
+            nick: u.nick,
+            presence: 'offline',
+            lastSeen: u.lastSeen,
+            profile: u.profile,
+            avatar: u.avatar,
+            bot: u.bot,
+            role: u.role,
+            mutedUntil: mutedUntil(u.ip),
+        })),
+];
+
+// Stores a message, sends it to everyone and hands it to any webhook it triggers.
+const postMessage = (
+    from: String,
+    text: string,
+    mentions: String[],
+    bot: boolean,
+    clientId?: string,
+    avatar?: string
+): ChatMessage => {
+    const message: ChatMessage = { id: nextMessageId++, from, message: text, mentions, time: Date.now(), clientId, bot, avatar };
+    history = [...history, message];
+    history.splice(0, history.length - HISTORY_SIZE).forEach((m) => senders.delete(m.id));
+    broadcast(
+        JSON.stringify({
+            messageType: 'message',
+            data: JSON.stringify(message),
+        })
+    );
+    // Bots never trigger webhooks, so a webhook can't answer itself forever.
+    if (!bot) {
+        outgoingWebhooks.filter((hook) => text.startsWith(hook.trigger)).forEach((hook) => callWebhook(hook, message));
+    }
+    return message;
+};
+
+const isLocalUrl = (raw: unknown) => {
+    try {
+        const url = new URL(String(raw));
+        return url.protocol === 'http:' && WEBHOOK_HOSTS.includes(url.hostname);
+    } catch {
+        return false;
+    }
+};
+
+// The JSON in environment variable `name`, or `fallback` if it is unset. A value that
+// doesn't parse is logged and treated as unset, rather than stopping the server.
+const readJsonEnv = (name: string, fallback: unknown): unknown => {
+    const raw = process.env[name];
+    if (!raw) {
+        return fallback;
+    }
+    try {
+        return JSON.parse(raw);
+    } catch (e) {
+        console.log(`Ignoring ${name}: it is not valid JSON (${(e as Error).message})`);
+        return fallback;
+    }
+};
+
+// `OUTGOING_WEBHOOKS` holds a JSON array of `{ name, trigger, url }`; bad entries are skipped.
+const parseWebhooks = (parsed: unknown): OutgoingWebhook[] =>
+    (Array.isArray(parsed) ? parsed : []).filter((hook) => {
+        const valid = isText(hook.name) && isText(hook.trigger) && isLocalUrl(hook.url);
+        if (!valid) {
+            console.log('Ignoring outgoing webhook', hook);
+        }
+        return valid;
+    });
+
+// `ROLES` holds a JSON object of `{ "<nick>": { "role": "moderator" | "admin", "key": "..." } }`.
+const parseRoles = (parsed: unknown): Map<String, RoleGrant> => {
+    const grants = new Map<String, RoleGrant>();
+    Object.entries(parsed && typeof parsed === 'object' ? parsed : {}).forEach(([nick, grant]: [string, any]) => {
+        if (grant && ROLES.includes(grant.role) && isText(grant.key)) {
+            grants.set(nick, { role: grant.role, key: grant.key });
+        } else {
+            console.log('Ignoring role for', nick);
+        }
+    });
+    return grants;
+};
+
+// The role `key` unlocks for `nick`, or `member` if it doesn't.
+const claimRole = (nick: String, key: string): Role => {
+    const grant = roleGrants.get(nick);
+    return grant && tokenMatches(key, grant.key) ? grant.role : 'member';
+};
+
+const rank = (role: Role) => ROLES.indexOf(role);
+
+const isPrivileged = (role: Role) => rank(role) >= rank('moderator');
+
+// Moderators act on members; admins on anyone but other admins.
+const outranks = (actor: Role, target: Role) => isPrivileged(actor) && rank(actor) > rank(target);
+
+// What `nick` is, online or not; a configured role counts even while they are away.
+// With `ip`, only someone by that name from that address counts.
+const roleOf = (nick: String, ip?: string): Role => {
+    const matches = (u: { nick: String; ip: string }) => u.nick === nick && (ip === undefined || u.ip === ip);
+    return users.find(matches)?.role ?? offlineUsers.find(matches)?.role ?? roleGrants.get(nick)?.role ?? 'member';
+};
+
+// Where `nick` connects from, or last did.
+const addressOf = (nick: String): string | undefined =>
+    (users.find((u) => u.nick === nick) ?? offlineUsers.find((u) => u.nick === nick))?.ip || undefined;
+
+// Whether `user` sent `message`: the same nick, from the same address.
+const isOwn = (user: User, message: ChatMessage) => message.from === user.nick && senders.get(message.id) === user.ip;
+
+// When `ip` may post again, if it is muted; expired mutes are forgotten.
+const mutedUntil = (ip: string): number | undefined => {
+    const until = mutes.get(ip);
+    if (until !== undefined && until <= Date.now()) {
+        mutes.delete(ip);
+        return undefined;
+    }
+    return until;
+};
+
+const helloFrame = () =>
+    JSON.stringify({
+        messageType: 'hello',
+        data: JSON.stringify({
+            protocolVersion: PROTOCOL_VERSION,
+            serverName: SERVER_NAME,
+            limits: {
+                messageLength: MAX_MESSAGE_LENGTH,
+                uploadBytes: MAX_AVATAR_BYTES,
+                messageBurst: MESSAGE_BURST,
+                messagesPerSecond: MESSAGES_PER_SECOND,
+            },
+            features: FEATURES,
+            motd: MOTD || undefined,
+        }),
+    });
+
+const errorFrame = (reply: ErrorReply) => JSON.stringify({ messageType: 'error', data: JSON.stringify(reply) });
+
+const moderationFrame = (notice: object) => JSON.stringify({ messageType: 'moderation', data: JSON.stringify(notice) });
+
+const audit = (actor: String, action: string, target: String, detail?: string) => {
+    auditLog = [...auditLog, { time: Date.now(), actor, action, target, detail }].slice(-MAX_AUDIT_ENTRIES);
+    console.log(`Moderation: ${actor} ${action} ${target}${detail ? ` (${detail})` : ''}`);
+};
+
+// Sends `notice` to every connection of `nick` and, with `close`, disconnects them.
+const tell = (targets: User[], notice: object, close = false) => {
+    targets.forEach((u) => {
+        u.ws.send(moderationFrame(notice));
+        if (close) {
+            u.ws.close(CLOSE_REMOVED, 'removed');
+        }
+    });
+};
+
+const deleteMessage = (id: number) => {
+    history = history.filter((m) => m.id !== id);
+    senders.delete(id);
+    broadcast(JSON.stringify({ messageType: 'delete', data: JSON.stringify(id) }));
+    if (pins.some((p) => p.message.id === id)) {
+        pins = pins.filter((p) => p.message.id !== id);
+        broadcast(pinsFrame());
+    }
+};
+
+// Carries out a `moderate` request from `actor`, if their role allows it.
+const moderate = (actor: User, action: any) => {
+    const deny = (reason: string) => actor.ws.send(moderationFrame({ action: 'denied', reason }));
+    if (action?.action === 'delete') {
+        const message = history.find((m) => m.id === action.id);
+        if (!message) {
+            deny('That message is already gone.');
+        } else if (isOwn(actor, message)) {
+            // Anyone may take back their own message; that isn't moderation.
+            deleteMessage(message.id);
+        } else if (outranks(actor.role, roleOf(message.from, senders.get(message.id)))) {
+            deleteMessage(message.id);
+            audit(actor.nick, 'deleted a message by', message.from, message.message.slice(0, 80));
+        } else {
+            deny("You can't delete that message.");
+        }
+        return;
+    }
+    if (!isPrivileged(actor.role)) {
+        deny('Only moderators and admins can do that.');
+        return;
+    }
+    if (!isText(action?.target) || action.target === actor.nick) {
+        return;
+    }
+    const target: string = action.target;
+    if (!outranks(actor.role, roleOf(target))) {
+        deny(`You can't moderate ${target}.`);
+        return;
+    }
+    const online = users.filter((u) => u.nick === target);
+    // Mutes and bans hold the address, so a new nick or connection doesn't get around them.
+    const ip = addressOf(target);
+    switch (action.action) {
+        case 'kick':
+            if (online.length === 0) {
+                deny(`${target} is not here.`);
+                return;
+            }
+            tell(online, { action: 'kicked', by: actor.nick }, true);
+            audit(actor.nick, 'kicked', target);
+            break;
+        case 'mute': {
+            const minutes = Number(action.minutes);
+            if (!Number.isInteger(minutes) || minutes < 1 || minutes > MAX_MUTE_MINUTES) {
+                deny(`Mutes last from 1 to ${MAX_MUTE_MINUTES} minutes.`);
+                return;
+            }
+            if (ip === undefined) {
+                deny(`${target} has not been here.`);
+                return;
+            }
+            const until = Date.now() + minutes * 60 * 1000;
+            mutes.set(ip, until);
+            // Let everyone's list drop the muted mark once it is over.
+            setTimeout(() => {
+                if (mutes.get(ip) === until) {
+                    mutes.delete(ip);
+                    broadcastUsers();
+                }
+            }, until - Date.now());
+            tell(online, { action: 'muted', by: actor.nick, until });
+            broadcastUsers();
+            audit(actor.nick, 'muted', target, `${minutes} min`);
+            break;
+        }
+        case 'unmute':
+            if (ip !== undefined && mutes.delete(ip)) {
+                tell(online, { action: 'unmuted', by: actor.nick });
+                broadcastUsers();
+                audit(actor.nick, 'unmuted', target);
+            }
+            break;
+        case 'ban': {
+            if (ip === undefined) {
+                deny(`${target} has not been here.`);
+                return;
+            }
+            bannedIps.set(ip, target);
+            // Anyone else from the address is kept out from their next connection, or
+            // with `byIp` dropped straight away.
+            const byIp = action.byIp === true;
+            const leaving = byIp ? users.filter((u) => u.ip === ip) : online;
+            tell(leaving, { action: 'banned', by: actor.nick }, true);
+            audit(actor.nick, 'banned', target, byIp ? 'and their IP address' : undefined);
+            break;
+        }
+        case 'unban':
+            bannedIps.forEach((nick, ip) => {
+                if (nick === target) {
+                    bannedIps.delete(ip);
+                }
+            });
+            audit(actor.nick, 'unbanned', target);
+            break;
+        case 'role':
+            // Lasts until they disconnect; standing roles come from `ROLES`.
+            if (actor.role !== 'admin') {
+                deny('Only admins can change roles.');
+            } else if (!ROLES.includes(action.role) || action.role === 'admin') {
+                deny('Roles can be changed to member or moderator.');
+            } else if (online.length === 0) {
+                deny(`${target} is not here.`);
+            } else {
+                online.forEach((u) => (u.role = action.role));
+                broadcastUsers();
+                audit(actor.nick, 'changed the role of', target, action.role);
+            }
+            break;
+    }
+};
+
+const outgoingWebhooks = parseWebhooks(readJsonEnv('OUTGOING_WEBHOOKS', []));
+const incomingWebhooks = parseIncomingWebhooks(readJsonEnv('INCOMING_WEBHOOKS', []));
+const roleGrants = parseRoles(readJsonEnv('ROLES', {}));
+
+// POSTs the message as JSON; a `{ "text": ... }` reply is posted back as the webhook.
+const callWebhook = (hook: OutgoingWebhook, message: ChatMessage) => {
+    const body = JSON.stringify({ id: message.id, from: message.from, message: message.message, trigger: hook.trigger });
+    const req = http.request(
+        hook.url,
+        {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json', 'Content-Length': Buffer.byteLength(body) },
+            timeout: WEBHOOK_TIMEOUT_MS,
+        },
+        (res) => {
+            const chunks: Buffer[] = [];
+            let size = 0;
+            res.on('data', (chunk: Buffer) => {
+                size += chunk.length;
+                if (size > MAX_WEBHOOK_RESPONSE_BYTES) {
+                    res.destroy();
+                    return;
+                }
+                chunks.push(chunk);
+            });
+            res.on('end', () => {
+                const status = res.statusCode ?? 0;
+                if (status < 200 || status >= 300 || size === 0 || size > MAX_WEBHOOK_RESPONSE_BYTES) {
+                    return;
+                }
+                try {
+                    const reply = JSON.parse(Buffer.concat(chunks).toString());
+                    if (isText(reply.text)) {
+                        postMessage(hook.name, reply.text, parseMentions(reply.mentions), true);
+                    }
+                } catch (e) {
+                    console.log(`Bad reply from webhook ${hook.name}`, e);
+                }
+            });
+        }
+    );
+    req.on('timeout', () => req.destroy(new Error('timed out')));
+    req.on('error', (e) => console.log(`Webhook ${hook.name} failed:`, e.message));
+    req.end(body);
+};
+
+const ack = (message: ChatMessage) =>
+    JSON.stringify({ messageType: 'ack', data: JSON.stringify({ clientId: message.clientId, id: message.id }) });
+
+const pinsFrame = () =>
+    JSON.stringify({ messageType: 'pins', data: JSON.stringify(pins.map(({ message, pinnedBy }) => ({ message, pinnedBy }))) });
+
+const topicFrame = () => JSON.stringify({ messageType: 'topic', data: JSON.stringify(topic) });
+
+const broadcastUsers = () => {
+    broadcast(JSON.stringify({ messageType: 'users', data: JSON.stringify(userList()) }));
+};
+
 const broadcast = (data: any) => {
     wss.clients.forEach((client) => {
         if (client.readyState === WebSocket.OPEN) {
@@ -0,0 +1,36 @@
+// Liveness: spotting clients that have gone away without their socket saying so.
+
+// Every client is pinged this often, and dropped after missing this many pongs in a row.
+export const HEARTBEAT_MS = 10_000;
+export const MAX_MISSED_PONGS = 3;
+
+// Pings sent to each socket since it was last heard from.
+export class PongTracker<Socket> {
+    private missed = new Map<Socket, number>();
+
+    constructor(readonly max = MAX_MISSED_PONGS) {}
+
+    // A pong, or any frame at all, shows the socket is still there.
+    heard(socket: Socket) {
+        this.missed.set(socket, 0);
+    }
+
+    // Counts a ping about to go out to `socket`, unless it has already missed `max`
+    // and should be dropped instead.
+    ping(socket: Socket): boolean {
+        const missed = this.missed.get(socket) ?? 0;
+        if (missed >= this.max) {
+            return false;
+        }
+        this.missed.set(socket, missed + 1);
+        return true;
+    }
+
+    forget(socket: Socket) {
+        this.missed.delete(socket);
+    }
+
+    missedBy(socket: Socket): number {
+        return this.missed.get(socket) ?? 0;
+    }
+}
@@ -0,0 +1,158 @@
+// Flood protection: how much a single connection may send, and how fast.
+
+// Frames larger than this are turned away unread.
+export const MAX_FRAME_BYTES = 32 * 1024;
+// In characters, as the client counts them.
+export const MAX_MESSAGE_LENGTH = 2000;
+export const MAX_CONNECTIONS_PER_IP = 5;
+// Any frame: a short burst, then this many per second.
+export const FRAME_BURST = 30;
+export const FRAMES_PER_SECOND = 10;
+// Chat messages and edits, which go out to everyone, are held to a lower rate.
+export const MESSAGE_BURST = 5;
+export const MESSAGES_PER_SECOND = 1;
+// Rejected frames in a row before the connection is dropped.
+export const MAX_STRIKES = 20;
+
+export type ErrorCode =
+    | 'rate_limited'
+    | 'frame_too_large'
+    | 'message_too_long'
+    | 'too_many_connections'
+    | 'feature_disabled'
+    | 'nick_taken';
+
+// Sent in an `error` frame in place of whatever was asked for.
+export interface ErrorReply {
+    code: ErrorCode;
+    message: string;
+    // Milliseconds until the same request would be let through.
+    retryAfter?: number;
+    // The chat message that was turned away.
+    clientId?: string;
+}
+
+// Holds up to `capacity` tokens and gains `perSecond` of them every second.
+export class TokenBucket {
+    private tokens: number;
+
+    constructor(readonly capacity: number, readonly perSecond: number, private updated = Date.now()) {
+        this.tokens = capacity;
+    }
+
+    // Spends a token if there is one.
+    take(now = Date.now()): boolean {
+        this.refill(now);
+        if (this.tokens < 1) {
+            return false;
+        }
+        this.tokens -= 1;
+        return true;
+    }
+
+    // Milliseconds until the next token.
+    retryAfter(now = Date.now()): number {
+        this.refill(now);
+        return this.tokens >= 1 ? 0 : Math.ceil(((1 - this.tokens) / this.perSecond) * 1000);
+    }
+
+    private refill(now: number) {
+        const elapsed = Math.max(0, now - this.updated) / 1000;
+        this.tokens = Math.min(this.capacity, this.tokens + elapsed * this.perSecond);
+        this.updated = now;
+    }
+}
+
+// What one connection may still send.
+export class FloodGuard {
+    private frames: TokenBucket;
+    private messages: TokenBucket;
+    private strikes = 0;
+
+    constructor(now = Date.now()) {
+        this.frames = new TokenBucket(FRAME_BURST, FRAMES_PER_SECOND, now);
+        this.messages = new TokenBucket(MESSAGE_BURST, MESSAGES_PER_SECOND, now);
+    }
+
+    // Checks any frame, before it is parsed. Passing doesn't forgive earlier
+    // rejections yet: a chat message still has `message` to get through.
+    frame(bytes: number, now = Date.now()): ErrorReply | undefined {
+        if (bytes > MAX_FRAME_BYTES) {
+            return this.strike({ code: 'frame_too_large', message: `Frames are limited to ${MAX_FRAME_BYTES} bytes.` });
+        }
+        if (!this.frames.take(now)) {
+            return this.strike({
+                code: 'rate_limited',
+                message: 'You are sending too fast; slow down.',
+                retryAfter: this.frames.retryAfter(now),
+            });
+        }
+        return undefined;
+    }
+
+    // Checks a chat message or edit, on top of `frame`; one that passes is accepted.
+    message(text: string, clientId?: string, now = Date.now()): ErrorReply | undefined {
+        if (Array.from(text).length > MAX_MESSAGE_LENGTH) {
+            return this.strike({
+                code: 'message_too_long',
+                message: `Messages are limited to ${MAX_MESSAGE_LENGTH} characters.`,
+                clientId,
+            });
+        }
+        if (!this.messages.take(now)) {
+            return this.strike({
+                code: 'rate_limited',
+                message: 'You are sending messages too fast; wait a moment.',
+                retryAfter: this.messages.retryAfter(now),
+                clientId,
+            });
+        }
+        this.accept();
+        return undefined;
+    }
+
+    // Forgives earlier rejections, once a frame has passed every check it is subject to.
+    accept() {
+        this.strikes = 0;
+    }
+
+    // Whether the connection has kept on after too many rejections.
+    get exhausted(): boolean {
+        return this.strikes >= MAX_STRIKES;
+    }
+
+    private strike(reply: ErrorReply): ErrorReply {
+        this.strikes += 1;
+        return reply;
+    }
+}
+
+// Open connections by IP address.
+export class ConnectionCounter {
+    private counts = new Map<string, number>();
+
+    constructor(readonly max = MAX_CONNECTIONS_PER_IP) {}
+
+    // Counts a new connection from `ip`, unless it already has `max`.
+    open(ip: string): boolean {
+        const count = this.counts.get(ip) ?? 0;
+        if (count >= this.max) {
+            return false;
+        }
+        this.counts.set(ip, count + 1);
+        return true;
+    }
+
+    close(ip: string) {
+        const count = (this.counts.get(ip) ?? 0) - 1;
+        if (count > 0) {
+            this.counts.set(ip, count);
+        } else {
+            this.counts.delete(ip);
+        }
+    }
+
+    count(ip: string): number {
+        return this.counts.get(ip) ?? 0;
+    }
+}
@@ -0,0 +1,96 @@
+// Runs the compiled server and checks that it speaks the frames in `fixtures/frames.json`.
+// The bot crate's tests stand in for the server with the same frames, so the two can't
+// drift apart unnoticed. `data` holds JSON-encoded payloads on the wire; the fixtures
+// show them decoded.
+const { test, before, after } = require('node:test');
+const assert = require('node:assert/strict');
+const { spawn } = require('node:child_process');
+const path = require('node:path');
+const WebSocket = require('ws');
+
+const fixtures = require('./fixtures/frames.json');
+
+const PORT = 20000 + Math.floor(Math.random() * 10000);
+let server;
+
+// The fixture as it goes over the wire.
+const encode = (frame) =>
+    JSON.stringify(typeof frame.data === 'object' ? { ...frame, data: JSON.stringify(frame.data) } : frame);
+
+const decode = (raw) => {
+    const frame = JSON.parse(raw.toString());
+    return frame.data && frame.messageType !== 'register' ? { ...frame, data: JSON.parse(frame.data) } : frame;
+};
+
+// Connects, retrying while the server starts up.
+const connect = async () => {
+    for (let attempt = 0; ; attempt++) {
+        try {
+            return await new Promise((resolve, reject) => {
+                const ws = new WebSocket(`ws://127.0.0.1:${PORT}`);
+                ws.once('open', () => resolve(ws));
+                ws.once('error', reject);
+            });
+        } catch (e) {
+            if (attempt >= 50) {
+                throw e;
+            }
+            await new Promise((resolve) => setTimeout(resolve, 100));
+        }
+    }
+};
+
+// The next frame of `messageType`, skipping anything else.
+const next = (ws, messageType) =>
+    new Promise((resolve) => {
+        const listener = (raw) => {
+            const frame = decode(raw);
+            if (frame.messageType === messageType) {
+                ws.off('message', listener);
+                resolve(frame);
+            }
+        };
+        ws.on('message', listener);
+    });
+
+before(() => {
+    server = spawn(process.execPath, [path.join(__dirname, '../dist/app.js')], {
+        env: { ...process.env, PORT: String(PORT) },
+        stdio: 'ignore',
+    });
+});
+
+after(() => server.kill());
+
+test('a bot registers with the fixture frame and hears messages in the fixture shape', async () => {
+    const bot = await connect();
+    const users = next(bot, 'users');
+    bot.send(encode(fixtures.register));
+    const listed = (await users).data.find((u) => u.nick === fixtures.register.data);
+    assert.equal(listed?.bot, true);
+
+    const alice = await connect();
+    alice.send(encode({ messageType: 'register', data: fixtures.message.data.from }));
+    await next(alice, 'history');
+    const heard = next(bot, 'message');
+    alice.send(encode({ messageType: 'message', data: fixtures.message.data.message, dataArray: [] }));
+    const message = await heard;
+    // The server picks the ID and time.
+    assert.equal(typeof message.data.id, 'number');
+    assert.equal(typeof message.data.time, 'number');
+    assert.deepEqual(
+        { ...message, data: { ...message.data, id: fixtures.message.data.id, time: fixtures.message.data.time } },
+        fixtures.message
+    );
+
+    bot.close();
+    alice.close();
+});
+
+test('a ping is answered with the fixture pong', async () => {
+    const ws = await connect();
+    const pong = next(ws, 'pong');
+    ws.send(encode(fixtures.ping));
+    assert.deepEqual(await pong, fixtures.pong);
+    ws.close();
+});
@@ -0,0 +1,43 @@
+// Runs against the compiled server: `npm test` builds first.
+const { test } = require('node:test');
+const assert = require('node:assert/strict');
+
+const { PongTracker, MAX_MISSED_PONGS } = require('../dist/heartbeat');
+
+test('a socket that never answers is dropped after missing too many pongs', () => {
+    const pongs = new PongTracker();
+    pongs.heard('a');
+    for (let i = 0; i < MAX_MISSED_PONGS; i++) {
+        assert.ok(pongs.ping('a'));
+    }
+    assert.equal(pongs.missedBy('a'), MAX_MISSED_PONGS);
+    assert.ok(!pongs.ping('a'));
+});
+
+test('hearing from a socket starts the count over', () => {
+    const pongs = new PongTracker(2);
+    assert.ok(pongs.ping('a'));
+    assert.ok(pongs.ping('a'));
+    pongs.heard('a');
+    assert.equal(pongs.missedBy('a'), 0);
+    assert.ok(pongs.ping('a'));
+    assert.ok(pongs.ping('a'));
+    assert.ok(!pongs.ping('a'));
+});
+
+test('sockets are counted separately', () => {
+    const pongs = new PongTracker(1);
+    assert.ok(pongs.ping('a'));
+    assert.ok(pongs.ping('b'));
+    assert.ok(!pongs.ping('a'));
+    pongs.heard('b');
+    assert.ok(pongs.ping('b'));
+});
+
+test('a closed socket is forgotten', () => {
+    const pongs = new PongTracker(1);
+    assert.ok(pongs.ping('a'));
+    pongs.forget('a');
+    assert.equal(pongs.missedBy('a'), 0);
+    assert.ok(pongs.ping('a'));
+});
@@ -0,0 +1,126 @@
+// Runs against the compiled server: `npm test` builds first.
+const { test } = require('node:test');
+const assert = require('node:assert/strict');
+
+const {
+    ConnectionCounter,
+    FloodGuard,
+    TokenBucket,
+    FRAME_BURST,
+    MAX_CONNECTIONS_PER_IP,
+    MAX_FRAME_BYTES,
+    MAX_MESSAGE_LENGTH,
+    MAX_STRIKES,
+    MESSAGE_BURST,
+    MESSAGES_PER_SECOND,
+} = require('../dist/limits');
+
+test('a bucket lets a burst through, then refills over time', () => {
+    const bucket = new TokenBucket(3, 2, 0);
+    assert.ok(bucket.take(0));
+    assert.ok(bucket.take(0));
+    assert.ok(bucket.take(0));
+    assert.ok(!bucket.take(0));
+    assert.equal(bucket.retryAfter(0), 500);
+    assert.ok(bucket.take(500));
+    assert.ok(!bucket.take(500));
+});
+
+test('a bucket never holds more than its capacity', () => {
+    const bucket = new TokenBucket(2, 1, 0);
+    for (let i = 0; i < 2; i++) {
+        assert.ok(bucket.take(60_000));
+    }
+    assert.ok(!bucket.take(60_000));
+});
+
+test('frames over the size limit are rejected', () => {
+    const guard = new FloodGuard(0);
+    assert.equal(guard.frame(MAX_FRAME_BYTES, 0), undefined);
+    assert.equal(guard.frame(MAX_FRAME_BYTES + 1, 0).code, 'frame_too_large');
+});
+
+test('frames beyond the burst are rate limited with a retry time', () => {
+    const guard = new FloodGuard(0);
+    for (let i = 0; i < FRAME_BURST; i++) {
+        assert.equal(guard.frame(10, 0), undefined);
+    }
+    const reply = guard.frame(10, 0);
+    assert.equal(reply.code, 'rate_limited');
+    assert.ok(reply.retryAfter > 0);
+    assert.equal(guard.frame(10, 1000), undefined);
+});
+
+test('messages have their own, lower rate', () => {
+    const guard = new FloodGuard(0);
+    for (let i = 0; i < MESSAGE_BURST; i++) {
+        assert.equal(guard.message('hi', `c${i}`, 0), undefined);
+    }
+    const reply = guard.message('hi', 'late', 0);
+    assert.equal(reply.code, 'rate_limited');
+    assert.equal(reply.clientId, 'late');
+    assert.equal(reply.retryAfter, 1000 / MESSAGES_PER_SECOND);
+    assert.equal(guard.message('hi', 'later', 1000 / MESSAGES_PER_SECOND), undefined);
+});
+
+test('message length is counted in characters', () => {
+    const guard = new FloodGuard(0);
+    assert.equal(guard.message('é'.repeat(MAX_MESSAGE_LENGTH), undefined, 0), undefined);
+    assert.equal(guard.message('😀'.repeat(MAX_MESSAGE_LENGTH), undefined, 0), undefined);
+    const reply = guard.message('x'.repeat(MAX_MESSAGE_LENGTH + 1), 'long', 0);
+    assert.equal(reply.code, 'message_too_long');
+    assert.equal(reply.clientId, 'long');
+});
+
+test('a connection that keeps flooding is exhausted; one accepted frame forgives it', () => {
+    const guard = new FloodGuard(0);
+    for (let i = 0; i < MAX_STRIKES - 1; i++) {
+        guard.frame(MAX_FRAME_BYTES + 1, 0);
+    }
+    assert.ok(!guard.exhausted);
+    assert.equal(guard.frame(10, 0), undefined);
+    guard.accept();
+    for (let i = 0; i < MAX_STRIKES; i++) {
+        guard.frame(MAX_FRAME_BYTES + 1, 0);
+    }
+    assert.ok(guard.exhausted);
+});
+
+test('messages over the rate exhaust the connection even though their frames pass', () => {
+    const guard = new FloodGuard(0);
+    for (let i = 0; i < MESSAGE_BURST; i++) {
+        assert.equal(guard.frame(10, 0), undefined);
+        assert.equal(guard.message('hi', `c${i}`, 0), undefined);
+    }
+    for (let i = 0; i < MAX_STRIKES; i++) {
+        assert.ok(!guard.exhausted);
+        assert.equal(guard.frame(10, 0), undefined);
+        assert.equal(guard.message('hi', `spam${i}`, 0).code, 'rate_limited');
+    }
+    assert.ok(guard.exhausted);
+});
+
+test('a message that gets through forgives earlier rejections', () => {
+    const guard = new FloodGuard(0);
+    for (let i = 0; i < MESSAGE_BURST + MAX_STRIKES - 1; i++) {
+        guard.message('hi', `c${i}`, 0);
+    }
+    assert.ok(!guard.exhausted);
+    assert.equal(guard.message('hi', 'later', 1000 / MESSAGES_PER_SECOND), undefined);
+    for (let i = 0; i < MAX_STRIKES - 1; i++) {
+        guard.message('hi', `again${i}`, 1000 / MESSAGES_PER_SECOND);
+    }
+    assert.ok(!guard.exhausted);
+});
+
+test('connections are capped per IP address', () => {
+    const connections = new ConnectionCounter();
+    for (let i = 0; i < MAX_CONNECTIONS_PER_IP; i++) {
+        assert.ok(connections.open('10.0.0.1'));
+    }
+    assert.ok(!connections.open('10.0.0.1'));
+    assert.ok(connections.open('10.0.0.2'));
+    connections.close('10.0.0.1');
+    assert.ok(connections.open('10.0.0.1'));
+    assert.equal(connections.count('10.0.0.1'), MAX_CONNECTIONS_PER_IP);
+});
@@ -0,0 +1,10 @@
+//! Repeats `!echo` messages. Start the chat server, then:
+//!
+//!     cargo run -p chat-bot --example echo [ws://127.0.0.1:8080]
+
+fn main() -> Result<(), chat_bot::Error> {
+    let url = std::env::args().nth(1).unwrap_or_else(|| "ws://127.0.0.1:8080".into());
+    let bot = chat_bot::Bot::connect(&url, "echo")?;
+    println!("{} is listening on {}", bot.nick(), url);
+    bot.run(chat_bot::echo::reply)
+}
@@ -0,0 +1,14 @@
+//! The example bot: repeats whatever follows `!echo`.
+
+use chat_protocol::MessageData;
+
+const TRIGGER: &str = "!echo ";
+
+pub fn reply(message: &MessageData) -> Option<String> {
+    message
+        .message
+        .strip_prefix(TRIGGER)
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(String::from)
+}
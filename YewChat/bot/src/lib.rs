@@ -0,0 +1,191 @@
+//! A small SDK for chat bots: connect to the server, hear messages, answer them.
+//!
+//! ```no_run
+//! let bot = chat_bot::Bot::connect("ws://127.0.0.1:8080", "echo")?;
+//! bot.run(chat_bot::echo::reply)?;
+//! # Ok::<(), chat_bot::Error>(())
+//! ```
+
+pub mod echo;
+
+use std::fmt;
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+pub use chat_protocol::MessageData;
+use chat_protocol::{
+    Beat, Heartbeat, MsgTypes, WebSocketMessage, BOT_FLAG, PING_INTERVAL_MS, SILENCE_TIMEOUT_MS,
+};
+use serde::Deserialize;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+#[derive(Debug)]
+pub enum Error {
+    Socket(Box<tungstenite::Error>),
+    /// The server sent a frame we couldn't make sense of.
+    Frame(serde_json::Error),
+    /// The server stopped answering pings, though the socket may still look open.
+    Silent,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Socket(e) => write!(f, "websocket: {}", e),
+            Error::Frame(e) => write!(f, "bad frame: {}", e),
+            Error::Silent => write!(f, "the server stopped answering"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Error::Socket(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Frame(e)
+    }
+}
+
+/// Any frame, with its type left as a string so newer ones don't trip us up.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Frame {
+    message_type: String,
+    data: Option<String>,
+}
+
+/// A bot account, signed in to the chat server.
+pub struct Bot {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    nick: String,
+    heartbeat: Heartbeat,
+    /// The heartbeat's clock starts here.
+    started: Instant,
+}
+
+impl Bot {
+    /// Connects to the server at `url`, e.g. `ws://127.0.0.1:8080`, and registers as
+    /// the bot `nick`.
+    pub fn connect(url: &str, nick: &str) -> Result<Self, Error> {
+        let (socket, _) = tungstenite::connect(url)?;
+        let mut bot = Self {
+            socket,
+            nick: nick.to_string(),
+            heartbeat: Heartbeat::new(PING_INTERVAL_MS, SILENCE_TIMEOUT_MS, 0.0),
+            started: Instant::now(),
+        };
+        bot.send(WebSocketMessage {
+            message_type: MsgTypes::Register,
+            data: Some(nick.to_string()),
+            data_array: Some(vec![BOT_FLAG.to_string()]),
+            client_id: None,
+        })?;
+        Ok(bot)
+    }
+
+    pub fn nick(&self) -> &str {
+        &self.nick
+    }
+
+    /// Pings a server that has been quiet for `interval`, and gives up on one that
+    /// has said nothing for `timeout`.
+    pub fn with_heartbeat(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.heartbeat = Heartbeat::new(millis(interval), millis(timeout), self.now());
+        self
+    }
+
+    fn now(&self) -> f64 {
+        millis(self.started.elapsed())
+    }
+
+    /// Makes the next read give up after `timeout`, so the heartbeat gets its turn.
+    fn set_read_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+        if let MaybeTlsStream::Plain(stream) = self.socket.get_mut() {
+            // Zero would mean no timeout at all.
+            stream
+                .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))
+                .map_err(tungstenite::Error::Io)?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, frame: WebSocketMessage) -> Result<(), Error> {
+        self.socket.send(Message::Text(serde_json::to_string(&frame)?))?;
+        Ok(())
+    }
+
+    /// Posts `text` to the chat.
+    pub fn say(&mut self, text: &str) -> Result<(), Error> {
+        self.send(WebSocketMessage {
+            message_type: MsgTypes::Message,
+            data: Some(text.to_string()),
+            data_array: Some(vec![]),
+            client_id: None,
+        })
+    }
+
+    /// Waits for the next chat message, including our own as the server echoes them.
+    /// Returns `None` once the server closes the connection, and `Error::Silent` if
+    /// it stops answering.
+    pub fn next_message(&mut self) -> Result<Option<MessageData>, Error> {
+        loop {
+            match self.heartbeat.poll(self.now()) {
+                Beat::Wait(ms) => self.set_read_timeout(Duration::from_secs_f64(ms / 1000.0))?,
+                Beat::Ping => {
+                    self.send(WebSocketMessage {
+                        message_type: MsgTypes::Ping,
+                        data: None,
+                        data_array: None,
+                        client_id: None,
+                    })?;
+                    continue;
+                }
+                Beat::Dead => return Err(Error::Silent),
+            }
+            let message = match self.socket.read() {
+                Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => return Ok(None),
+                Ok(message) => message,
+                Err(tungstenite::Error::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue
+                }
+                Err(e) => return Err(e.into()),
+            };
+            self.heartbeat.heard(self.now());
+            let text = match message {
+                Message::Text(text) => text,
+                _ => continue,
+            };
+            let frame: Frame = serde_json::from_str(&text)?;
+            if let ("message", Some(data)) = (frame.message_type.as_str(), frame.data) {
+                return Ok(Some(serde_json::from_str(&data)?));
+            }
+        }
+    }
+
+    /// Answers messages until the server closes the connection. `handler` sees every
+    /// message from a person (never our own, nor other bots', so bots can't get into
+    /// a loop), and whatever it returns is posted.
+    pub fn run(mut self, mut handler: impl FnMut(&MessageData) -> Option<String>) -> Result<(), Error> {
+        while let Some(message) = self.next_message()? {
+            if message.bot || message.from == self.nick {
+                continue;
+            }
+            if let Some(reply) = handler(&message) {
+                self.say(&reply)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
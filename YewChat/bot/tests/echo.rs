@@ -0,0 +1,88 @@
+//! The echo bot, talking to a stand-in for the chat server on a local port.
+//!
+//! The stand-in's frames come from the fixtures the server's own tests check `app.ts`
+//! against; see `fixtures`.
+
+mod fixtures;
+
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use chat_bot::{echo, Bot, MessageData};
+use chat_protocol::{MsgTypes, WebSocketMessage};
+use tungstenite::{Message, WebSocket};
+
+/// Speaks just enough of the server's side of the protocol for one client.
+struct Server {
+    socket: WebSocket<TcpStream>,
+    next_id: u64,
+}
+
+impl Server {
+    fn accept(listener: &TcpListener) -> Self {
+        let (stream, _) = listener.accept().unwrap();
+        // A bot that stays quiet fails the test instead of hanging it.
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        Self {
+            socket: tungstenite::accept(stream).unwrap(),
+            next_id: 1,
+        }
+    }
+
+    fn read(&mut self) -> WebSocketMessage {
+        loop {
+            if let Message::Text(text) = self.socket.read().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    /// Broadcasts a stored message, as the server does after every post.
+    fn post(&mut self, from: &str, text: &str, bot: bool) {
+        let message = MessageData {
+            id: self.next_id,
+            from: from.into(),
+            message: text.into(),
+            mentions: vec![],
+            client_id: None,
+            edited: false,
+            time: 0.0,
+            bot,
+            avatar: None,
+        };
+        self.next_id += 1;
+        self.socket.send(Message::Text(fixtures::message(&message))).unwrap();
+    }
+
+    fn close(mut self) {
+        self.socket.close(None).unwrap();
+        while self.socket.read().is_ok() {}
+    }
+}
+
+#[test]
+fn echo_bot_answers_through_a_local_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let bot = thread::spawn(move || Bot::connect(&url, "echo")?.run(echo::reply));
+    let mut server = Server::accept(&listener);
+
+    assert_eq!(fixtures::written(&server.read()), fixtures::fixture("register"));
+
+    server.post("alice", "just chatting", false);
+    server.post("alice", "!echo hello", false);
+    let reply = server.read();
+    assert_eq!(reply.message_type, MsgTypes::Message);
+    assert_eq!(reply.data.as_deref(), Some("hello"));
+
+    // Neither its own reply coming back nor another bot gets an answer; the next
+    // thing it says is for bob.
+    server.post("echo", "hello", true);
+    server.post("otherbot", "!echo loop", true);
+    server.post("bob", "!echo again", false);
+    assert_eq!(server.read().data.as_deref(), Some("again"));
+
+    server.close();
+    bot.join().unwrap().unwrap();
+}
@@ -0,0 +1,41 @@
+//! Frames from SimpleWebsocketServer's `test/fixtures/frames.json`. The server's own
+//! tests check that `app.ts` sends and accepts them, so a stand-in server built from
+//! them can't quietly fall out of step with the real one.
+
+use chat_protocol::{MessageData, WebSocketMessage};
+use serde_json::Value;
+
+const FRAMES: &str = include_str!("../../../../SimpleWebsocketServer/test/fixtures/frames.json");
+
+/// The fixture called `name`, with `data` decoded where the wire has it JSON-encoded.
+pub fn fixture(name: &str) -> Value {
+    let frames: Value = serde_json::from_str(FRAMES).unwrap();
+    frames[name].clone()
+}
+
+/// The fixture called `name` as it goes over the wire.
+pub fn encode(mut frame: Value) -> String {
+    if frame["data"].is_object() {
+        frame["data"] = Value::String(frame["data"].to_string());
+    }
+    frame.to_string()
+}
+
+/// The server's `message` frame for `message`, in the fixture's shape.
+pub fn message(message: &MessageData) -> String {
+    let mut frame = fixture("message");
+    for (key, value) in serde_json::to_value(message).unwrap().as_object().unwrap() {
+        if frame["data"].get(key).is_some() {
+            frame["data"][key] = value.clone();
+        }
+    }
+    encode(frame)
+}
+
+/// `frame` as the fixtures write it, with unset fields left out, for comparing what a
+/// client sent with a fixture.
+pub fn written(frame: &WebSocketMessage) -> Value {
+    let mut value = serde_json::to_value(frame).unwrap();
+    value.as_object_mut().unwrap().retain(|_, v| !v.is_null());
+    value
+}
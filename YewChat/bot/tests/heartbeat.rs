@@ -0,0 +1,105 @@
+//! The bot's heartbeat, against a stand-in server that answers pings or doesn't.
+//!
+//! Like the one in `echo.rs`, the stand-in speaks the frames in `fixtures`, which the
+//! server's own tests check `app.ts` against; here those of its `ping` handler.
+
+mod fixtures;
+
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chat_bot::{Bot, Error, MessageData};
+use chat_protocol::WebSocketMessage;
+use tungstenite::{Message, WebSocket};
+
+const INTERVAL: Duration = Duration::from_millis(50);
+const TIMEOUT: Duration = Duration::from_millis(300);
+
+fn accept(listener: &TcpListener) -> WebSocket<TcpStream> {
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+    tungstenite::accept(stream).unwrap()
+}
+
+/// The next frame from the bot, if one comes before the read timeout.
+fn read(socket: &mut WebSocket<TcpStream>) -> Option<WebSocketMessage> {
+    match socket.read() {
+        Ok(Message::Text(text)) => Some(serde_json::from_str(&text).unwrap()),
+        Ok(_) => None,
+        Err(tungstenite::Error::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => None,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, frame: String) {
+    socket.send(Message::Text(frame)).unwrap();
+}
+
+#[test]
+fn a_server_that_stops_responding_is_given_up_on() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let mut socket = accept(&listener);
+        // Registers the bot, then goes quiet with the connection still open.
+        while read(&mut socket).is_none() {}
+        thread::sleep(Duration::from_secs(1));
+    });
+
+    let mut bot = Bot::connect(&url, "bot").unwrap().with_heartbeat(INTERVAL, TIMEOUT);
+    let started = Instant::now();
+    assert!(matches!(bot.next_message(), Err(Error::Silent)));
+    let waited = started.elapsed();
+    assert!(waited >= TIMEOUT && waited < Duration::from_secs(2), "gave up after {:?}", waited);
+    drop(bot);
+    server.join().unwrap();
+}
+
+#[test]
+fn pongs_keep_a_quiet_connection_open() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let mut socket = accept(&listener);
+        let started = Instant::now();
+        let mut pings = 0;
+        // Nothing but pongs for longer than the bot would wait in silence.
+        while started.elapsed() < TIMEOUT * 3 {
+            if let Some(frame) = read(&mut socket) {
+                if fixtures::written(&frame) == fixtures::fixture("ping") {
+                    pings += 1;
+                    send(&mut socket, fixtures::encode(fixtures::fixture("pong")));
+                }
+            }
+        }
+        let message = MessageData {
+            id: 1,
+            from: "alice".into(),
+            message: "still here".into(),
+            mentions: vec![],
+            client_id: None,
+            edited: false,
+            time: 0.0,
+            bot: false,
+            avatar: None,
+        };
+        send(&mut socket, fixtures::message(&message));
+        // Until the bot hangs up, which it may do without a closing handshake.
+        loop {
+            match socket.read() {
+                Err(tungstenite::Error::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(_) => break,
+                Ok(_) => {}
+            }
+        }
+        pings
+    });
+
+    let mut bot = Bot::connect(&url, "bot").unwrap().with_heartbeat(INTERVAL, TIMEOUT);
+    let message = bot.next_message().unwrap().unwrap();
+    assert_eq!(message.message, "still here");
+    drop(bot);
+    assert!(server.join().unwrap() > 0);
+}
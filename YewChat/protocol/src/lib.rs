@@ -0,0 +1,417 @@
+//! The frames the chat server and its clients exchange over the WebSocket, shared by
+//! the web client and bots.
+
+use serde::{Deserialize, Serialize};
+
+/// The version of these frames this crate speaks; servers announce theirs in `hello`.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Listed in a register frame's `data_array` to sign in as a bot.
+pub const BOT_FLAG: &str = "bot";
+/// Prefixes the key that claims a nick's configured role in a register frame's
+/// `data_array`, as in `key:secret`.
+pub const KEY_PREFIX: &str = "key:";
+/// The close code of a connection the server ended on purpose, for a kick or a ban;
+/// a client that sees it shouldn't reconnect.
+pub const CLOSE_REMOVED: u16 = 4000;
+/// The close code of a connection the server turned away because too many are open
+/// from the same address; a client that sees it should wait a good while before
+/// trying again.
+pub const CLOSE_TOO_MANY_CONNECTIONS: u16 = 4001;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MsgTypes {
+    Users,
+    Register,
+    Message,
+    Presence,
+    Profile,
+    Avatar,
+    History,
+    Marker,
+    Read,
+    Receipts,
+    Ack,
+    Edit,
+    Search,
+    Pin,
+    Unpin,
+    Pins,
+    Topic,
+    Moderate,
+    Moderation,
+    Delete,
+    Audit,
+    Error,
+    Hello,
+    Ping,
+    Pong,
+}
+
+/// A frame on the wire; structured payloads are JSON-encoded into `data`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketMessage {
+    pub message_type: MsgTypes,
+    pub data_array: Option<Vec<String>>,
+    pub data: Option<String>,
+    /// Set on outgoing chat messages so the server can acknowledge them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MessageData {
+    /// Assigned by the server; increases with every message.
+    #[serde(default)]
+    pub id: u64,
+    pub from: String,
+    pub message: String,
+    /// Names of the users pinged in `message`.
+    #[serde(default)]
+    pub mentions: Vec<String>,
+    /// The sender's ID for the message, if it came from a client that set one.
+    #[serde(default, rename = "clientId")]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub edited: bool,
+    /// When the server stored it, in milliseconds since the epoch; 0 if unknown.
+    #[serde(default)]
+    pub time: f64,
+    /// Posted by a bot account or a webhook rather than a person.
+    #[serde(default)]
+    pub bot: bool,
+    /// Shown instead of the sender's own avatar; webhooks can set one per message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+}
+
+/// New text for one of our messages; its mentions go in `data_array`.
+#[derive(Debug, Serialize)]
+pub struct MessageEdit {
+    pub id: u64,
+    pub message: String,
+}
+
+/// A read receipt: `nick` has seen everything up to message `id`.
+#[derive(Debug, Deserialize)]
+pub struct Receipt {
+    pub nick: String,
+    pub id: u64,
+}
+
+/// The server's answer to a search, newest match first.
+#[derive(Debug, Deserialize)]
+pub struct SearchResults {
+    /// The query as we sent it, to tell answers to old queries apart.
+    pub query: String,
+    pub results: Vec<MessageData>,
+}
+
+/// The server has stored our message `client_id` as message `id`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ack {
+    pub client_id: String,
+    pub id: u64,
+}
+
+/// How far a user has read in a conversation, as synced with the server.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadMarker {
+    pub conversation: String,
+    pub last_read: u64,
+}
+
+/// A pinned message, as listed in a `pins` frame.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pin {
+    pub message: MessageData,
+    pub pinned_by: String,
+}
+
+/// The room's topic line; empty when none is set.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomTopic {
+    pub text: String,
+    pub set_by: Option<String>,
+}
+
+/// What someone may do in the chat. Moderators act on members; admins on anyone,
+/// and they alone hand out roles.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    Member,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn is_privileged(self) -> bool {
+        self >= Role::Moderator
+    }
+
+    /// Whether someone with this role may moderate someone with `other`.
+    pub fn outranks(self, other: Role) -> bool {
+        self.is_privileged() && self > other
+    }
+}
+
+/// A moderator's request, sent in a `moderate` frame.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum ModAction {
+    /// Disconnects `target`; they may come back.
+    Kick { target: String },
+    /// Silences `target`'s address, whatever nick it uses, for `minutes`.
+    Mute { target: String, minutes: u32 },
+    Unmute { target: String },
+    /// Keeps `target`'s address out; with `by_ip` anyone else already connected from
+    /// it leaves too.
+    Ban {
+        target: String,
+        #[serde(rename = "byIp", default)]
+        by_ip: bool,
+    },
+    Unban { target: String },
+    /// Deletes any message; members may delete their own.
+    Delete { id: u64 },
+    Role { target: String, role: Role },
+}
+
+/// Tells a user what moderation did to them or their request, in a `moderation` frame.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum ModNotice {
+    Kicked { by: String },
+    /// Sent when we are banned, and when a banned user tries to come back.
+    Banned { by: Option<String> },
+    /// We can't post until `until`; `client_id` is the message that was turned away.
+    Muted {
+        by: Option<String>,
+        until: f64,
+        #[serde(rename = "clientId", default)]
+        client_id: Option<String>,
+    },
+    Unmuted { by: String },
+    /// A moderation request we weren't allowed to make.
+    Denied { reason: String },
+}
+
+/// What the server sends first on every connection.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hello {
+    pub protocol_version: u32,
+    pub server_name: String,
+    pub limits: Limits,
+    pub features: Features,
+    /// The message of the day, if the server has one.
+    #[serde(default)]
+    pub motd: Option<String>,
+}
+
+/// What the server accepts; frames past these are answered with an `error`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Limits {
+    /// In characters.
+    pub message_length: usize,
+    /// The largest avatar upload, in bytes.
+    pub upload_bytes: u64,
+    /// Chat messages that may go out at once, before `messages_per_second` applies.
+    pub message_burst: u32,
+    pub messages_per_second: f64,
+}
+
+/// What the server has switched on.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Features {
+    pub rooms: bool,
+    pub uploads: bool,
+    pub search: bool,
+}
+
+impl Default for Features {
+    /// What servers from before the `hello` frame offer.
+    fn default() -> Self {
+        Self {
+            rooms: false,
+            uploads: true,
+            search: true,
+        }
+    }
+}
+
+/// Why the server turned a frame away.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    RateLimited,
+    FrameTooLarge,
+    MessageTooLong,
+    TooManyConnections,
+    /// Someone connected right now already goes by that nick.
+    NickTaken,
+    /// Asked for something the server has switched off.
+    FeatureDisabled,
+    /// Something newer than this client knows about.
+    #[serde(other)]
+    Other,
+}
+
+/// Sent in an `error` frame in place of whatever was asked for.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerError {
+    pub code: ErrorCode,
+    /// Says what went wrong, for people.
+    pub message: String,
+    /// Milliseconds until the same request would be let through.
+    #[serde(default)]
+    pub retry_after: Option<f64>,
+    /// The chat message that was turned away.
+    #[serde(default)]
+    pub client_id: Option<String>,
+}
+
+/// One moderator action, as listed in an `audit` frame.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct AuditEntry {
+    pub time: f64,
+    pub actor: String,
+    pub action: String,
+    pub target: String,
+    #[serde(default)]
+    pub detail: Option<String>,
+}
+
+/// How long a client lets the server stay quiet before pinging it, in milliseconds.
+pub const PING_INTERVAL_MS: f64 = 15_000.0;
+/// How long a client waits without hearing a thing before it gives the connection up.
+pub const SILENCE_TIMEOUT_MS: f64 = 40_000.0;
+
+/// What a client should do next to keep its connection honest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Beat {
+    /// Nothing, for this many milliseconds.
+    Wait(f64),
+    /// Send a `ping`; the server answers with a `pong`.
+    Ping,
+    /// Nothing has come back for too long. The socket may still look open, but the
+    /// server is gone.
+    Dead,
+}
+
+/// Decides when a client pings the server and when it gives up on it. Anything the
+/// server sends counts as a sign of life. Times are in milliseconds, on whatever
+/// clock the client has.
+#[derive(Clone, Debug)]
+pub struct Heartbeat {
+    interval: f64,
+    timeout: f64,
+    last_heard: f64,
+    last_ping: f64,
+}
+
+impl Heartbeat {
+    pub fn new(interval: f64, timeout: f64, now: f64) -> Self {
+        Self {
+            interval,
+            timeout,
+            last_heard: now,
+            last_ping: now,
+        }
+    }
+
+    pub fn heard(&mut self, now: f64) {
+        self.last_heard = now;
+    }
+
+    pub fn poll(&mut self, now: f64) -> Beat {
+        let deadline = self.last_heard + self.timeout;
+        if now >= deadline {
+            return Beat::Dead;
+        }
+        // A connection with traffic on it needs no pinging.
+        let quiet_since = self.last_heard.max(self.last_ping);
+        if now - quiet_since >= self.interval {
+            self.last_ping = now;
+            return Beat::Ping;
+        }
+        Beat::Wait((quiet_since + self.interval).min(deadline) - now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_quiet_server_is_pinged_then_given_up_on() {
+        let mut heartbeat = Heartbeat::new(100.0, 250.0, 0.0);
+        assert_eq!(heartbeat.poll(0.0), Beat::Wait(100.0));
+        assert_eq!(heartbeat.poll(100.0), Beat::Ping);
+        assert_eq!(heartbeat.poll(150.0), Beat::Wait(50.0));
+        assert_eq!(heartbeat.poll(200.0), Beat::Ping);
+        assert_eq!(heartbeat.poll(200.0), Beat::Wait(50.0));
+        assert_eq!(heartbeat.poll(250.0), Beat::Dead);
+    }
+
+    #[test]
+    fn hearing_from_the_server_puts_off_pings_and_the_deadline() {
+        let mut heartbeat = Heartbeat::new(100.0, 250.0, 0.0);
+        assert_eq!(heartbeat.poll(100.0), Beat::Ping);
+        heartbeat.heard(120.0);
+        assert_eq!(heartbeat.poll(200.0), Beat::Wait(20.0));
+        heartbeat.heard(210.0);
+        assert_eq!(heartbeat.poll(300.0), Beat::Wait(10.0));
+        assert_eq!(heartbeat.poll(310.0), Beat::Ping);
+        assert_eq!(heartbeat.poll(460.0), Beat::Dead);
+    }
+
+    /// Steps a client through `until` ms with the shipped timings, the server answering
+    /// each ping after `answer_ms`, or never. Returns the pings sent and when, if ever,
+    /// the client gave up.
+    fn run(answer_ms: Option<f64>, until: f64) -> (usize, Option<f64>) {
+        let mut heartbeat = Heartbeat::new(PING_INTERVAL_MS, SILENCE_TIMEOUT_MS, 0.0);
+        let mut now = 0.0;
+        let mut pings = 0;
+        // When the pongs on their way back arrive, soonest first.
+        let mut pongs: Vec<f64> = vec![];
+        while now <= until {
+            if pongs.first().is_some_and(|at| *at <= now) {
+                heartbeat.heard(pongs.remove(0));
+            }
+            match heartbeat.poll(now) {
+                Beat::Wait(ms) => now = pongs.first().map_or(now + ms, |at| at.min(now + ms)),
+                Beat::Ping => {
+                    pings += 1;
+                    pongs.extend(answer_ms.map(|ms| now + ms));
+                }
+                Beat::Dead => return (pings, Some(now)),
+            }
+        }
+        (pings, None)
+    }
+
+    #[test]
+    fn a_silent_server_is_given_up_on_after_the_timeout() {
+        let (pings, dead_at) = run(None, 10.0 * 60_000.0);
+        assert_eq!(dead_at, Some(SILENCE_TIMEOUT_MS));
+        // It gets more than one chance to answer first.
+        assert!(pings >= 2);
+    }
+
+    #[test]
+    fn a_slow_but_answering_server_is_kept() {
+        let (_, dead_at) = run(Some(SILENCE_TIMEOUT_MS - PING_INTERVAL_MS - 1.0), 10.0 * 60_000.0);
+        assert_eq!(dead_at, None);
+    }
+}
@@ -1,52 +1,856 @@
-use serde::{Deserialize, Serialize};
-use web_sys::HtmlInputElement;
+use std::cell::Cell;
+use std::rc::Rc;
+
+use gloo_events::EventListener;
+use gloo_timers::callback::{Interval, Timeout};
+use web_sys::{Element, HtmlTextAreaElement};
 use yew::prelude::*;
-use yew_agent::{Bridge, Bridged};
+use yew_agent::Bridge;
 
-use crate::services::event_bus::EventBus;
+use crate::services::avatar;
+use crate::services::moderation::{self, Outcome};
+use crate::services::event_bus::{ConnectionState, Event, EventBus, Topic};
+use crate::services::notifications::{self, document_hidden};
+use crate::services::offline::{OfflineStore, Snapshot};
+use crate::services::outbox::{self, Delivery, Outbox};
+use crate::services::protocol::{
+    Ack, ErrorCode, Features, Hello, MessageData, MessageEdit, ModAction, ModNotice, MsgTypes, Presence, ReadMarker,
+    Role, ServerError, ServerEvent, WebSocketMessage, KEY_PREFIX, PROTOCOL_VERSION,
+};
+use crate::services::read_markers::{ReadState, DEFAULT_CONVERSATION};
+use crate::services::timeline::{self, Timeline};
 use crate::{services::websocket::WebsocketService, User};
-use crate::components::theme_toggle::ThemeToggle;
+use crate::components::commands::{Action, Registry};
+use crate::components::composer::{self, Completions, Composer, InputHistory, DEFAULT_MAX_LENGTH};
+use crate::components::connection_banner::{ConnectionBanner, LeftRoom};
+use crate::components::mentions;
+use crate::components::message_list::{MessageList, MessageRow, Receipts, SeenObserver};
+use crate::components::notifier::Notifier;
+use crate::components::pins::{self, PinnedDrawer, Pins};
+use crate::components::profile::{Profile, ProfileCard, ProfileEditor};
+use crate::components::room_header::RoomHeader;
+use crate::components::search::{Search, SearchPanel};
+use crate::components::settings::{NotifyMode, Settings, SettingsPanel};
+use crate::components::topic;
+use crate::components::user_list::{Availability, UserList, UserListHeader, UserProfile};
+use crate::components::virtual_list::Viewport;
+
+/// How often the idle check runs (also refreshes "last seen" labels).
+const IDLE_CHECK_INTERVAL_MS: u32 = 30 * 1000;
+/// How long the server has to acknowledge a message before we call it failed.
+const ACK_TIMEOUT_MS: u32 = 10 * 1000;
+/// How long the message cache waits for things to settle before it is written.
+const CACHE_SAVE_DELAY_MS: u32 = 1000;
+/// Messages kept in memory; older ones are dropped and fetched again on demand.
+const MAX_BUFFERED_MESSAGES: usize = 500;
 
 pub enum Msg {
-    HandleMsg(String),
+    /// The socket opened or closed.
+    Connection(ConnectionState),
+    /// A frame from the server, as the bus delivered it.
+    Frame(Rc<Event>),
     SubmitMessage,
+    Activity,
+    CheckIdle,
+    ToggleDnd,
+    EditProfile,
+    CloseProfileEditor,
+    SaveProfile(Profile),
+    ShowProfile(String),
+    CloseProfile,
+    UploadAvatar(web_sys::File),
+    AvatarUploaded(Result<String, String>),
+    RemoveAvatar,
+    OpenSettings,
+    CloseSettings,
+    UpdateSettings(Settings),
+    NotificationPermission(bool),
+    ComposerInput,
+    ComposerKeyDown(KeyboardEvent),
+    CompleteMention(String),
+    CompleteCommand(String),
+    DismissNotice(usize),
+    JumpToMention,
+    VisibilityChanged(bool),
+    JumpToUnread,
+    MarkAllRead,
+    MessageSeen(u64),
+    Scrolled,
+    ScrollToBottom,
+    AckTimeout(String),
+    RetryMessage(String),
+    Restored(Result<(OfflineStore, Snapshot), String>),
+    SaveCache,
+    CancelEdit,
+    Search(String),
+    SearchServer,
+    JumpToMessage(u64),
+    CloseSearch,
+    TogglePin(u64),
+    SetTopic(String),
+    TogglePins,
+    Moderate(ModAction),
+    DeleteMessage(u64),
+    /// Sends an outbox entry that was held back to stay under the rate limit.
+    Transmit(String),
 }
 
-#[derive(Deserialize)]
-struct MessageData {
-    from: String,
-    message: String,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum MsgTypes {
-    Users,
-    Register,
-    Message,
-}
-
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct WebSocketMessage {
-    message_type: MsgTypes,
-    data_array: Option<Vec<String>>,
-    data: Option<String>,
+/// Callbacks handed to child components. They are made once, in `create`, so the
+/// children's props compare equal across renders and unchanged children skip theirs.
+struct Callbacks {
+    show_profile: Callback<String>,
+    scrolled: Callback<()>,
+    jump_to_unread: Callback<()>,
+    mark_all_read: Callback<()>,
+    scroll_to_bottom: Callback<()>,
+    composer_input: Callback<()>,
+    composer_keydown: Callback<KeyboardEvent>,
+    submit: Callback<()>,
+    complete_mention: Callback<String>,
+    complete_command: Callback<String>,
+    retry_message: Callback<String>,
+    cancel_edit: Callback<()>,
+    dismiss_notice: Callback<usize>,
+    jump_to_message: Callback<u64>,
+    search: Callback<String>,
+    close_search: Callback<()>,
+    toggle_pin: Callback<u64>,
+    set_topic: Callback<String>,
+    toggle_pins: Callback<()>,
+    jump_to_mention: Callback<()>,
+    edit_profile: Callback<()>,
+    open_settings: Callback<()>,
+    toggle_dnd: Callback<()>,
+    moderate: Callback<ModAction>,
+    delete_message: Callback<u64>,
 }
 
-#[derive(Clone)]
-struct UserProfile {
-    name: String,
-    avatar: String,
+impl Callbacks {
+    fn new(ctx: &Context<Chat>) -> Self {
+        let link = ctx.link();
+        Self {
+            show_profile: link.callback(Msg::ShowProfile),
+            scrolled: link.callback(|_| Msg::Scrolled),
+            jump_to_unread: link.callback(|_| Msg::JumpToUnread),
+            mark_all_read: link.callback(|_| Msg::MarkAllRead),
+            scroll_to_bottom: link.callback(|_| Msg::ScrollToBottom),
+            composer_input: link.callback(|_| Msg::ComposerInput),
+            composer_keydown: link.callback(Msg::ComposerKeyDown),
+            submit: link.callback(|_| Msg::SubmitMessage),
+            complete_mention: link.callback(Msg::CompleteMention),
+            retry_message: link.callback(Msg::RetryMessage),
+            cancel_edit: link.callback(|_| Msg::CancelEdit),
+            complete_command: link.callback(Msg::CompleteCommand),
+            dismiss_notice: link.callback(Msg::DismissNotice),
+            jump_to_message: link.callback(Msg::JumpToMessage),
+            search: link.callback(Msg::Search),
+            close_search: link.callback(|_| Msg::CloseSearch),
+            toggle_pin: link.callback(Msg::TogglePin),
+            set_topic: link.callback(Msg::SetTopic),
+            toggle_pins: link.callback(|_| Msg::TogglePins),
+            jump_to_mention: link.callback(|_| Msg::JumpToMention),
+            edit_profile: link.callback(|_| Msg::EditProfile),
+            open_settings: link.callback(|_| Msg::OpenSettings),
+            toggle_dnd: link.callback(|_| Msg::ToggleDnd),
+            moderate: link.callback(Msg::Moderate),
+            delete_message: link.callback(Msg::DeleteMessage),
+        }
+    }
 }
 
 pub struct Chat {
-    users: Vec<UserProfile>,
+    username: String,
+    users: Rc<Vec<UserProfile>>,
     chat_input: NodeRef,
     _producer: Box<dyn Bridge<EventBus>>,
-    wss: WebsocketService,
-    messages: Vec<MessageData>,
+    /// Dropped for good once we are kicked or banned, so we don't reconnect.
+    wss: Option<WebsocketService>,
+    /// The role key typed at login, if any, claimed again on every register.
+    key: String,
+    /// Why we were thrown out of the room, once we have been.
+    removed: Option<String>,
+    /// Our nick before a rename the server hasn't taken yet; it goes back to this if
+    /// the new one is taken.
+    renamed_from: Option<String>,
+    /// What the server has stored, by ID, then our messages it hasn't yet.
+    timeline: Timeline,
+    callbacks: Callbacks,
+    /// Counts idle checks, so the user list refreshes its "last seen" labels.
+    tick: u32,
+    availability: Availability,
+    _idle_check: Interval,
+    _activity_listeners: Vec<EventListener>,
+    profile: Profile,
+    editing_profile: bool,
+    shown_profile: Option<String>,
+    avatar_error: Option<String>,
+    settings: Settings,
+    show_settings: bool,
+    notifications_blocked: bool,
+    completions: Completions,
+    read: ReadState,
+    seen: SeenObserver,
+    receipts: Receipts,
+    /// Scroll position and windowing of the message list.
+    viewport: Viewport,
+    /// Our messages the server hasn't acknowledged yet.
+    outbox: Outbox,
+    connected: bool,
+    /// Server path of our uploaded avatar, restored after reconnecting.
+    avatar_path: Option<String>,
+    /// Where the outbox and recent messages outlive the tab, once it is open.
+    store: Option<OfflineStore>,
+    cache_save: Option<Timeout>,
+    /// ID of our message the composer is editing.
+    editing: Option<u64>,
+    input_history: InputHistory,
+    /// Characters in the composer, kept for its counter.
+    composer_length: Cell<usize>,
+    /// The longest message the server takes, in characters.
+    max_message_length: usize,
+    /// What the server said about itself when we last connected.
+    hello: Option<Hello>,
+    commands: Registry,
+    /// Local system messages, such as command output; never sent anywhere.
+    notices: Vec<String>,
+    search: Option<Search>,
+    /// A message to bring into view once it is loaded and rendered.
+    reveal: Option<u64>,
+    /// The search result or pin last opened, picked out in the list.
+    highlighted: Option<u64>,
+    pins: Pins,
+}
+
+impl Chat {
+    fn send_presence(&mut self, presence: Presence) {
+        self.availability.reported = presence;
+        self.send(WebSocketMessage {
+            message_type: MsgTypes::Presence,
+            data: Some(presence.as_str().into()),
+            data_array: None,
+            client_id: None,
+        });
+    }
+
+    fn send_profile(&self) {
+        self.send(WebSocketMessage {
+            message_type: MsgTypes::Profile,
+            data: Some(serde_json::to_string(&self.profile).unwrap()),
+            data_array: None,
+            client_id: None,
+        });
+    }
+
+    fn send_avatar(&mut self, path: String) {
+        self.avatar_path = Some(path.clone()).filter(|p| !p.is_empty());
+        self.send(WebSocketMessage {
+            message_type: MsgTypes::Avatar,
+            data: Some(path),
+            data_array: None,
+            client_id: None,
+        });
+    }
+
+    /// Introduces us to the server and restores what it knew about us, which it
+    /// forgets when the connection drops.
+    fn register(&mut self) {
+        self.send(WebSocketMessage {
+            message_type: MsgTypes::Register,
+            data: Some(self.username.clone()),
+            data_array: (!self.key.is_empty()).then(|| vec![format!("{}{}", KEY_PREFIX, self.key)]),
+            client_id: None,
+        });
+        if self.availability.reported != Presence::Online {
+            self.send_presence(self.availability.reported);
+        }
+        if self.profile != Profile::default() {
+            self.send_profile();
+        }
+        if let Some(path) = self.avatar_path.clone() {
+            self.send_avatar(path);
+        }
+    }
+
+    /// Sends message `client_id` from the outbox, if we are connected, and starts
+    /// the clock on its acknowledgement.
+    fn transmit(&mut self, ctx: &Context<Self>, client_id: &str) {
+        if !self.connected {
+            return;
+        }
+        let frame = match self.outbox.get(client_id) {
+            Some(entry) => WebSocketMessage {
+                message_type: MsgTypes::Message,
+                data: Some(entry.text.clone()),
+                data_array: Some(entry.mentions.clone()),
+                client_id: Some(entry.client_id.clone()),
+            },
+            None => return,
+        };
+        if !self.send(frame) {
+            self.outbox.fail(client_id);
+            return;
+        }
+        self.outbox.mark_sent(client_id, js_sys::Date::now());
+        let timeout = ctx.link().callback(Msg::AckTimeout);
+        let client_id = client_id.to_string();
+        Timeout::new(ACK_TIMEOUT_MS, move || timeout.emit(client_id)).forget();
+    }
+
+    /// Sends a backlog of outbox entries, as fast as the server's rate limit allows:
+    /// a burst straight away, the rest spaced out behind it.
+    fn flush(&mut self, ctx: &Context<Self>, client_ids: Vec<String>) {
+        let (burst, per_second) = match &self.hello {
+            Some(hello) if hello.limits.messages_per_second > 0.0 => {
+                (hello.limits.message_burst as usize, hello.limits.messages_per_second)
+            }
+            _ => (client_ids.len(), 1.0),
+        };
+        let delays = outbox::pace(client_ids.len(), burst, per_second);
+        for (client_id, delay) in client_ids.into_iter().zip(delays) {
+            if delay == 0 {
+                self.transmit(ctx, &client_id);
+            } else {
+                let transmit = ctx.link().callback(Msg::Transmit);
+                Timeout::new(delay, move || transmit.emit(client_id)).forget();
+            }
+        }
+    }
+
+    fn persist_outbox(&self) {
+        if let Some(store) = &self.store {
+            store.save_outbox(self.outbox.entries());
+        }
+    }
+
+    /// If the timeline `changed`, writes the message cache once things have been
+    /// quiet for a moment. Passes `changed` on, as whether to re-render.
+    fn timeline_changed(&mut self, ctx: &Context<Self>, changed: bool) -> bool {
+        if changed && self.store.is_some() {
+            let save = ctx.link().callback(|_: ()| Msg::SaveCache);
+            self.cache_save = Some(Timeout::new(CACHE_SAVE_DELAY_MS, move || save.emit(())));
+        }
+        changed
+    }
+
+    /// Users matching the `@partial` being typed, excluding ourselves.
+    fn mention_candidates(&self) -> Vec<String> {
+        self.completions.mentions(self.users.iter().map(|u| &u.name), &self.username)
+    }
+
+    fn composer_text(&self) -> String {
+        self.chat_input
+            .cast::<HtmlTextAreaElement>()
+            .map(|input| input.value())
+            .unwrap_or_default()
+    }
+
+    /// Replaces what the composer holds, with the caret at the end.
+    fn set_composer_text(&self, text: &str) {
+        self.composer_length.set(text.chars().count());
+        if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+            input.set_value(text);
+            let end = text.encode_utf16().count() as u32;
+            let _ = input.set_selection_range(end, end);
+            composer::fit(&input);
+            let _ = input.focus();
+        }
+    }
+
+    /// Loads our newest message into the composer to be edited.
+    fn edit_last_message(&mut self) -> bool {
+        match self.timeline.last_from(&self.username) {
+            Some(message) => {
+                self.editing = Some(message.id);
+                self.set_composer_text(&message.message);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether `text` is over the server's limit; if so, says so. The composer keeps
+    /// it, to be cut down.
+    fn too_long(&mut self, text: &str) -> bool {
+        let length = text.chars().count();
+        if length > self.max_message_length {
+            self.notice(format!(
+                "That message is {} characters long; the limit is {}.",
+                length, self.max_message_length
+            ));
+        }
+        length > self.max_message_length
+    }
+
+    fn send_edit(&self, id: u64, text: String) {
+        let mentioned = mentions::find_mentions(&text, self.users.iter().map(|u| &u.name));
+        self.send(WebSocketMessage {
+            message_type: MsgTypes::Edit,
+            data: Some(serde_json::to_string(&MessageEdit { id, message: text }).unwrap()),
+            data_array: Some(mentioned),
+            client_id: None,
+        });
+    }
+
+    /// Opens, updates or closes the autocomplete popups to match the composer.
+    fn update_completions(&mut self) -> bool {
+        match self.chat_input.cast::<HtmlTextAreaElement>() {
+            Some(input) => self.completions.update(&input.value(), input.selection_start().ok().flatten()),
+            None => self.completions.update("", None),
+        }
+    }
+
+    fn notice(&mut self, text: String) {
+        self.notices.push(text);
+        self.viewport.follow();
+    }
+
+    /// Carries out what a command asked for, other than sending a message.
+    fn run_action(&mut self, ctx: &Context<Self>, action: Action) {
+        match action {
+            Action::Send(_) => {}
+            Action::Rename(name) => self.rename(ctx, name),
+            Action::Clear => {
+                self.timeline.clear();
+                self.notices.clear();
+                self.viewport.clear_below();
+                self.read.clear();
+            }
+            Action::SetTopic(text) => {
+                if self.own_role().is_privileged() {
+                    self.send(topic::frame(text));
+                } else {
+                    self.notice(topic::DENIED.into());
+                }
+            }
+            Action::Audit => {
+                if self.own_role().is_privileged() {
+                    self.send(WebSocketMessage {
+                        message_type: MsgTypes::Audit,
+                        data: None,
+                        data_array: None,
+                        client_id: None,
+                    });
+                } else {
+                    self.notice("Only moderators and admins can see the moderation log.".into());
+                }
+            }
+            Action::Help => self.notice(self.commands.help()),
+            Action::Notice(text) => self.notice(text),
+        }
+    }
+
+    fn rename(&mut self, ctx: &Context<Self>, name: String) {
+        let taken = self
+            .users
+            .iter()
+            .any(|u| u.name == name && u.presence != Presence::Offline);
+        if name == self.username {
+            self.notice(format!("You are already {}.", name));
+        } else if taken {
+            self.notice(format!("{} is already taken.", name));
+        } else if !self.outbox.entries().is_empty() {
+            // Their echoes would come back under the old name and never match.
+            self.notice("Wait for your messages to go out before changing your nickname.".into());
+        } else {
+            self.renamed_from = Some(self.set_username(ctx, name));
+            self.register();
+            self.notice(format!("You are now known as {}.", self.username));
+        }
+    }
+
+    /// Goes by `name` from now on, here and at login; returns the old name.
+    fn set_username(&mut self, ctx: &Context<Self>, name: String) -> String {
+        let (user, _) = ctx.link().context::<User>(Callback::noop()).expect("context to be set");
+        *user.username.borrow_mut() = name.clone();
+        std::mem::replace(&mut self.username, name)
+    }
+
+    /// Moves our read marker to the newest message, locally and (if enabled) on the
+    /// server.
+    fn mark_read(&mut self) {
+        let moved = self.read.mark_read(&self.username, self.timeline.newest_id());
+        if let (Some(newest), true) = (moved, self.settings.sync_read_markers) {
+            self.send(WebSocketMessage {
+                message_type: MsgTypes::Marker,
+                data: Some(
+                    serde_json::to_string(&ReadMarker {
+                        conversation: DEFAULT_CONVERSATION.into(),
+                        last_read: newest,
+                    })
+                    .unwrap(),
+                ),
+                data_array: None,
+                client_id: None,
+            });
+        }
+        self.update_title();
+    }
+
+    /// Shows the unread count in the tab title while the tab is hidden.
+    fn update_title(&self) {
+        let unread = if document_hidden() {
+            self.read.unread_count(self.timeline.messages(), &self.username)
+        } else {
+            0
+        };
+        self.read.show_unread(unread);
+    }
+
+    /// The message's own avatar if it carries one, otherwise its sender's.
+    fn avatar_for(&self, message: &MessageData) -> String {
+        if message.avatar.is_some() {
+            return avatar::resolve(&message.from, message.avatar.as_deref());
+        }
+        self.users
+            .iter()
+            .find(|u| u.name == message.from)
+            .map_or_else(|| avatar::generated(&message.from), |u| u.avatar.clone())
+    }
+
+    /// The first message from someone else after the "New messages" divider.
+    fn first_unread(&self) -> Option<u64> {
+        self.read.first_unread(self.timeline.messages(), &self.username)
+    }
+
+    /// Drops the oldest messages once we hold more than `MAX_BUFFERED_MESSAGES`;
+    /// scrolling back up fetches them again.
+    fn evict_old_messages(&mut self) {
+        for id in self.timeline.evict(MAX_BUFFERED_MESSAGES) {
+            self.viewport.forget(id);
+        }
+    }
+
+    fn load_older(&mut self) {
+        if let Some(frame) = self.timeline.load_older() {
+            self.send(frame);
+        }
+    }
+
+    fn load_back_to(&mut self, id: u64) {
+        if let Some(frame) = self.timeline.load_back_to(id) {
+            self.send(frame);
+        }
+    }
+
+    fn own_role(&self) -> Role {
+        self.users
+            .iter()
+            .find(|u| u.name == self.username)
+            .map_or(Role::Member, |u| u.role)
+    }
+
+    /// When our mute runs out, if we are muted.
+    fn muted_until(&self) -> Option<f64> {
+        self.users
+            .iter()
+            .find(|u| u.name == self.username)
+            .and_then(|u| u.muted_until)
+            .filter(|until| *until > js_sys::Date::now())
+    }
+
+    fn send_moderation(&self, action: &ModAction) {
+        self.send(WebSocketMessage {
+            message_type: MsgTypes::Moderate,
+            data: Some(serde_json::to_string(action).unwrap()),
+            data_array: None,
+            client_id: None,
+        });
+    }
+
+    /// Hands a frame from the server to whatever it concerns; returns whether to
+    /// re-render.
+    fn receive(&mut self, ctx: &Context<Self>, frame: &ServerEvent) -> bool {
+        match frame {
+            ServerEvent::Users(users) => {
+                self.users = Rc::new(users.iter().map(UserProfile::from).collect());
+                if users.iter().any(|u| u.nick == self.username && u.presence != Presence::Offline) {
+                    self.renamed_from = None;
+                }
+                true
+            }
+            ServerEvent::Message(message) => self.receive_message(ctx, message),
+            ServerEvent::History(page) => self.receive_history(ctx, page.clone()),
+            ServerEvent::Read(receipt) => {
+                self.receipts.insert(receipt);
+                true
+            }
+            ServerEvent::Receipts(receipts) => {
+                self.receipts.replace(receipts);
+                true
+            }
+            ServerEvent::Marker(marker) => {
+                self.read.sync(&self.username, marker);
+                false
+            }
+            ServerEvent::Ack(ack) => self.receive_ack(ctx, ack),
+            ServerEvent::Edit(message) => {
+                let replaced = self.timeline.edit(message.clone());
+                self.timeline_changed(ctx, replaced)
+            }
+            // An answer to something no longer in the search box changes nothing.
+            ServerEvent::Search(found) => self.search.as_mut().is_some_and(|search| search.receive(found)),
+            ServerEvent::Pins(pins) => {
+                self.pins.set(pins.clone());
+                true
+            }
+            ServerEvent::Moderation(notice) => {
+                self.receive_moderation(notice);
+                true
+            }
+            ServerEvent::Delete(id) => {
+                if let Some(search) = &mut self.search {
+                    search.forget(*id);
+                }
+                if self.highlighted == Some(*id) {
+                    self.highlighted = None;
+                }
+                let removed = self.timeline.delete(*id);
+                self.timeline_changed(ctx, removed)
+            }
+            ServerEvent::Hello(hello) => {
+                self.greet(hello.clone());
+                true
+            }
+            ServerEvent::Error(error) => {
+                self.receive_error(ctx, error);
+                true
+            }
+            ServerEvent::Audit(entries) => {
+                self.notice(moderation::audit_report(entries));
+                true
+            }
+            // `TopicLine` and the service keep these to themselves.
+            ServerEvent::Topic(_) | ServerEvent::Pong => false,
+        }
+    }
+
+    fn receive_message(&mut self, ctx: &Context<Self>, message: &MessageData) -> bool {
+        if let (Some(client_id), true) = (&message.client_id, message.from == self.username) {
+            if self.outbox.acknowledge(client_id) {
+                self.persist_outbox();
+            }
+        }
+        // Replaces our own copy if we sent it; a repeated echo changes nothing.
+        let inserted = self.timeline.receive(message.clone());
+        if !self.timeline_changed(ctx, inserted) {
+            return false;
+        }
+        let from_other = message.from != self.username;
+        // Out of sight: the tab is hidden or we are reading further up.
+        let unseen = from_other && (!self.viewport.at_bottom() || document_hidden());
+        if unseen {
+            self.read.missed(message, &self.username);
+        }
+        if self.viewport.at_bottom() || !from_other {
+            self.viewport.follow();
+            self.evict_old_messages();
+        } else {
+            self.viewport.arrived_below();
+        }
+        if document_hidden() {
+            if from_other {
+                self.read.hold_divider();
+            }
+            self.update_title();
+        } else if !unseen {
+            self.mark_read();
+        }
+        true
+    }
+
+    fn receive_history(&mut self, ctx: &Context<Self>, history: Vec<MessageData>) -> bool {
+        let page = self.timeline.receive_page(history);
+        match page.old_first {
+            None => self.viewport.follow(),
+            Some(first) if page.prepends => {
+                self.viewport.anchor();
+                self.viewport.shift_past(&self.timeline.keys(), first);
+            }
+            Some(_) => {}
+        }
+        self.timeline_changed(ctx, true);
+        if let Some(id) = self.reveal.filter(|id| !self.timeline.contains(*id)) {
+            // Still short of a search result we're jumping to: keep going back while
+            // there is more, or give up.
+            if page.prepends {
+                self.load_back_to(id);
+            } else {
+                self.reveal = None;
+            }
+        }
+        self.read.place_divider(self.timeline.messages(), &self.username);
+        if document_hidden() {
+            self.update_title();
+        } else {
+            self.mark_read();
+        }
+        true
+    }
+
+    fn receive_ack(&mut self, ctx: &Context<Self>, ack: &Ack) -> bool {
+        log::debug!("message {} stored as {}", ack.client_id, ack.id);
+        let confirmed = self.timeline.confirm(&self.username, &ack.client_id, ack.id);
+        self.timeline_changed(ctx, confirmed);
+        let acknowledged = self.outbox.acknowledge(&ack.client_id);
+        if acknowledged {
+            self.persist_outbox();
+        }
+        acknowledged || confirmed
+    }
+
+    fn receive_moderation(&mut self, notice: &ModNotice) {
+        match moderation::outcome(notice) {
+            Outcome::Leave(reason) => self.leave(reason),
+            Outcome::Notice { text, failed } => {
+                if let Some(client_id) = failed {
+                    if self.outbox.fail(&client_id) {
+                        self.persist_outbox();
+                    }
+                }
+                self.notice(text);
+            }
+        }
+    }
+
+    /// Shows what the server turned away; a chat message it refused is marked failed,
+    /// so it can be retried, and a rename it refused is undone.
+    fn receive_error(&mut self, ctx: &Context<Self>, error: &ServerError) {
+        if let (ErrorCode::NickTaken, Some(name)) = (error.code, self.renamed_from.take()) {
+            // The server still knows us by the old name.
+            self.set_username(ctx, name);
+        }
+        if let Some(client_id) = &error.client_id {
+            if self.outbox.fail(client_id) {
+                self.persist_outbox();
+            }
+        }
+        match error.retry_after {
+            Some(ms) if ms > 0.0 => self.notice(format!("{} Try again in {}s.", error.message, (ms / 1000.0).ceil())),
+            _ => self.notice(error.message.clone()),
+        }
+    }
+
+    fn features(&self) -> Features {
+        self.hello.as_ref().map_or_else(Features::default, |hello| hello.features)
+    }
+
+    /// Takes in what the server says about itself. It says it again on every
+    /// reconnect, so only news is passed on to the user.
+    fn greet(&mut self, hello: Hello) {
+        let previous = self.hello.replace(hello.clone());
+        self.max_message_length = hello.limits.message_length;
+        let new_version = previous.as_ref().map(|p| p.protocol_version) != Some(hello.protocol_version);
+        if hello.protocol_version != PROTOCOL_VERSION && new_version {
+            self.notice(format!(
+                "{} speaks version {} of the chat protocol, but this page speaks version {}. Some things may not work until you reload.",
+                hello.server_name, hello.protocol_version, PROTOCOL_VERSION
+            ));
+        }
+        let motd = hello.motd.filter(|motd| !motd.trim().is_empty());
+        if motd.is_some() && motd != previous.and_then(|p| p.motd) {
+            self.notice(format!("📣 {}", motd.unwrap_or_default()));
+        }
+        if !hello.features.search {
+            self.search = None;
+        }
+    }
+
+    /// Stops talking to the server for good and says why.
+    fn leave(&mut self, reason: String) {
+        self.wss = None;
+        self.connected = false;
+        self.removed = Some(reason);
+    }
+
+    /// Queues a frame for the socket; returns whether it was accepted.
+    fn send(&self, message: WebSocketMessage) -> bool {
+        let wss = match &self.wss {
+            Some(wss) => wss,
+            None => return false,
+        };
+        if let Err(e) = wss.tx.clone().try_send(serde_json::to_string(&message).unwrap()) {
+            log::debug!("error sending to channel: {:?}", e);
+            return false;
+        }
+        true
+    }
+
+    fn view_profile_card(&self, ctx: &Context<Self>, name: &str) -> Html {
+        let onclose = ctx.link().callback(|_| Msg::CloseProfile);
+        match self.users.iter().find(|u| u.name == name) {
+            Some(u) => html! {
+                <ProfileCard
+                    name={u.name.clone()}
+                    avatar={u.avatar.clone()}
+                    presence={u.presence}
+                    last_seen={u.last_seen}
+                    profile={u.profile.clone()}
+                    {onclose}
+                />
+            },
+            None => html! {
+                <ProfileCard
+                    name={name.to_string()}
+                    avatar={avatar::generated(name)}
+                    presence={Presence::Offline}
+                    last_seen={None::<f64>}
+                    profile={Profile::default()}
+                    {onclose}
+                />
+            },
+        }
+    }
+
+    /// Rows for the messages in `window`. An unchanged message makes a row that
+    /// compares equal, so its bubble skips rendering.
+    fn rows(&self, window: std::ops::Range<usize>) -> Vec<MessageRow> {
+        let first_unread = self.first_unread();
+        let privileged = self.own_role().is_privileged();
+        self.timeline.messages()[window]
+            .iter()
+            .map(|m| {
+                let own = m.from == self.username;
+                MessageRow {
+                    message: m.clone(),
+                    avatar: self.avatar_for(m),
+                    own,
+                    mentions_me: !own && m.mentions.contains(&self.username),
+                    first_unread: first_unread == Some(m.id),
+                    seen_by: self.receipts.seen_by(m, &self.username, &self.users),
+                    delivery: None,
+                    highlighted: self.highlighted == Some(m.id),
+                    pinned: self.pins.contains(m.id),
+                    deletable: own || privileged,
+                }
+            })
+            .collect()
+    }
+
+    /// Rows for our messages the server hasn't stored yet.
+    fn pending_rows(&self, avatar: &str) -> Vec<MessageRow> {
+        timeline::pending(self.timeline.messages())
+            .iter()
+            .map(|m| MessageRow {
+                message: m.clone(),
+                avatar: avatar.to_string(),
+                own: true,
+                mentions_me: false,
+                first_unread: false,
+                seen_by: vec![],
+                highlighted: false,
+                pinned: false,
+                deletable: false,
+                delivery: Some(
+                    m.client_id
+                        .as_deref()
+                        .and_then(|client_id| self.outbox.get(client_id))
+                        .map_or(Delivery::Pending, |entry| entry.delivery),
+                ),
+            })
+            .collect()
+    }
 }
+
 impl Component for Chat {
     type Message = Msg;
     type Properties = ();
@@ -58,241 +862,709 @@ impl Component for Chat {
             .expect("context to be set");
         let wss = WebsocketService::new();
         let username = user.username.borrow().clone();
+        let key = user.key.borrow().clone();
+        {
+            let username = username.clone();
+            ctx.link()
+                .send_future(async move { Msg::Restored(OfflineStore::restore(&username).await) });
+        }
 
-        let message = WebSocketMessage {
-            message_type: MsgTypes::Register,
-            data: Some(username.to_string()),
-            data_array: None,
+        let idle_check = {
+            let check = ctx.link().callback(|_: ()| Msg::CheckIdle);
+            Interval::new(IDLE_CHECK_INTERVAL_MS, move || check.emit(()))
         };
 
-        if let Ok(_) = wss
-            .tx
-            .clone()
-            .try_send(serde_json::to_string(&message).unwrap())
-        {
-            log::debug!("message sent successfully");
-        }
+        let document = web_sys::window()
+            .and_then(|w| w.document())
+            .expect("no document exists");
+        let mut activity_listeners: Vec<EventListener> = ["keydown", "mousemove", "pointerdown"]
+            .iter()
+            .map(|event| {
+                let activity = ctx.link().callback(|_: ()| Msg::Activity);
+                EventListener::new(&document, *event, move |_| activity.emit(()))
+            })
+            .collect();
+        activity_listeners.push({
+            let visibility = ctx.link().callback(Msg::VisibilityChanged);
+            let doc = document.clone();
+            EventListener::new(&document, "visibilitychange", move |_| visibility.emit(doc.hidden()))
+        });
 
         Self {
-            users: vec![],
-            messages: vec![],
+            users: Rc::new(vec![]),
+            timeline: Timeline::default(),
+            callbacks: Callbacks::new(ctx),
+            tick: 0,
             chat_input: NodeRef::default(),
-            wss,
-            _producer: EventBus::bridge(ctx.link().callback(Msg::HandleMsg)),
+            wss: Some(wss),
+            key,
+            removed: None,
+            renamed_from: None,
+            _producer: EventBus::subscribe(
+                &[Topic::Connection, Topic::Chat, Topic::Presence],
+                ctx.link().callback(|event: Rc<Event>| match &*event {
+                    Event::Connection(state) => Msg::Connection(*state),
+                    Event::Server(_) => Msg::Frame(event.clone()),
+                }),
+            ),
+            availability: Availability::new(js_sys::Date::now()),
+            _idle_check: idle_check,
+            _activity_listeners: activity_listeners,
+            profile: Profile::load(),
+            editing_profile: false,
+            shown_profile: None,
+            avatar_error: None,
+            settings: Settings::load(),
+            show_settings: false,
+            notifications_blocked: false,
+            completions: Completions::default(),
+            read: ReadState::load(&username, document.title()),
+            seen: SeenObserver::new(ctx.link().callback(Msg::MessageSeen)),
+            receipts: Receipts::default(),
+            viewport: Viewport::new(),
+            outbox: Outbox::default(),
+            connected: false,
+            avatar_path: None,
+            store: None,
+            cache_save: None,
+            editing: None,
+            input_history: InputHistory::default(),
+            composer_length: Cell::new(0),
+            max_message_length: DEFAULT_MAX_LENGTH,
+            hello: None,
+            commands: Registry::with_defaults(),
+            notices: vec![],
+            search: None,
+            reveal: None,
+            highlighted: None,
+            pins: Pins::default(),
+            username,
         }
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            Msg::HandleMsg(s) => {
-                let msg: WebSocketMessage = serde_json::from_str(&s).unwrap();
-                match msg.message_type {
-                    MsgTypes::Users => {
-                        let users_from_message = msg.data_array.unwrap_or_default();
-                        self.users = users_from_message
-                            .iter()
-                            .map(|u| UserProfile {
-                                name: u.into(),
-                                avatar: format!(
-                                    "https://avatars.dicebear.com/api/adventurer-neutral/{}.svg",
-                                    u
-                                )
-                                .into(),
-                            })
-                            .collect();
+            // A service we have let go of may still be winding down.
+            Msg::Connection(_) if self.wss.is_none() => false,
+            Msg::Connection(ConnectionState::Open) => {
+                // Also our cue after a reconnect: the server has forgotten us and
+                // whatever was in flight.
+                self.connected = true;
+                self.register();
+                self.flush(ctx, self.outbox.pending());
+                true
+            }
+            Msg::Connection(_) => {
+                self.connected = false;
+                false
+            }
+            Msg::Frame(event) => match &*event {
+                Event::Server(frame) => self.receive(ctx, frame),
+                Event::Connection(_) => false,
+            },
+            Msg::SubmitMessage => {
+                let text = self.composer_text().trim().to_string();
+                if self.editing.is_some() && self.too_long(&text) {
+                    return true;
+                }
+                if let Some(id) = self.editing.take() {
+                    let unchanged = timeline::confirmed(self.timeline.messages())
+                        .iter()
+                        .any(|m| m.id == id && m.message == text);
+                    // Clearing a message out doesn't delete it; it just cancels the edit.
+                    if !text.is_empty() && !unchanged {
+                        self.send_edit(id, text);
+                    }
+                    self.set_composer_text("");
+                    self.completions.close();
+                    return true;
+                }
+                if text.is_empty() {
+                    return false;
+                }
+                self.input_history.push(text.clone());
+                self.completions.close();
+                let text = match self.commands.run(&text) {
+                    None => text,
+                    Some(Ok(Action::Send(text))) => text,
+                    Some(Ok(action)) => {
+                        self.run_action(ctx, action);
+                        self.set_composer_text("");
                         return true;
                     }
-                    MsgTypes::Message => {
-                        let message_data: MessageData =
-                            serde_json::from_str(&msg.data.unwrap()).unwrap();
-                        self.messages.push(message_data);
+                    // Left in the composer to be fixed.
+                    Some(Err(e)) => {
+                        self.notice(e);
                         return true;
                     }
-                    _ => {
-                        return false;
+                };
+                // Counted once commands such as /shrug have had their say.
+                if self.too_long(&text) {
+                    return true;
+                }
+                if let Some(until) = self.muted_until() {
+                    // Left in the composer for when the mute is over.
+                    self.notice(format!("You are muted until {}.", moderation::clock_time(until)));
+                    return true;
+                }
+                let mentioned = mentions::find_mentions(&text, self.users.iter().map(|u| &u.name));
+                let client_id = self.outbox.push(text, mentioned, js_sys::Date::now());
+                if let Some(entry) = self.outbox.get(&client_id) {
+                    // Shown straight away; the server's echo takes its place later.
+                    self.timeline.push_pending(entry.pending_copy(&self.username));
+                }
+                self.transmit(ctx, &client_id);
+                self.persist_outbox();
+                self.viewport.follow();
+                self.set_composer_text("");
+                self.read.divider_after = None;
+                true
+            }
+            Msg::Activity => {
+                if let Some(presence) = self.availability.activity(js_sys::Date::now()) {
+                    self.send_presence(presence);
+                }
+                false
+            }
+            Msg::CheckIdle => {
+                if let Some(presence) = self.availability.check_idle(js_sys::Date::now()) {
+                    self.send_presence(presence);
+                }
+                // Re-render so "last seen" labels stay current.
+                self.tick = self.tick.wrapping_add(1);
+                true
+            }
+            Msg::ToggleDnd => {
+                let presence = if self.availability.chosen == Presence::Dnd { Presence::Online } else { Presence::Dnd };
+                self.availability.choose(presence, js_sys::Date::now());
+                self.send_presence(presence);
+                true
+            }
+            Msg::EditProfile => {
+                self.editing_profile = true;
+                self.avatar_error = None;
+                true
+            }
+            Msg::CloseProfileEditor => {
+                self.editing_profile = false;
+                true
+            }
+            Msg::SaveProfile(profile) => {
+                self.profile = profile;
+                self.profile.save();
+                self.send_profile();
+                self.editing_profile = false;
+                true
+            }
+            Msg::ShowProfile(name) => {
+                self.shown_profile = Some(name);
+                true
+            }
+            Msg::CloseProfile => {
+                self.shown_profile = None;
+                true
+            }
+            Msg::UploadAvatar(file) => {
+                if let Some(hello) = self.hello.as_ref().filter(|h| file.size() > h.limits.upload_bytes as f64) {
+                    self.avatar_error = Some(format!(
+                        "Images are limited to {} KB.",
+                        hello.limits.upload_bytes / 1024
+                    ));
+                    return true;
+                }
+                self.avatar_error = None;
+                ctx.link()
+                    .send_future(async move { Msg::AvatarUploaded(avatar::upload(file).await) });
+                true
+            }
+            Msg::AvatarUploaded(Ok(path)) => {
+                self.send_avatar(path);
+                false
+            }
+            Msg::AvatarUploaded(Err(e)) => {
+                log::debug!("avatar upload failed: {}", e);
+                self.avatar_error = Some(format!("Upload failed: {}", e));
+                true
+            }
+            Msg::RemoveAvatar => {
+                self.send_avatar(String::new());
+                false
+            }
+            Msg::OpenSettings => {
+                self.show_settings = true;
+                true
+            }
+            Msg::CloseSettings => {
+                self.show_settings = false;
+                true
+            }
+            Msg::UpdateSettings(settings) => {
+                let wants_notifications = settings.notifications != NotifyMode::Off;
+                self.settings = settings;
+                self.settings.save();
+                if wants_notifications && !notifications::permission_granted() {
+                    ctx.link().send_future(async {
+                        Msg::NotificationPermission(notifications::request_permission().await)
+                    });
+                }
+                true
+            }
+            Msg::ComposerInput => {
+                let length = self.composer_text().chars().count();
+                let resized = self.composer_length.replace(length) != length;
+                self.update_completions() || resized
+            }
+            Msg::ComposerKeyDown(e) => {
+                let commands = self.completions.commands(&self.commands);
+                let mentions = self.mention_candidates();
+                let count = if commands.is_empty() { mentions.len() } else { commands.len() };
+                // An open popup gets first say over the keys it uses.
+                let popup = count > 0;
+                match e.key().as_str() {
+                    "ArrowDown" if popup => {
+                        e.prevent_default();
+                        self.completions.next(count);
+                        true
+                    }
+                    "ArrowUp" if popup => {
+                        e.prevent_default();
+                        self.completions.previous(count);
+                        true
+                    }
+                    "Enter" | "Tab" if popup => {
+                        e.prevent_default();
+                        let selected = self.completions.selected.min(count - 1);
+                        ctx.link().send_message(match commands.get(selected) {
+                            Some(command) => Msg::CompleteCommand(command.name.clone()),
+                            None => Msg::CompleteMention(mentions[selected].clone()),
+                        });
+                        false
+                    }
+                    "Escape" if popup => {
+                        self.completions.close();
+                        true
+                    }
+                    // Shift+Enter falls through to the textarea as a newline.
+                    "Enter" if !e.shift_key() && !e.is_composing() => {
+                        e.prevent_default();
+                        ctx.link().send_message(Msg::SubmitMessage);
+                        false
                     }
+                    "ArrowUp" if e.ctrl_key() => {
+                        e.prevent_default();
+                        if let Some(text) = self.input_history.back(&self.composer_text()) {
+                            self.set_composer_text(&text);
+                        }
+                        false
+                    }
+                    "ArrowDown" if e.ctrl_key() => {
+                        e.prevent_default();
+                        if let Some(text) = self.input_history.forward() {
+                            self.set_composer_text(&text);
+                        }
+                        false
+                    }
+                    "ArrowUp" if self.editing.is_none() && self.composer_text().is_empty() => {
+                        e.prevent_default();
+                        self.edit_last_message()
+                    }
+                    "Escape" if self.editing.is_some() => {
+                        ctx.link().send_message(Msg::CancelEdit);
+                        false
+                    }
+                    _ => false,
                 }
             }
-            Msg::SubmitMessage => {
-                let input = self.chat_input.cast::<HtmlInputElement>();
-                if let Some(input) = input {
-                    let message = WebSocketMessage {
-                        message_type: MsgTypes::Message,
-                        data: Some(input.value()),
-                        data_array: None,
-                    };
-                    if let Err(e) = self
-                        .wss
-                        .tx
-                        .clone()
-                        .try_send(serde_json::to_string(&message).unwrap())
-                    {
-                        log::debug!("error sending to channel: {:?}", e);
+            Msg::CompleteCommand(name) => {
+                self.set_composer_text(&format!("/{} ", name));
+                self.completions.close_command();
+                true
+            }
+            Msg::DismissNotice(index) => {
+                if index < self.notices.len() {
+                    self.notices.remove(index);
+                }
+                true
+            }
+            Msg::Search(query) => {
+                let ask_server = ctx.link().callback(|_: ()| Msg::SearchServer);
+                self.search = Search::start(query, timeline::confirmed(self.timeline.messages()), ask_server);
+                true
+            }
+            Msg::SearchServer => {
+                if let Some(search) = &self.search {
+                    self.send(search.frame());
+                }
+                false
+            }
+            Msg::JumpToMessage(id) => {
+                self.highlighted = Some(id);
+                self.reveal = Some(id);
+                // Stop following new messages, or they would pull the list away again.
+                self.viewport.stop_following();
+                if !self.timeline.contains(id) {
+                    self.load_back_to(id);
+                }
+                true
+            }
+            Msg::CloseSearch => {
+                self.search = None;
+                self.reveal = None;
+                self.highlighted = None;
+                true
+            }
+            Msg::TogglePin(id) => {
+                if self.pins.contains(id) && !self.pins.may_unpin(id, &self.username, self.own_role().is_privileged()) {
+                    self.notice(pins::UNPIN_DENIED.into());
+                    return true;
+                }
+                self.send(self.pins.toggle_frame(id));
+                false
+            }
+            Msg::SetTopic(text) => {
+                self.send(topic::frame(text));
+                false
+            }
+            Msg::TogglePins => {
+                self.pins.open = !self.pins.open;
+                true
+            }
+            Msg::Moderate(action) => {
+                self.send_moderation(&action);
+                false
+            }
+            Msg::Transmit(client_id) => {
+                let waiting = self
+                    .outbox
+                    .get(&client_id)
+                    .is_some_and(|e| e.delivery == Delivery::Pending && e.sent_at.is_none());
+                if waiting {
+                    self.transmit(ctx, &client_id);
+                }
+                false
+            }
+            Msg::DeleteMessage(id) => {
+                let confirmed = web_sys::window()
+                    .and_then(|w| w.confirm_with_message("Delete this message for everyone?").ok())
+                    .unwrap_or(false);
+                if confirmed {
+                    self.send_moderation(&ModAction::Delete { id });
+                }
+                false
+            }
+            Msg::CancelEdit => {
+                self.editing = None;
+                self.set_composer_text("");
+                true
+            }
+            Msg::CompleteMention(name) => {
+                if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                    let caret = input.selection_start().ok().flatten().unwrap_or(0);
+                    if let Some((value, caret)) = mentions::complete(&input.value(), caret, &name) {
+                        input.set_value(&value);
+                        let _ = input.set_selection_range(caret, caret);
+                    }
+                    let _ = input.focus();
+                }
+                self.completions.close_mention();
+                true
+            }
+            Msg::JumpToMention => {
+                if !self.read.unread_mentions.is_empty() {
+                    let id = self.read.unread_mentions.remove(0);
+                    self.viewport.scroll_to(&self.timeline.keys(), id);
+                }
+                true
+            }
+            Msg::VisibilityChanged(hidden) => {
+                if hidden {
+                    // The tab may be about to go away; don't wait for the timer.
+                    if let (Some(store), Some(_)) = (&self.store, self.cache_save.take()) {
+                        store.save_messages(self.timeline.messages());
                     }
-                    input.set_value("");
+                    return false;
+                }
+                ctx.link().send_message(Msg::Activity);
+                // Scrolled up, we haven't seen what came in; reaching the bottom reads it.
+                if self.viewport.at_bottom() {
+                    self.mark_read();
+                } else {
+                    self.update_title();
+                }
+                // Bubbles that came into view while hidden didn't count; starting over
+                // makes the observer report everything that is on screen now.
+                self.seen.reset();
+                true
+            }
+            Msg::JumpToUnread => {
+                if let Some(id) = self.first_unread() {
+                    self.viewport.scroll_to(&self.timeline.keys(), id);
+                }
+                false
+            }
+            Msg::MarkAllRead => {
+                self.read.divider_after = None;
+                self.mark_read();
+                true
+            }
+            Msg::Scrolled => {
+                let scrolled = match self.viewport.scrolled() {
+                    Some(scrolled) => scrolled,
+                    None => return false,
                 };
+                let mut changed = scrolled.changed;
+                if scrolled.at_bottom {
+                    let behind = self.read.behind(self.timeline.newest_id());
+                    if (behind || !self.read.unread_mentions.is_empty()) && !document_hidden() {
+                        self.mark_read();
+                        changed = true;
+                    }
+                    if self.timeline.len() > MAX_BUFFERED_MESSAGES {
+                        self.viewport.follow();
+                        self.evict_old_messages();
+                        changed = true;
+                    }
+                }
+                if scrolled.near_top {
+                    self.load_older();
+                }
+                changed || self.viewport.moved(&self.timeline.keys())
+            }
+            Msg::AckTimeout(client_id) => {
+                // A resend restarts the clock, so only the latest timeout counts.
+                let overdue = self.outbox.get(&client_id).and_then(|e| e.sent_at).is_some_and(|sent_at| {
+                    js_sys::Date::now() - sent_at >= ACK_TIMEOUT_MS as f64
+                });
+                let failed = overdue && self.outbox.fail(&client_id);
+                if failed {
+                    self.persist_outbox();
+                }
+                failed
+            }
+            Msg::RetryMessage(client_id) => {
+                if self.outbox.retry(&client_id) {
+                    self.transmit(ctx, &client_id);
+                    self.persist_outbox();
+                }
+                true
+            }
+            Msg::Restored(Ok((store, snapshot))) => {
+                let old_first = timeline::oldest_id(self.timeline.messages());
+                // The cache fills the list until the server's history arrives. If that
+                // came first, the cache only goes in behind it, and only without a gap.
+                if self.timeline.restore(snapshot.messages) {
+                    if let Some(first) = old_first {
+                        self.viewport.anchor();
+                        self.viewport.shift_past(&self.timeline.keys(), first);
+                    }
+                }
+                let restored = self.outbox.restore(snapshot.outbox);
+                for entry in &restored {
+                    self.timeline.push_pending(entry.pending_copy(&self.username));
+                }
+                self.flush(ctx, restored.into_iter().map(|e| e.client_id).collect());
+                self.store = Some(store);
+                self.persist_outbox();
+                if old_first.is_none() {
+                    self.viewport.follow();
+                    self.read.place_divider(self.timeline.messages(), &self.username);
+                }
+                true
+            }
+            Msg::Restored(Err(e)) => {
+                log::debug!("offline store unavailable: {}", e);
+                false
+            }
+            Msg::SaveCache => {
+                self.cache_save = None;
+                if let Some(store) = &self.store {
+                    store.save_messages(self.timeline.messages());
+                }
+                false
+            }
+            Msg::ScrollToBottom => {
+                self.viewport.follow();
+                self.viewport.clear_below();
+                self.evict_old_messages();
+                true
+            }
+            Msg::MessageSeen(id) => {
+                let from_other = self
+                    .timeline
+                    .messages()
+                    .iter()
+                    .any(|m| m.id == id && m.from != self.username);
+                if self.settings.read_receipts && from_other && !document_hidden() {
+                    if let Some(receipt) = self.receipts.send(id) {
+                        self.send(receipt);
+                    }
+                }
                 false
             }
+            Msg::NotificationPermission(granted) => {
+                self.notifications_blocked = !granted;
+                if !granted {
+                    self.settings.notifications = NotifyMode::Off;
+                    self.settings.save();
+                }
+                true
+            }
+        }
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, _first_render: bool) {
+        // Real heights or a new scroll position can change which rows belong on
+        // screen; go round again until they settle.
+        if self.viewport.rendered(&self.timeline.keys(), &mut self.reveal) {
+            ctx.link().send_message(Msg::Scrolled);
+        }
+        if let Some(list) = self.viewport.node().cast::<Element>() {
+            self.seen.observe(&list);
         }
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
-        let submit = ctx.link().callback(|_| Msg::SubmitMessage);
-        let (user, _) = ctx.link().context::<User>(Callback::noop()).expect("context to be set");
-        let current_username = user.username.borrow().clone();
+        let online_count = self
+            .users
+            .iter()
+            .filter(|u| u.presence != Presence::Offline)
+            .count();
+        let own_avatar = self
+            .users
+            .iter()
+            .find(|u| u.name == self.username)
+            .map_or_else(|| avatar::generated(&self.username), |u| u.avatar.clone());
+        let window = self.viewport.window(&self.timeline.keys());
+        let new_since_divider = self.first_unread().map(|first| {
+            self.timeline
+                .messages()
+                .iter()
+                .filter(|m| m.id >= first && m.from != self.username)
+                .count()
+        });
+        let own_role = self.own_role();
+        let features = self.features();
 
         html! {
             <div class="flex flex-col md:flex-row h-screen bg-gray-100 dark:bg-gray-900 transition-colors duration-200">
                 <div class="flex-none w-full md:w-80 h-auto md:h-screen bg-white dark:bg-gray-800 shadow-lg overflow-y-auto">
-                    <div class="sticky top-0 bg-violet-600 dark:bg-violet-800 text-white p-4 font-bold text-lg flex justify-between items-center">
-                        <div class="flex items-center">
-                            <svg class="w-6 h-6 mr-2" fill="currentColor" viewBox="0 0 20 20" xmlns="http://www.w3.org/2000/svg">
-                                <path d="M13 6a3 3 0 11-6 0 3 3 0 016 0zM18 8a2 2 0 11-4 0 2 2 0 014 0zM14 15a4 4 0 00-8 0v3h8v-3zM6 8a2 2 0 11-4 0 2 2 0 014 0zM16 18v-3a5.972 5.972 0 00-.75-2.906A3.005 3.005 0 0119 15v3h-3zM4.75 12.094A5.973 5.973 0 004 15v3H1v-3a3 3 0 013.75-2.906z"></path>
-                            </svg>
-                            {format!("Online Users ({})", self.users.len())}
-                        </div>
-                        <ThemeToggle />
-                    </div>
-                    <div class="p-3 space-y-3">
-                    {
-                        if self.users.is_empty() {
-                            html! {
-                                <div class="flex items-center justify-center h-20 text-gray-500 dark:text-gray-400 italic">
-                                    {"No users online"}
-                                </div>
-                            }
-                        } else {
-                            html! {}
-                        }
-                    }
-                    {
-                        self.users.clone().iter().map(|u| {
-                            let is_current_user = u.name == current_username;
-                            html!{
-                                <div class={format!("flex items-center p-3 rounded-lg {} {}", 
-                                    if is_current_user { 
-                                        "bg-violet-100 dark:bg-violet-900 border-l-4 border-violet-500" 
-                                    } else { 
-                                        "bg-white dark:bg-gray-800 hover:bg-gray-50 dark:hover:bg-gray-700" 
-                                    },
-                                    "transition-all duration-200 transform hover:translate-x-1 shadow-sm"
-                                )}>
-                                    <div class="relative">
-                                        <img class="w-12 h-12 rounded-full border-2 border-gray-200 dark:border-gray-600" src={u.avatar.clone()} alt="avatar"/>
-                                        <div class="absolute bottom-0 right-0 w-3.5 h-3.5 bg-green-500 border-2 border-white dark:border-gray-700 rounded-full"></div>
-                                    </div>
-                                    <div class="flex-grow ml-3">
-                                        <div class="flex justify-between items-center">
-                                            <div class={format!("font-medium {}", 
-                                                if is_current_user { 
-                                                    "text-violet-700 dark:text-violet-300" 
-                                                } else { 
-                                                    "text-gray-700 dark:text-gray-300" 
-                                                }
-                                            )}>
-                                                {u.name.clone()}{if is_current_user { " (You)" } else { "" }}
-                                            </div>
-                                        </div>
-                                        <div class="text-xs text-gray-500 dark:text-gray-400">
-                                            {"Active now"}
-                                        </div>
-                                    </div>
-                                </div>
-                            }
-                        }).collect::<Html>()
-                    }
-                    </div>
+                    <UserListHeader
+                        online={online_count}
+                        presence={self.availability.reported}
+                        dnd={self.availability.chosen == Presence::Dnd}
+                        ontogglednd={self.callbacks.toggle_dnd.clone()}
+                    />
+                    <UserList
+                        users={self.users.clone()}
+                        current_user={self.username.clone()}
+                        {own_role}
+                        tick={self.tick}
+                        onselect={self.callbacks.show_profile.clone()}
+                        onmoderate={self.callbacks.moderate.clone()}
+                    />
                 </div>
-                
+
                 <div class="grow h-screen flex flex-col bg-gray-50 dark:bg-gray-900">
-                    <div class="w-full h-16 border-b border-gray-200 dark:border-gray-700 bg-white dark:bg-gray-800 shadow-sm flex items-center px-5">
-                        <div class="flex items-center">
-                            <svg class="w-6 h-6 text-violet-600 dark:text-violet-400" fill="currentColor" viewBox="0 0 20 20" xmlns="http://www.w3.org/2000/svg">
-                                <path fill-rule="evenodd" d="M18 10c0 3.866-3.582 7-8 7a8.841 8.841 0 01-4.083-.98L2 17l1.338-3.123C2.493 12.767 2 11.434 2 10c0-3.866 3.582-7 8-7s8 3.134 8 7zM7 9H5v2h2V9zm8 0h-2v2h2V9zM9 9h2v2H9V9z" clip-rule="evenodd"></path>
-                            </svg>
-                            <h1 class="ml-2 text-xl font-semibold text-gray-800 dark:text-gray-100">{"YewChat Room"}</h1>
-                        </div>
-                    </div>
-                    <div class="w-full grow overflow-auto p-6 space-y-4">
-                        {
-                            if self.messages.is_empty() {
-                                html! {
-                                    <div class="flex flex-col items-center justify-center h-full text-gray-500 dark:text-gray-400">
-                                        <svg class="w-16 h-16 mb-4 text-gray-300 dark:text-gray-600" fill="currentColor" viewBox="0 0 20 20" xmlns="http://www.w3.org/2000/svg">
-                                            <path fill-rule="evenodd" d="M18 10c0 3.866-3.582 7-8 7a8.841 8.841 8.841 0 01-4.083-.98L2 17l1.338-3.123C2.493 12.767 2 11.434 2 10c0-3.866 3.582-7 8-7s8 3.134 8 7zM7 9H5v2h2V9zm8 0h-2v2h2V9zM9 9h2v2H9V9z" clip-rule="evenodd"></path>
-                                        </svg>
-                                        <p class="text-lg">{"No messages yet"}</p>
-                                        <p class="text-sm mt-2">{"Start the conversation by sending a message below!"}</p>
-                                    </div>
-                                }
-                            } else {
-                                html! {}
-                            }
-                        }
-                        {
-                            self.messages.iter().map(|m| {
-                                let user_profile = self.users.iter().find(|u| u.name == m.from);
-                                let is_current_user = m.from == current_username;
-                                let avatar = user_profile.map_or_else(
-                                    || format!("https://avatars.dicebear.com/api/adventurer-neutral/{}.svg", m.from),
-                                    |u| u.avatar.clone()
-                                );
-
-                                html!{
-                                    <div class={format!("flex {}", if is_current_user { "justify-end" } else { "justify-start" })}>
-                                        <div class={format!("flex items-end max-w-[80%] md:max-w-[60%] {}", 
-                                            if is_current_user { "flex-row-reverse" } else { "flex-row" }
-                                        )}>
-                                            <img class={format!("w-8 h-8 rounded-full {}", if is_current_user { "ml-2" } else { "mr-2" })} 
-                                                src={avatar} alt="avatar"/>
-                                            <div class={format!("px-4 py-3 rounded-t-lg {} space-y-1 shadow-sm", 
-                                                if is_current_user { 
-                                                    "bg-violet-600 dark:bg-violet-700 text-white rounded-bl-lg rounded-br-none" 
-                                                } else { 
-                                                    "bg-white dark:bg-gray-700 text-gray-800 dark:text-gray-200 rounded-br-lg rounded-bl-none" 
-                                                }
-                                            )}>
-                                                <div class="text-xs font-medium">
-                                                    {if is_current_user { "You" } else { &m.from }}
-                                                </div>
-                                                <div class={if is_current_user { "text-violet-100" } else { "text-gray-700 dark:text-gray-300" }}>
-                                                    if m.message.ends_with(".gif") {
-                                                        <img class="mt-2 rounded-lg max-w-full" src={m.message.clone()}/>
-                                                    } else {
-                                                        <p class="break-words">{m.message.clone()}</p>
-                                                    }
-                                                </div>
-                                                <div class={format!("text-[10px] {}", 
-                                                    if is_current_user { "text-violet-200" } else { "text-gray-400 dark:text-gray-500" }
-                                                )}>
-                                                    {"just now"}
-                                                </div>
-                                            </div>
-                                        </div>
-                                    </div>
-                                }
-                            }).collect::<Html>()
-                        }
-                    </div>
-                    <div class="w-full bg-white dark:bg-gray-800 border-t border-gray-200 dark:border-gray-700 p-3 flex items-center">
-                        <input 
-                            ref={self.chat_input.clone()} 
-                            type="text" 
-                            placeholder="Type a message..." 
-                            class="block w-full py-3 px-4 bg-gray-100 dark:bg-gray-700 rounded-full outline-none focus:ring-2 focus:ring-violet-500 focus:bg-white dark:focus:bg-gray-600 transition-all text-gray-800 dark:text-gray-200 placeholder-gray-500 dark:placeholder-gray-400" 
-                            name="message" 
-                            required=true 
-                        />
-                        <button 
-                            onclick={submit} 
-                            class="ml-3 p-3 bg-violet-600 hover:bg-violet-700 dark:bg-violet-700 dark:hover:bg-violet-800 transition-colors w-12 h-12 rounded-full flex justify-center items-center text-white shadow-lg hover:shadow-violet-300/50 dark:hover:shadow-violet-900/50"
-                        >
-                            <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="w-6 h-6 fill-current">
-                                <path d="M0 0h24v24H0z" fill="none"></path><path d="M2.01 21L23 12 2.01 3 2 10l15 2-15 2z"></path>
-                            </svg>
-                        </button>
-                    </div>
+                    <RoomHeader
+                        server_name={self.hello.as_ref().map(|hello| hello.server_name.clone())}
+                        moderator={own_role.is_privileged()}
+                        onsettopic={self.callbacks.set_topic.clone()}
+                        searchable={features.search}
+                        query={self.search.as_ref().map(|s| s.query().to_string()).unwrap_or_default()}
+                        onsearch={self.callbacks.search.clone()}
+                        onclosesearch={self.callbacks.close_search.clone()}
+                        pin_count={self.pins.list().len()}
+                        pins_open={self.pins.open}
+                        ontogglepins={self.callbacks.toggle_pins.clone()}
+                        mentions={self.read.unread_mentions.len()}
+                        onjumpmention={self.callbacks.jump_to_mention.clone()}
+                        status={self.profile.status()}
+                        oneditprofile={self.callbacks.edit_profile.clone()}
+                        onopensettings={self.callbacks.open_settings.clone()}
+                    />
+                    <ConnectionBanner />
+                    <Notifier />
+                    <MessageList
+                        list_ref={self.viewport.node()}
+                        rows={self.rows(window.start..window.end)}
+                        pending={self.pending_rows(&own_avatar)}
+                        notices={self.notices.clone()}
+                        empty={self.timeline.is_empty() && self.notices.is_empty()}
+                        pad_top={window.pad_top}
+                        pad_bottom={window.pad_bottom}
+                        unread={new_since_divider}
+                        new_below={self.viewport.new_below()}
+                        onscroll={self.callbacks.scrolled.clone()}
+                        onjumpunread={self.callbacks.jump_to_unread.clone()}
+                        onmarkread={self.callbacks.mark_all_read.clone()}
+                        onscrollbottom={self.callbacks.scroll_to_bottom.clone()}
+                        onshowprofile={self.callbacks.show_profile.clone()}
+                        onretry={self.callbacks.retry_message.clone()}
+                        ontogglepin={self.callbacks.toggle_pin.clone()}
+                        ondelete={self.callbacks.delete_message.clone()}
+                        ondismissnotice={self.callbacks.dismiss_notice.clone()}
+                    />
+                    <Composer
+                        input_ref={self.chat_input.clone()}
+                        candidates={self.mention_candidates()}
+                        commands={self.completions.commands(&self.commands)}
+                        selected={self.completions.selected}
+                        editing={self.editing.is_some()}
+                        length={self.composer_length.get()}
+                        max_length={self.max_message_length}
+                        oninput={self.callbacks.composer_input.clone()}
+                        onkeydown={self.callbacks.composer_keydown.clone()}
+                        onsubmit={self.callbacks.submit.clone()}
+                        onselectmention={self.callbacks.complete_mention.clone()}
+                        onselectcommand={self.callbacks.complete_command.clone()}
+                        oncanceledit={self.callbacks.cancel_edit.clone()}
+                    />
                 </div>
+                if self.pins.open {
+                    <PinnedDrawer
+                        pins={self.pins.list()}
+                        onselect={self.callbacks.jump_to_message.clone()}
+                        onunpin={self.callbacks.toggle_pin.clone()}
+                        onclose={self.callbacks.toggle_pins.clone()}
+                        me={self.username.clone()}
+                        privileged={own_role.is_privileged()}
+                    />
+                }
+                if let Some(search) = &self.search {
+                    <SearchPanel
+                        query={search.query().to_string()}
+                        results={search.results()}
+                        searching={search.searching()}
+                        onselect={self.callbacks.jump_to_message.clone()}
+                        onclose={self.callbacks.close_search.clone()}
+                    />
+                }
+                if self.editing_profile {
+                    <ProfileEditor
+                        profile={self.profile.clone()}
+                        avatar={own_avatar}
+                        avatar_error={self.avatar_error.clone()}
+                        uploads={features.uploads}
+                        onupload={ctx.link().callback(Msg::UploadAvatar)}
+                        onremoveavatar={ctx.link().callback(|_| Msg::RemoveAvatar)}
+                        onsave={ctx.link().callback(Msg::SaveProfile)}
+                        oncancel={ctx.link().callback(|_| Msg::CloseProfileEditor)}
+                    />
+                }
+                if self.show_settings {
+                    <SettingsPanel
+                        settings={self.settings.clone()}
+                        notifications_blocked={self.notifications_blocked}
+                        onchange={ctx.link().callback(Msg::UpdateSettings)}
+                        onclose={ctx.link().callback(|_| Msg::CloseSettings)}
+                    />
+                }
+                if let Some(name) = &self.shown_profile {
+                    {self.view_profile_card(ctx, name)}
+                }
+                if let Some(reason) = &self.removed {
+                    <LeftRoom reason={reason.clone()} />
+                }
             </div>
         }
     }
@@ -0,0 +1,456 @@
+use yew::prelude::*;
+
+use crate::components::topic::MAX_TOPIC_LENGTH;
+
+/// What a command takes after its name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arg {
+    None,
+    Optional(&'static str),
+    Required(&'static str),
+}
+
+/// What `Chat` should do once a command has run.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    /// Send this as a chat message.
+    Send(String),
+    /// Change our nickname.
+    Rename(String),
+    /// Set the room's topic; empty clears it.
+    SetTopic(String),
+    /// Empty the message list.
+    Clear,
+    /// Ask the server for the moderation log.
+    Audit,
+    /// List the commands.
+    Help,
+    /// Tell the user something; nothing goes to the server.
+    Notice(String),
+}
+
+/// A `/name` the composer understands.
+pub trait Command {
+    fn name(&self) -> &'static str;
+    fn arg(&self) -> Arg;
+    /// One line on what it does.
+    fn help(&self) -> &'static str;
+    /// Runs with the trimmed text after the name; a `Required` one is never empty.
+    fn run(&self, arg: &str) -> Result<Action, String>;
+}
+
+fn usage(command: &dyn Command) -> String {
+    match command.arg() {
+        Arg::None => format!("/{}", command.name()),
+        Arg::Optional(arg) => format!("/{} [{}]", command.name(), arg),
+        Arg::Required(arg) => format!("/{} <{}>", command.name(), arg),
+    }
+}
+
+/// A command offered by the autocomplete popup.
+#[derive(Clone, PartialEq)]
+pub struct Suggestion {
+    pub name: String,
+    pub usage: String,
+    pub help: String,
+}
+
+/// Every command the composer knows, in the order `/help` lists them.
+#[derive(Default)]
+pub struct Registry {
+    commands: Vec<Box<dyn Command>>,
+}
+
+impl Registry {
+    /// The commands that ship with the chat.
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::default();
+        registry.register(Me);
+        registry.register(Nick);
+        registry.register(SetTopic);
+        registry.register(Join);
+        registry.register(Dm);
+        registry.register(Shrug);
+        registry.register(Clear);
+        registry.register(Audit);
+        registry.register(Help);
+        registry
+    }
+
+    /// Adds `command`, replacing any of the same name.
+    pub fn register(&mut self, command: impl Command + 'static) {
+        self.commands.retain(|c| c.name() != command.name());
+        self.commands.push(Box::new(command));
+    }
+
+    fn find(&self, name: &str) -> Option<&dyn Command> {
+        self.commands.iter().find(|c| c.name() == name).map(|c| c.as_ref())
+    }
+
+    /// Commands whose name starts with `prefix`.
+    pub fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        let prefix = prefix.to_lowercase();
+        self.commands
+            .iter()
+            .filter(|c| c.name().starts_with(&prefix))
+            .map(|c| Suggestion {
+                name: c.name().to_string(),
+                usage: usage(c.as_ref()),
+                help: c.help().to_string(),
+            })
+            .collect()
+    }
+
+    /// Runs `input` if it is a command. `None` means it is plain chat text; a leading
+    /// `//` sends the rest with a single slash.
+    pub fn run(&self, input: &str) -> Option<Result<Action, String>> {
+        if let Some(escaped) = input.strip_prefix("//") {
+            return Some(Ok(Action::Send(format!("/{}", escaped))));
+        }
+        let input = input.strip_prefix('/')?;
+        let (name, arg) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+        let arg = arg.trim();
+        let command = match self.find(&name.to_lowercase()) {
+            Some(command) => command,
+            None => return Some(Err(format!("Unknown command /{}. Type /help for a list.", name))),
+        };
+        if let (Arg::Required(_), true) = (command.arg(), arg.is_empty()) {
+            return Some(Err(format!("Usage: {}", usage(command))));
+        }
+        Some(command.run(arg))
+    }
+
+    pub fn help(&self) -> String {
+        let mut lines: Vec<String> = self
+            .commands
+            .iter()
+            .map(|c| format!("{} — {}", usage(c.as_ref()), c.help()))
+            .collect();
+        lines.push("Start a message with // to send it with a leading slash.".into());
+        lines.join("\n")
+    }
+}
+
+/// The partial command name while the composer holds nothing but `/partial`.
+pub fn query(value: &str) -> Option<&str> {
+    value
+        .strip_prefix('/')
+        .filter(|rest| !rest.starts_with('/') && !rest.contains(char::is_whitespace))
+}
+
+/// The action in a `/me` message, which is sent as typed and shown as an action.
+pub fn action_text(message: &str) -> Option<&str> {
+    message.strip_prefix("/me ")
+}
+
+struct Me;
+
+impl Command for Me {
+    fn name(&self) -> &'static str {
+        "me"
+    }
+    fn arg(&self) -> Arg {
+        Arg::Required("action")
+    }
+    fn help(&self) -> &'static str {
+        "Say what you are doing, e.g. /me waves"
+    }
+    fn run(&self, arg: &str) -> Result<Action, String> {
+        Ok(Action::Send(format!("/me {}", arg)))
+    }
+}
+
+struct Nick;
+
+impl Command for Nick {
+    fn name(&self) -> &'static str {
+        "nick"
+    }
+    fn arg(&self) -> Arg {
+        Arg::Required("name")
+    }
+    fn help(&self) -> &'static str {
+        "Change your nickname"
+    }
+    fn run(&self, arg: &str) -> Result<Action, String> {
+        let name = arg.strip_prefix('@').unwrap_or(arg);
+        if name.is_empty() {
+            return Err("Usage: /nick <name>".into());
+        }
+        if name.contains(char::is_whitespace) {
+            return Err("Nicknames can't contain spaces.".into());
+        }
+        Ok(Action::Rename(name.to_string()))
+    }
+}
+
+struct SetTopic;
+
+impl Command for SetTopic {
+    fn name(&self) -> &'static str {
+        "topic"
+    }
+    fn arg(&self) -> Arg {
+        Arg::Optional("text")
+    }
+    fn help(&self) -> &'static str {
+        "Set the room's topic, or clear it (moderators only)"
+    }
+    fn run(&self, arg: &str) -> Result<Action, String> {
+        if arg.chars().count() > MAX_TOPIC_LENGTH {
+            return Err(format!("Topics can be at most {} characters.", MAX_TOPIC_LENGTH));
+        }
+        Ok(Action::SetTopic(arg.to_string()))
+    }
+}
+
+struct Join;
+
+impl Command for Join {
+    fn name(&self) -> &'static str {
+        "join"
+    }
+    fn arg(&self) -> Arg {
+        Arg::Required("#room")
+    }
+    fn help(&self) -> &'static str {
+        "Switch to another room"
+    }
+    fn run(&self, arg: &str) -> Result<Action, String> {
+        match arg.strip_prefix('#') {
+            Some("general") => Ok(Action::Notice("You are already in #general.".into())),
+            Some(room) if !room.is_empty() => Err(format!("There is no #{} yet; everyone is in #general.", room)),
+            _ => Err("Room names start with #, e.g. /join #general".into()),
+        }
+    }
+}
+
+struct Dm;
+
+impl Command for Dm {
+    fn name(&self) -> &'static str {
+        "dm"
+    }
+    fn arg(&self) -> Arg {
+        Arg::Required("user")
+    }
+    fn help(&self) -> &'static str {
+        "Start a direct conversation"
+    }
+    fn run(&self, arg: &str) -> Result<Action, String> {
+        let user = arg.strip_prefix('@').unwrap_or(arg);
+        Err(format!("Direct messages aren't available yet; try @{} in #general.", user))
+    }
+}
+
+struct Shrug;
+
+impl Command for Shrug {
+    fn name(&self) -> &'static str {
+        "shrug"
+    }
+    fn arg(&self) -> Arg {
+        Arg::Optional("message")
+    }
+    fn help(&self) -> &'static str {
+        "Send a message with ¯\\_(ツ)_/¯ on the end"
+    }
+    fn run(&self, arg: &str) -> Result<Action, String> {
+        Ok(Action::Send(format!("{} ¯\\_(ツ)_/¯", arg).trim_start().to_string()))
+    }
+}
+
+struct Clear;
+
+impl Command for Clear {
+    fn name(&self) -> &'static str {
+        "clear"
+    }
+    fn arg(&self) -> Arg {
+        Arg::None
+    }
+    fn help(&self) -> &'static str {
+        "Clear the messages on your screen"
+    }
+    fn run(&self, _arg: &str) -> Result<Action, String> {
+        Ok(Action::Clear)
+    }
+}
+
+struct Audit;
+
+impl Command for Audit {
+    fn name(&self) -> &'static str {
+        "audit"
+    }
+    fn arg(&self) -> Arg {
+        Arg::None
+    }
+    fn help(&self) -> &'static str {
+        "Show recent moderator actions (moderators only)"
+    }
+    fn run(&self, _arg: &str) -> Result<Action, String> {
+        Ok(Action::Audit)
+    }
+}
+
+struct Help;
+
+impl Command for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+    fn arg(&self) -> Arg {
+        Arg::None
+    }
+    fn help(&self) -> &'static str {
+        "List these commands"
+    }
+    fn run(&self, _arg: &str) -> Result<Action, String> {
+        Ok(Action::Help)
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct CommandPopupProps {
+    pub suggestions: Vec<Suggestion>,
+    pub selected: usize,
+    pub onselect: Callback<String>,
+}
+
+#[function_component(CommandPopup)]
+pub fn command_popup(props: &CommandPopupProps) -> Html {
+    html! {
+        <div class="absolute bottom-full left-3 mb-2 w-80 py-1 bg-white dark:bg-gray-700 rounded-lg shadow-xl border border-gray-200 dark:border-gray-600">
+            {
+                props.suggestions.iter().enumerate().map(|(i, suggestion)| {
+                    let onmousedown = {
+                        let name = suggestion.name.clone();
+                        let onselect = props.onselect.clone();
+                        // `mousedown` so the input doesn't lose focus before we act.
+                        Callback::from(move |e: MouseEvent| {
+                            e.prevent_default();
+                            onselect.emit(name.clone());
+                        })
+                    };
+                    let selected = i == props.selected;
+                    html! {
+                        <div
+                            {onmousedown}
+                            class={classes!(
+                                "px-3", "py-2", "text-sm", "cursor-pointer",
+                                if selected {
+                                    "bg-violet-600 text-white"
+                                } else {
+                                    "text-gray-700 dark:text-gray-200 hover:bg-gray-100 dark:hover:bg-gray-600"
+                                }
+                            )}
+                        >
+                            <div class="font-mono">{&suggestion.usage}</div>
+                            <div class={classes!("text-xs", if selected { "text-violet-100" } else { "text-gray-500 dark:text-gray-400" })}>
+                                {&suggestion.help}
+                            </div>
+                        </div>
+                    }
+                }).collect::<Html>()
+            }
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(input: &str) -> Option<Result<Action, String>> {
+        Registry::with_defaults().run(input)
+    }
+
+    #[test]
+    fn plain_text_is_not_a_command() {
+        assert_eq!(run("hello /nick"), None);
+    }
+
+    #[test]
+    fn a_double_slash_sends_the_rest_with_one_slash() {
+        assert_eq!(run("//nick me"), Some(Ok(Action::Send("/nick me".into()))));
+        assert_eq!(run("//"), Some(Ok(Action::Send("/".into()))));
+    }
+
+    #[test]
+    fn unknown_commands_are_errors() {
+        assert_eq!(
+            run("/frobnicate now"),
+            Some(Err("Unknown command /frobnicate. Type /help for a list.".into()))
+        );
+    }
+
+    #[test]
+    fn a_missing_required_argument_shows_the_usage() {
+        assert_eq!(run("/me"), Some(Err("Usage: /me <action>".into())));
+        assert_eq!(run("/nick   "), Some(Err("Usage: /nick <name>".into())));
+    }
+
+    #[test]
+    fn names_are_matched_regardless_of_case() {
+        assert_eq!(run("/NICK bob"), Some(Ok(Action::Rename("bob".into()))));
+        assert_eq!(run("/Clear"), Some(Ok(Action::Clear)));
+    }
+
+    #[test]
+    fn nick_strips_one_at_sign_and_rejects_an_empty_name() {
+        assert_eq!(run("/nick @bob"), Some(Ok(Action::Rename("bob".into()))));
+        assert_eq!(run("/nick @"), Some(Err("Usage: /nick <name>".into())));
+        assert_eq!(run("/nick @ bob"), Some(Err("Nicknames can't contain spaces.".into())));
+    }
+
+    #[test]
+    fn shrug_works_with_and_without_text() {
+        assert_eq!(run("/shrug"), Some(Ok(Action::Send("¯\\_(ツ)_/¯".into()))));
+        assert_eq!(run("/shrug  oh well "), Some(Ok(Action::Send("oh well ¯\\_(ツ)_/¯".into()))));
+    }
+
+    #[test]
+    fn register_replaces_a_command_of_the_same_name() {
+        struct Quiet;
+
+        impl Command for Quiet {
+            fn name(&self) -> &'static str {
+                "shrug"
+            }
+            fn arg(&self) -> Arg {
+                Arg::None
+            }
+            fn help(&self) -> &'static str {
+                "Shrug silently"
+            }
+            fn run(&self, _arg: &str) -> Result<Action, String> {
+                Ok(Action::Notice("*shrugs*".into()))
+            }
+        }
+
+        let mut registry = Registry::with_defaults();
+        let count = registry.suggestions("").len();
+        registry.register(Quiet);
+        assert_eq!(registry.suggestions("").len(), count);
+        assert_eq!(registry.run("/shrug"), Some(Ok(Action::Notice("*shrugs*".into()))));
+        let help: Vec<String> = registry.suggestions("sh").into_iter().map(|s| s.help).collect();
+        assert_eq!(help, ["Shrug silently"]);
+    }
+
+    #[test]
+    fn query_is_the_partial_name_being_typed() {
+        assert_eq!(query("/"), Some(""));
+        assert_eq!(query("/ni"), Some("ni"));
+        assert_eq!(query("/nick bob"), None);
+        assert_eq!(query("//ni"), None);
+        assert_eq!(query("nick"), None);
+    }
+
+    #[test]
+    fn action_text_is_what_follows_me() {
+        assert_eq!(action_text("/me waves"), Some("waves"));
+        assert_eq!(action_text("/meet me"), None);
+        assert_eq!(action_text("hello"), None);
+    }
+}
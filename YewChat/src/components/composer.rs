@@ -0,0 +1,236 @@
+use web_sys::HtmlTextAreaElement;
+use yew::prelude::*;
+
+use crate::components::commands::{self, CommandPopup, Registry, Suggestion};
+use crate::components::mentions::{self, MentionPopup, MAX_SUGGESTIONS};
+
+/// How many sent messages Ctrl+Up can reach back through.
+const MAX_INPUT_HISTORY: usize = 50;
+/// The longest message the server takes, in characters, unless it says otherwise.
+pub const DEFAULT_MAX_LENGTH: usize = 2000;
+/// How much of the limit can be used before the counter turns red.
+const NEAR_LIMIT: f64 = 0.9;
+
+/// Grows the composer with its text, up to the height its CSS allows.
+pub fn fit(textarea: &HtmlTextAreaElement) {
+    let _ = textarea.set_attribute("style", "height: auto");
+    let _ = textarea.set_attribute("style", &format!("height: {}px", textarea.scroll_height()));
+}
+
+/// What was sent from the composer, for Ctrl+Up/Down.
+#[derive(Default)]
+pub struct InputHistory {
+    entries: Vec<String>,
+    /// The entry on show while browsing.
+    position: Option<usize>,
+    /// What the composer held before browsing started.
+    draft: String,
+}
+
+impl InputHistory {
+    pub fn push(&mut self, text: String) {
+        self.position = None;
+        if self.entries.last() != Some(&text) {
+            self.entries.push(text);
+        }
+        if self.entries.len() > MAX_INPUT_HISTORY {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Steps to an older entry; `current` is kept to come back to. Returns the text
+    /// to show, or `None` at the oldest.
+    pub fn back(&mut self, current: &str) -> Option<String> {
+        let position = match self.position {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len() - 1
+            }
+            Some(0) => return None,
+            Some(position) => position - 1,
+        };
+        self.position = Some(position);
+        Some(self.entries[position].clone())
+    }
+
+    /// Steps to a newer entry, and past the newest back to the draft.
+    pub fn forward(&mut self) -> Option<String> {
+        let next = self.position? + 1;
+        if next < self.entries.len() {
+            self.position = Some(next);
+            Some(self.entries[next].clone())
+        } else {
+            self.position = None;
+            Some(std::mem::take(&mut self.draft))
+        }
+    }
+}
+
+/// Which autocomplete popup is open, for what, and which entry is highlighted.
+#[derive(Default)]
+pub struct Completions {
+    /// The `@partial` being typed, while the mention popup is open.
+    mention: Option<String>,
+    /// The `/partial` being typed, while the command popup is open.
+    command: Option<String>,
+    /// The highlighted entry of whichever popup is open.
+    pub selected: usize,
+}
+
+impl Completions {
+    /// Opens, updates or closes the popups to match the composer holding `value` with
+    /// the caret at `caret`; returns whether anything changed.
+    pub fn update(&mut self, value: &str, caret: Option<u32>) -> bool {
+        let mention = caret
+            .and_then(|caret| mentions::query_at(value, caret))
+            .map(|(_, query)| query);
+        let command = commands::query(value).map(String::from);
+        if mention == self.mention && command == self.command {
+            return false;
+        }
+        self.mention = mention;
+        self.command = command;
+        self.selected = 0;
+        true
+    }
+
+    pub fn close(&mut self) {
+        self.mention = None;
+        self.command = None;
+    }
+
+    pub fn close_mention(&mut self) {
+        self.mention = None;
+    }
+
+    pub fn close_command(&mut self) {
+        self.command = None;
+    }
+
+    /// Names out of `names` matching the `@partial` being typed, other than `me`.
+    pub fn mentions<'a>(&self, names: impl IntoIterator<Item = &'a String>, me: &str) -> Vec<String> {
+        let query = match &self.mention {
+            Some(query) => query.to_lowercase(),
+            None => return vec![],
+        };
+        let mut candidates: Vec<String> = vec![];
+        for name in names {
+            if name != me && name.to_lowercase().starts_with(&query) && !candidates.contains(name) {
+                candidates.push(name.clone());
+            }
+        }
+        candidates.truncate(MAX_SUGGESTIONS);
+        candidates
+    }
+
+    /// Commands matching the `/partial` being typed.
+    pub fn commands(&self, registry: &Registry) -> Vec<Suggestion> {
+        match &self.command {
+            Some(query) => registry.suggestions(query),
+            None => vec![],
+        }
+    }
+
+    /// Moves the highlight down one of `count` entries, wrapping round.
+    pub fn next(&mut self, count: usize) {
+        self.selected = (self.selected + 1) % count;
+    }
+
+    pub fn previous(&mut self, count: usize) {
+        self.selected = (self.selected + count - 1) % count;
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ComposerProps {
+    pub input_ref: NodeRef,
+    /// Names offered by the `@mention` popup; empty hides it.
+    pub candidates: Vec<String>,
+    /// Commands offered by the `/command` popup; empty hides it.
+    pub commands: Vec<Suggestion>,
+    /// The highlighted entry of whichever popup is open.
+    pub selected: usize,
+    /// Whether the text replaces one of our messages rather than making a new one.
+    pub editing: bool,
+    /// Characters in the composer.
+    pub length: usize,
+    /// Characters a message may have; past this, sending is blocked.
+    pub max_length: usize,
+    pub oninput: Callback<()>,
+    pub onkeydown: Callback<KeyboardEvent>,
+    pub onsubmit: Callback<()>,
+    pub onselectmention: Callback<String>,
+    pub onselectcommand: Callback<String>,
+    pub oncanceledit: Callback<()>,
+}
+
+#[function_component(Composer)]
+pub fn composer(props: &ComposerProps) -> Html {
+    let oninput = {
+        let input_ref = props.input_ref.clone();
+        props.oninput.reform(move |_: InputEvent| {
+            if let Some(textarea) = input_ref.cast::<HtmlTextAreaElement>() {
+                fit(&textarea);
+            }
+        })
+    };
+    let onclick = props.oninput.reform(|_: MouseEvent| ());
+    let onsubmit = props.onsubmit.reform(|_: MouseEvent| ());
+    let oncanceledit = props.oncanceledit.reform(|_: MouseEvent| ());
+    let too_long = props.length > props.max_length;
+    let near_limit = props.length as f64 >= props.max_length as f64 * NEAR_LIMIT;
+
+    html! {
+        <div class="relative w-full bg-white dark:bg-gray-800 border-t border-gray-200 dark:border-gray-700 p-3 flex items-end">
+            if !props.commands.is_empty() {
+                <CommandPopup
+                    suggestions={props.commands.clone()}
+                    selected={props.selected}
+                    onselect={props.onselectcommand.clone()}
+                />
+            } else if !props.candidates.is_empty() {
+                <MentionPopup
+                    candidates={props.candidates.clone()}
+                    selected={props.selected}
+                    onselect={props.onselectmention.clone()}
+                />
+            } else if props.editing {
+                <div class="absolute bottom-full left-3 mb-2 flex items-center px-3 py-1 text-xs font-medium rounded-full shadow-md bg-violet-100 dark:bg-violet-900 text-violet-800 dark:text-violet-200">
+                    {"Editing message · Esc to"}
+                    <button onclick={oncanceledit} class="ml-1 underline hover:no-underline">{"cancel"}</button>
+                </div>
+            }
+            <textarea
+                ref={props.input_ref.clone()}
+                {oninput}
+                onkeydown={props.onkeydown.clone()}
+                {onclick}
+                rows="1"
+                placeholder="Type a message..."
+                class="block w-full max-h-40 py-3 px-4 resize-none overflow-y-auto bg-gray-100 dark:bg-gray-700 rounded-3xl outline-none focus:ring-2 focus:ring-violet-500 focus:bg-white dark:focus:bg-gray-600 transition-all text-gray-800 dark:text-gray-200 placeholder-gray-500 dark:placeholder-gray-400"
+                name="message"
+            />
+            if props.length > 0 {
+                <span
+                    class={classes!(
+                        "absolute", "right-20", "bottom-0.5", "text-[10px]", "tabular-nums", "pointer-events-none",
+                        if near_limit { "font-semibold text-red-500" } else { "text-gray-400" }
+                    )}
+                    title={if too_long { "Too long to send" } else { "Characters" }}
+                >
+                    {format!("{}/{}", props.length, props.max_length)}
+                </span>
+            }
+            <button
+                onclick={onsubmit}
+                disabled={too_long}
+                class="ml-3 p-3 disabled:opacity-50 disabled:cursor-not-allowed flex-none bg-violet-600 hover:bg-violet-700 dark:bg-violet-700 dark:hover:bg-violet-800 transition-colors w-12 h-12 rounded-full flex justify-center items-center text-white shadow-lg hover:shadow-violet-300/50 dark:hover:shadow-violet-900/50"
+            >
+                <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="w-6 h-6 fill-current">
+                    <path d="M0 0h24v24H0z" fill="none"></path><path d="M2.01 21L23 12 2.01 3 2 10l15 2-15 2z"></path>
+                </svg>
+            </button>
+        </div>
+    }
+}
@@ -0,0 +1,62 @@
+use std::rc::Rc;
+
+use yew::prelude::*;
+use yew_agent::use_bridge;
+use yew_router::prelude::*;
+
+use crate::services::event_bus::{ConnectionState, Event, EventBus, Request, Topic};
+use crate::Route;
+
+/// A strip under the room header while the chat server is unreachable.
+#[function_component(ConnectionBanner)]
+pub fn connection_banner() -> Html {
+    let state = use_state(|| None::<ConnectionState>);
+    let bridge = {
+        let state = state.clone();
+        use_bridge::<EventBus, _>(move |event: Rc<Event>| {
+            if let Event::Connection(connection) = *event {
+                state.set(Some(connection));
+            }
+        })
+    };
+    use_effect_with_deps(
+        move |_| {
+            bridge.send(Request::Subscribe(vec![Topic::Connection]));
+            || ()
+        },
+        (),
+    );
+
+    let (text, class) = match *state {
+        Some(ConnectionState::Connecting) => ("Connecting to the chat server…", "bg-amber-100 dark:bg-amber-900/60 text-amber-800 dark:text-amber-200"),
+        Some(ConnectionState::Closed) => ("Disconnected from the chat server.", "bg-red-100 dark:bg-red-900/60 text-red-700 dark:text-red-200"),
+        _ => return html! {},
+    };
+    html! {
+        <div class={classes!("w-full", "px-5", "py-1.5", "text-xs", "font-medium", "text-center", class)}>
+            {text}
+        </div>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct LeftRoomProps {
+    /// Why we were thrown out.
+    pub reason: String,
+}
+
+/// Covers the chat once we are kicked or banned; we don't reconnect after that.
+#[function_component(LeftRoom)]
+pub fn left_room(props: &LeftRoomProps) -> Html {
+    html! {
+        <div class="fixed inset-0 z-50 flex items-center justify-center bg-gray-900/70">
+            <div class="max-w-sm mx-4 p-6 text-center bg-white dark:bg-gray-800 rounded-2xl shadow-2xl">
+                <h2 class="text-lg font-semibold text-gray-800 dark:text-gray-100">{"You have left the chat"}</h2>
+                <p class="mt-2 text-sm text-gray-600 dark:text-gray-300">{&props.reason}</p>
+                <Link<Route> to={Route::Login} classes="mt-5 inline-block px-5 py-2 bg-violet-600 text-white rounded-lg hover:bg-violet-700 transition">
+                    {"Back to the login page"}
+                </Link<Route>>
+            </div>
+        </div>
+    }
+}
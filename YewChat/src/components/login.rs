@@ -9,7 +9,8 @@ use crate::components::theme_toggle::ThemeToggle;
 
 #[function_component(Login)]
 pub fn login() -> Html {
-    let username = use_state(|| String::new());
+    let username = use_state(String::new);
+    let key = use_state(String::new);
     let user = use_context::<User>().expect("No context found.");
 
     let oninput = {
@@ -21,10 +22,22 @@ pub fn login() -> Html {
         })
     };
 
+    let onkeyinput = {
+        let key = key.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            key.set(input.value());
+        })
+    };
+
     let onclick = {
         let username = username.clone();
+        let key = key.clone();
         let user = user.clone();
-        Callback::from(move |_| *user.username.borrow_mut() = (*username).clone())
+        Callback::from(move |_| {
+            *user.username.borrow_mut() = (*username).clone();
+            *user.key.borrow_mut() = (*key).clone();
+        })
     };
 
     html! {
@@ -50,6 +63,15 @@ pub fn login() -> Html {
                             class="relative block w-full px-4 py-3 text-gray-900 border border-gray-300 rounded-lg focus:outline-none focus:ring-2 focus:ring-violet-500 focus:border-transparent dark:bg-gray-700 dark:border-gray-600 dark:text-white dark:placeholder-gray-400" 
                         />
                     </div>
+                    <details class="text-sm text-gray-500 dark:text-gray-400">
+                        <summary class="cursor-pointer select-none">{"Moderator or admin?"}</summary>
+                        <input
+                            oninput={onkeyinput}
+                            type="password"
+                            placeholder="Role key"
+                            class="mt-2 block w-full px-4 py-2 text-gray-900 border border-gray-300 rounded-lg focus:outline-none focus:ring-2 focus:ring-violet-500 focus:border-transparent dark:bg-gray-700 dark:border-gray-600 dark:text-white dark:placeholder-gray-400"
+                        />
+                    </details>
                     
                     <div>
                         <Link<Route> to={Route::Chat}>
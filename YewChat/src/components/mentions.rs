@@ -0,0 +1,138 @@
+use yew::prelude::*;
+
+/// How many suggestions the autocomplete popup shows at most.
+pub const MAX_SUGGESTIONS: usize = 8;
+
+/// Whether `text` pings `name` with an `@name`.
+pub fn mentions(text: &str, name: &str) -> bool {
+    let needle = format!("@{}", name.to_lowercase());
+    let text = text.to_lowercase();
+    text.match_indices(&needle).any(|(i, _)| {
+        text[i + needle.len()..]
+            .chars()
+            .next()
+            .is_none_or(|c| !c.is_alphanumeric() && c != '_')
+    })
+}
+
+/// The names out of `known` that `text` mentions, each once.
+pub fn find_mentions<'a>(text: &str, known: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+    let mut found: Vec<String> = vec![];
+    for name in known {
+        if mentions(text, name) && !found.contains(name) {
+            found.push(name.clone());
+        }
+    }
+    found
+}
+
+/// The part of `value` before the caret; `caret` is a UTF-16 offset, as the DOM
+/// reports it.
+fn before_caret(value: &str, caret: u32) -> &str {
+    let mut units = 0;
+    for (i, c) in value.char_indices() {
+        if units >= caret as usize {
+            return &value[..i];
+        }
+        units += c.len_utf16();
+    }
+    value
+}
+
+/// If the caret sits right after an `@partial` word, returns the byte offset of the
+/// `@` and the partial name typed so far.
+pub fn query_at(value: &str, caret: u32) -> Option<(usize, String)> {
+    let before = before_caret(value, caret);
+    let at = before.rfind('@')?;
+    let starts_word = before[..at].chars().last().is_none_or(char::is_whitespace);
+    let query = &before[at + 1..];
+    if starts_word && !query.contains(char::is_whitespace) {
+        Some((at, query.to_string()))
+    } else {
+        None
+    }
+}
+
+/// Replaces the `@partial` under the caret with `@name `, returning the new value and
+/// the UTF-16 caret position just after the completion.
+pub fn complete(value: &str, caret: u32, name: &str) -> Option<(String, u32)> {
+    let (at, _) = query_at(value, caret)?;
+    let after = &value[before_caret(value, caret).len()..];
+    let head = format!("{}@{} ", &value[..at], name);
+    let caret = head.encode_utf16().count() as u32;
+    Some((format!("{}{}", head, after), caret))
+}
+
+/// `text` with every `@name` for the given mentions wrapped in a highlight.
+pub fn highlight(text: &str, mentioned: &[String]) -> Html {
+    let lower = text.to_lowercase();
+    let mut spans: Vec<(usize, usize)> = mentioned
+        .iter()
+        .flat_map(|name| {
+            let needle = format!("@{}", name.to_lowercase());
+            lower
+                .match_indices(&needle)
+                .map(|(i, m)| (i, i + m.len()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    spans.sort_unstable();
+
+    let mut parts: Vec<Html> = vec![];
+    let mut cursor = 0;
+    for (start, end) in spans {
+        // Lowercasing can change byte lengths for some scripts; skip anything that
+        // doesn't line up with the original text.
+        if start < cursor || !text.is_char_boundary(start) || !text.is_char_boundary(end) {
+            continue;
+        }
+        parts.push(html! { {&text[cursor..start]} });
+        parts.push(html! { <span class="font-semibold underline decoration-2">{&text[start..end]}</span> });
+        cursor = end;
+    }
+    parts.push(html! { {&text[cursor..]} });
+    parts.into_iter().collect()
+}
+
+#[derive(Properties, PartialEq)]
+pub struct MentionPopupProps {
+    pub candidates: Vec<String>,
+    pub selected: usize,
+    pub onselect: Callback<String>,
+}
+
+#[function_component(MentionPopup)]
+pub fn mention_popup(props: &MentionPopupProps) -> Html {
+    html! {
+        <div class="absolute bottom-full left-3 mb-2 w-64 py-1 bg-white dark:bg-gray-700 rounded-lg shadow-xl border border-gray-200 dark:border-gray-600">
+            {
+                props.candidates.iter().enumerate().map(|(i, name)| {
+                    let onmousedown = {
+                        let name = name.clone();
+                        let onselect = props.onselect.clone();
+                        // `mousedown` so the input doesn't lose focus before we act.
+                        Callback::from(move |e: MouseEvent| {
+                            e.prevent_default();
+                            onselect.emit(name.clone());
+                        })
+                    };
+                    html! {
+                        <div
+                            {onmousedown}
+                            class={classes!(
+                                "px-3", "py-2", "text-sm", "cursor-pointer",
+                                if i == props.selected {
+                                    "bg-violet-600 text-white"
+                                } else {
+                                    "text-gray-700 dark:text-gray-200 hover:bg-gray-100 dark:hover:bg-gray-600"
+                                }
+                            )}
+                        >
+                            {format!("@{}", name)}
+                        </div>
+                    }
+                }).collect::<Html>()
+            }
+        </div>
+    }
+}
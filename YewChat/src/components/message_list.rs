@@ -0,0 +1,643 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{Element, IntersectionObserver, IntersectionObserverEntry, IntersectionObserverInit};
+use yew::prelude::*;
+
+use crate::services::protocol::{MessageData, MsgTypes, Receipt, WebSocketMessage};
+use crate::components::commands;
+use crate::components::mentions;
+use crate::components::user_list::UserProfile;
+use crate::services::outbox::Delivery;
+use crate::services::search;
+
+/// Someone who has read up to a message.
+#[derive(Clone, PartialEq)]
+pub struct Reader {
+    pub name: String,
+    pub avatar: String,
+}
+
+/// Who has seen up to which message, and how far our own receipts have gone.
+#[derive(Default)]
+pub struct Receipts {
+    /// Newest message seen, by nick.
+    by_nick: HashMap<String, u64>,
+    /// ID of the newest message we have sent a receipt for.
+    last_sent: u64,
+}
+
+impl Receipts {
+    pub fn insert(&mut self, receipt: &Receipt) {
+        self.by_nick.insert(receipt.nick.clone(), receipt.id);
+    }
+
+    /// Replaces everything with what the server holds.
+    pub fn replace(&mut self, receipts: &[Receipt]) {
+        self.by_nick = receipts.iter().map(|r| (r.nick.clone(), r.id)).collect();
+    }
+
+    /// Readers whose receipt stops at `message`, excluding its author and `me`.
+    pub fn seen_by(&self, message: &MessageData, me: &str, users: &[UserProfile]) -> Vec<Reader> {
+        let mut readers: Vec<Reader> = vec![];
+        for (nick, id) in &self.by_nick {
+            if *id == message.id && *nick != message.from && nick != me {
+                if let Some(user) = users.iter().find(|u| u.name == *nick) {
+                    readers.push(Reader {
+                        name: user.name.clone(),
+                        avatar: user.avatar.clone(),
+                    });
+                }
+            }
+        }
+        readers.sort_by(|a, b| a.name.cmp(&b.name));
+        readers
+    }
+
+    /// The receipt to send for message `id`, unless one has gone out for it or
+    /// something newer.
+    pub fn send(&mut self, id: u64) -> Option<WebSocketMessage> {
+        if id <= self.last_sent {
+            return None;
+        }
+        self.last_sent = id;
+        Some(WebSocketMessage {
+            message_type: MsgTypes::Read,
+            data: Some(id.to_string()),
+            data_array: None,
+            client_id: None,
+        })
+    }
+}
+
+type SeenCallback = Closure<dyn FnMut(js_sys::Array)>;
+
+/// Reports bubbles scrolling into view, by message ID, for read receipts.
+pub struct SeenObserver {
+    onseen: Callback<u64>,
+    observer: Option<(IntersectionObserver, SeenCallback)>,
+}
+
+impl SeenObserver {
+    pub fn new(onseen: Callback<u64>) -> Self {
+        Self { onseen, observer: None }
+    }
+
+    /// Watches every bubble in `list`, starting the observer on first use.
+    pub fn observe(&mut self, list: &Element) {
+        if self.observer.is_none() {
+            let seen = self.onseen.clone();
+            let callback = Closure::wrap(Box::new(move |entries: js_sys::Array| {
+                for entry in entries.iter() {
+                    let entry: IntersectionObserverEntry = entry.unchecked_into();
+                    let id = entry
+                        .target()
+                        .get_attribute("data-message-id")
+                        .and_then(|id| id.parse().ok());
+                    if let (true, Some(id)) = (entry.is_intersecting(), id) {
+                        seen.emit(id);
+                    }
+                }
+            }) as Box<dyn FnMut(js_sys::Array)>);
+            let options = IntersectionObserverInit::new();
+            options.set_root(Some(list));
+            options.set_threshold(&JsValue::from(0.5));
+            match IntersectionObserver::new_with_options(callback.as_ref().unchecked_ref(), &options) {
+                Ok(observer) => self.observer = Some((observer, callback)),
+                Err(e) => {
+                    log::debug!("no IntersectionObserver: {:?}", e);
+                    return;
+                }
+            }
+        }
+        let (observer, _) = self.observer.as_ref().unwrap();
+        // Observing an element twice is a no-op, so just hand over every bubble.
+        if let Ok(bubbles) = list.query_selector_all("[data-message-id]") {
+            for i in 0..bubbles.length() {
+                if let Some(bubble) = bubbles.item(i).and_then(|n| n.dyn_into::<Element>().ok()) {
+                    observer.observe(&bubble);
+                }
+            }
+        }
+    }
+
+    /// Forgets what has been reported, so the next `observe` reports everything on
+    /// screen again.
+    pub fn reset(&self) {
+        if let Some((observer, _)) = &self.observer {
+            observer.disconnect();
+        }
+    }
+}
+
+impl Drop for SeenObserver {
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
+
+/// Everything a bubble shows, worked out by `Chat` so unchanged rows compare equal.
+#[derive(Clone, PartialEq)]
+pub struct MessageRow {
+    pub message: Rc<MessageData>,
+    pub avatar: String,
+    pub own: bool,
+    pub mentions_me: bool,
+    /// Draw the "New messages" divider above this message.
+    pub first_unread: bool,
+    pub seen_by: Vec<Reader>,
+    /// Set while the server hasn't stored the message yet.
+    pub delivery: Option<Delivery>,
+    /// The search result being looked at.
+    pub highlighted: bool,
+    pub pinned: bool,
+    /// Ours, or we moderate the room.
+    pub deletable: bool,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct MessageBubbleProps {
+    pub row: MessageRow,
+    pub onshowprofile: Callback<String>,
+    /// Resends a failed message, by client ID.
+    pub onretry: Callback<String>,
+    /// Pins or unpins a message, by ID.
+    pub ontogglepin: Callback<u64>,
+    pub ondelete: Callback<u64>,
+}
+
+/// When a message was sent: the time of day, and the date as well if it wasn't today.
+fn sent_at(time: f64) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(time));
+    let clock = format!("{:02}:{:02}", date.get_hours(), date.get_minutes());
+    if date.to_date_string() == js_sys::Date::new_0().to_date_string() {
+        clock
+    } else {
+        format!("{} {}", String::from(date.to_locale_date_string("default", &JsValue::UNDEFINED)), clock)
+    }
+}
+
+#[function_component(MessageBubble)]
+pub fn message_bubble(props: &MessageBubbleProps) -> Html {
+    #[cfg(test)]
+    tests::BUBBLE_RENDERS.with(|count| count.set(count.get() + 1));
+
+    let row = &props.row;
+    let m = &row.message;
+    let show_profile = {
+        let name = m.from.clone();
+        let onshowprofile = props.onshowprofile.clone();
+        Callback::from(move |_| onshowprofile.emit(name.clone()))
+    };
+    // Pending rows have no server ID yet, so they stay out of measuring and receipts.
+    let server_id = row.delivery.is_none().then(|| m.id.to_string());
+    let element_id = server_id.as_ref().map(|id| format!("message-{}", id));
+    let status = match (row.delivery, &m.client_id) {
+        (Some(Delivery::Failed), Some(client_id)) => {
+            let onretry = props.onretry.clone();
+            let client_id = client_id.clone();
+            html! {
+                <button
+                    onclick={Callback::from(move |_| onretry.emit(client_id.clone()))}
+                    class="text-[10px] font-medium text-red-200 hover:underline"
+                >
+                    {"Failed – tap to retry"}
+                </button>
+            }
+        }
+        (Some(_), _) => html! {
+            <div class="flex items-center text-[10px] text-violet-200" title="Sending">
+                <svg class="w-3 h-3 mr-1" fill="none" stroke="currentColor" stroke-width="2" viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg">
+                    <circle cx="12" cy="12" r="9"></circle><path d="M12 7v5l3 3"></path>
+                </svg>
+                {"Sending…"}
+            </div>
+        },
+        (None, _) => html! {
+            <div class={format!("text-[10px] {}",
+                if row.own { "text-violet-200" } else { "text-gray-400 dark:text-gray-500" }
+            )}>
+                if m.time > 0.0 {
+                    <time title={String::from(js_sys::Date::new(&JsValue::from_f64(m.time)).to_locale_string("default", &JsValue::UNDEFINED))}>
+                        {sent_at(m.time)}
+                    </time>
+                }
+                if m.edited {
+                    <span class="ml-1">{"(edited)"}</span>
+                }
+                if row.own {
+                    <span class="ml-1" title="Delivered">{"✓"}</span>
+                }
+            </div>
+        },
+    };
+
+    let actions = if row.delivery.is_none() {
+        let id = m.id;
+        let ontogglepin = props.ontogglepin.reform(move |_: MouseEvent| id);
+        let ondelete = props.ondelete.reform(move |_: MouseEvent| id);
+        html! {
+            <div class="self-center flex flex-col mx-2">
+                <button
+                    onclick={ontogglepin}
+                    class={classes!(
+                        "p-1", "text-xs", "rounded-full", "hover:bg-gray-200", "dark:hover:bg-gray-700",
+                        if row.pinned { "opacity-100" } else { "opacity-0 group-hover:opacity-100" }
+                    )}
+                    title={if row.pinned { "Unpin" } else { "Pin" }}
+                >
+                    {"📌"}
+                </button>
+                if row.deletable {
+                    <button
+                        onclick={ondelete}
+                        class="p-1 text-xs rounded-full opacity-0 group-hover:opacity-100 hover:bg-gray-200 dark:hover:bg-gray-700"
+                        title="Delete"
+                    >
+                        {"🗑"}
+                    </button>
+                }
+            </div>
+        }
+    } else {
+        html! {}
+    };
+
+    html! {
+        <div data-row-key={server_id.clone()} class="pb-4">
+            if row.first_unread {
+                <div id="unread-divider" class="flex items-center mb-4 text-xs font-semibold text-red-500">
+                    <div class="grow border-t border-red-400"></div>
+                    <span class="px-3">{"New messages"}</span>
+                    <div class="grow border-t border-red-400"></div>
+                </div>
+            }
+            <div id={element_id} data-message-id={server_id} class={format!("flex {} {}",
+                if row.own { "justify-end" } else { "justify-start" },
+                if row.highlighted { "-mx-2 px-2 py-1 rounded-lg bg-violet-100 dark:bg-violet-900/40" } else { "" }
+            )}>
+                <div class={format!("group flex items-end max-w-[80%] md:max-w-[60%] {}",
+                    if row.own { "flex-row-reverse" } else { "flex-row" }
+                )}>
+                    <img class={format!("w-8 h-8 rounded-full cursor-pointer {}", if row.own { "ml-2" } else { "mr-2" })}
+                        src={row.avatar.clone()} alt="avatar" onclick={show_profile}/>
+                    <div class={format!("px-4 py-3 rounded-t-lg {} space-y-1 shadow-sm",
+                        if row.own {
+                            "bg-violet-600 dark:bg-violet-700 text-white rounded-bl-lg rounded-br-none"
+                        } else if row.mentions_me {
+                            "bg-amber-50 dark:bg-amber-900/40 ring-2 ring-amber-400 text-gray-800 dark:text-gray-200 rounded-br-lg rounded-bl-none"
+                        } else {
+                            "bg-white dark:bg-gray-700 text-gray-800 dark:text-gray-200 rounded-br-lg rounded-bl-none"
+                        }
+                    )}>
+                        <div class="text-xs font-medium">
+                            {if row.own { "You" } else { &m.from }}
+                            if m.bot {
+                                <span class="ml-1.5 px-1 rounded text-[10px] font-semibold bg-violet-600 text-white">{"BOT"}</span>
+                            }
+                        </div>
+                        <div class={if row.own { "text-violet-100" } else { "text-gray-700 dark:text-gray-300" }}>
+                            if let Some(action) = commands::action_text(&m.message) {
+                                <p class="break-words whitespace-pre-wrap italic">{format!("{} {}", m.from, action)}</p>
+                            } else if search::is_image(&m.message) {
+                                <img class="mt-2 rounded-lg max-w-full" src={m.message.clone()}/>
+                            } else {
+                                <p class="break-words whitespace-pre-wrap">{mentions::highlight(&m.message, &m.mentions)}</p>
+                            }
+                        </div>
+                        {status}
+                    </div>
+                    {actions}
+                </div>
+            </div>
+            if !row.seen_by.is_empty() {
+                <div
+                    class={format!("flex items-center mt-1 text-[10px] text-gray-400 dark:text-gray-500 {}", if row.own { "justify-end" } else { "justify-start ml-10" })}
+                    title={row.seen_by.iter().map(|u| u.name.as_str()).collect::<Vec<_>>().join(", ")}
+                >
+                    <div class="flex -space-x-1 mr-1">
+                        {
+                            row.seen_by.iter().take(3).map(|u| html! {
+                                <img class="w-4 h-4 rounded-full border border-white dark:border-gray-900" src={u.avatar.clone()} alt={u.name.clone()}/>
+                            }).collect::<Html>()
+                        }
+                    </div>
+                    {format!("Seen by {}", row.seen_by.len())}
+                </div>
+            }
+        </div>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct MessageListProps {
+    pub list_ref: NodeRef,
+    /// The rows inside the rendered window.
+    pub rows: Vec<MessageRow>,
+    /// Our messages the server hasn't stored yet, shown after everything else.
+    pub pending: Vec<MessageRow>,
+    /// Local system messages, shown last.
+    pub notices: Vec<String>,
+    /// Whether there are no messages at all, not just none in the window.
+    pub empty: bool,
+    /// Space standing in for the rows above and below the window.
+    pub pad_top: i32,
+    pub pad_bottom: i32,
+    /// Messages since the "New messages" divider, if there is one.
+    pub unread: Option<usize>,
+    /// Messages that arrived below the fold.
+    pub new_below: usize,
+    pub onscroll: Callback<()>,
+    pub onjumpunread: Callback<()>,
+    pub onmarkread: Callback<()>,
+    pub onscrollbottom: Callback<()>,
+    pub onshowprofile: Callback<String>,
+    pub onretry: Callback<String>,
+    pub ontogglepin: Callback<u64>,
+    pub ondelete: Callback<u64>,
+    /// Dismisses a notice, by index.
+    pub ondismissnotice: Callback<usize>,
+}
+
+#[function_component(MessageList)]
+pub fn message_list(props: &MessageListProps) -> Html {
+    let onscroll = props.onscroll.reform(|_: Event| ());
+    let onjumpunread = props.onjumpunread.reform(|_: MouseEvent| ());
+    let onmarkread = props.onmarkread.reform(|_: MouseEvent| ());
+    let onscrollbottom = props.onscrollbottom.reform(|_: MouseEvent| ());
+
+    html! {
+        <div class="relative w-full grow min-h-0 flex flex-col">
+            <div ref={props.list_ref.clone()} {onscroll} class="w-full grow overflow-auto p-6">
+                if let Some(count) = props.unread {
+                    <div class="sticky top-0 z-10 flex justify-center">
+                        <div class="flex items-center text-xs font-medium rounded-full shadow-md bg-red-500 text-white">
+                            <button onclick={onjumpunread} class="pl-3 pr-2 py-1.5 hover:underline">
+                                {format!("{} new message{} · Jump", count, if count == 1 { "" } else { "s" })}
+                            </button>
+                            <button onclick={onmarkread} class="pl-2 pr-3 py-1.5 border-l border-red-400 hover:underline">
+                                {"Mark read"}
+                            </button>
+                        </div>
+                    </div>
+                }
+                if props.empty {
+                    <div class="flex flex-col items-center justify-center h-full text-gray-500 dark:text-gray-400">
+                        <svg class="w-16 h-16 mb-4 text-gray-300 dark:text-gray-600" fill="currentColor" viewBox="0 0 20 20" xmlns="http://www.w3.org/2000/svg">
+                            <path fill-rule="evenodd" d="M18 10c0 3.866-3.582 7-8 7a8.841 8.841 8.841 0 01-4.083-.98L2 17l1.338-3.123C2.493 12.767 2 11.434 2 10c0-3.866 3.582-7 8-7s8 3.134 8 7zM7 9H5v2h2V9zm8 0h-2v2h2V9zM9 9h2v2H9V9z" clip-rule="evenodd"></path>
+                        </svg>
+                        <p class="text-lg">{"No messages yet"}</p>
+                        <p class="text-sm mt-2">{"Start the conversation by sending a message below!"}</p>
+                    </div>
+                }
+                <div style={format!("height: {}px", props.pad_top)}></div>
+                {
+                    props.rows.iter().map(|row| html! {
+                        <MessageBubble
+                            key={row.message.id.to_string()}
+                            row={row.clone()}
+                            onshowprofile={props.onshowprofile.clone()}
+                            onretry={props.onretry.clone()}
+                            ontogglepin={props.ontogglepin.clone()}
+                            ondelete={props.ondelete.clone()}
+                        />
+                    }).collect::<Html>()
+                }
+                <div style={format!("height: {}px", props.pad_bottom)}></div>
+                {
+                    props.pending.iter().map(|row| html! {
+                        <MessageBubble
+                            key={row.message.client_id.clone().unwrap_or_default()}
+                            row={row.clone()}
+                            onshowprofile={props.onshowprofile.clone()}
+                            onretry={props.onretry.clone()}
+                            ontogglepin={props.ontogglepin.clone()}
+                            ondelete={props.ondelete.clone()}
+                        />
+                    }).collect::<Html>()
+                }
+                {
+                    props.notices.iter().enumerate().map(|(i, text)| {
+                        let ondismiss = props.ondismissnotice.reform(move |_: MouseEvent| i);
+                        html! {
+                            <div class="flex justify-center pb-4">
+                                <div class="flex items-start max-w-[80%] px-4 py-2 text-xs rounded-lg bg-gray-200 dark:bg-gray-800 text-gray-600 dark:text-gray-300">
+                                    <div class="whitespace-pre-wrap">
+                                        <div class="font-semibold mb-0.5">{"Only visible to you"}</div>
+                                        {text}
+                                    </div>
+                                    <button onclick={ondismiss} class="ml-3 hover:text-gray-900 dark:hover:text-white" aria-label="Dismiss">{"✕"}</button>
+                                </div>
+                            </div>
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+            if props.new_below > 0 {
+                <button
+                    onclick={onscrollbottom}
+                    class="absolute bottom-4 left-1/2 -translate-x-1/2 px-4 py-2 text-sm font-medium rounded-full shadow-lg bg-violet-600 hover:bg-violet-700 text-white transition-colors"
+                >
+                    {format!("↓ {} new message{}", props.new_below, if props.new_below == 1 { "" } else { "s" })}
+                </button>
+            }
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use yew::Callback;
+
+    use super::{MessageBubbleProps, MessageRow, Reader};
+    use crate::services::outbox::Delivery;
+    use crate::services::protocol::MessageData;
+
+    thread_local! {
+        /// How many times any `MessageBubble` has rendered.
+        pub static BUBBLE_RENDERS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn message(id: u64) -> MessageData {
+        MessageData {
+            id,
+            from: "alice".into(),
+            message: format!("message {}", id),
+            mentions: vec![],
+            client_id: None,
+            edited: false,
+            time: 1_700_000_000_000.0,
+            bot: false,
+            avatar: None,
+        }
+    }
+
+    fn row(id: u64) -> MessageRow {
+        MessageRow {
+            message: Rc::new(message(id)),
+            avatar: String::new(),
+            own: false,
+            mentions_me: false,
+            first_unread: false,
+            seen_by: vec![],
+            delivery: None,
+            highlighted: false,
+            pinned: false,
+            deletable: false,
+        }
+    }
+
+    fn props(row: MessageRow, callbacks: &MessageBubbleProps) -> MessageBubbleProps {
+        MessageBubbleProps {
+            row,
+            onshowprofile: callbacks.onshowprofile.clone(),
+            onretry: callbacks.onretry.clone(),
+            ontogglepin: callbacks.ontogglepin.clone(),
+            ondelete: callbacks.ondelete.clone(),
+        }
+    }
+
+    fn callbacks() -> MessageBubbleProps {
+        MessageBubbleProps {
+            row: row(0),
+            onshowprofile: Callback::from(|_: String| ()),
+            onretry: Callback::from(|_: String| ()),
+            ontogglepin: Callback::from(|_: u64| ()),
+            ondelete: Callback::from(|_: u64| ()),
+        }
+    }
+
+    // Yew skips a bubble whose props compare equal to last time's, so these are what
+    // keep an unrelated update from re-rendering the whole list.
+
+    #[test]
+    fn a_row_rebuilt_from_the_same_message_is_equal() {
+        let shared = Rc::new(message(1));
+        let first = MessageRow { message: shared.clone(), ..row(1) };
+        let again = MessageRow { message: shared, ..row(1) };
+        assert!(first == again);
+        // A copy that came back from the server or the cache compares by value.
+        assert!(row(1) == row(1));
+    }
+
+    #[test]
+    fn anything_the_bubble_shows_makes_the_row_differ() {
+        let base = row(1);
+        let edited = MessageData {
+            message: "changed".into(),
+            edited: true,
+            ..message(1)
+        };
+        assert!(base != MessageRow { message: Rc::new(edited), ..row(1) });
+        assert!(base != MessageRow { pinned: true, ..row(1) });
+        assert!(base != MessageRow { first_unread: true, ..row(1) });
+        assert!(base != MessageRow { delivery: Some(Delivery::Failed), ..row(1) });
+        let seen_by = vec![Reader {
+            name: "bob".into(),
+            avatar: String::new(),
+        }];
+        assert!(base != MessageRow { seen_by, ..row(1) });
+    }
+
+    #[test]
+    fn props_are_equal_only_with_the_same_callbacks() {
+        let shared = callbacks();
+        assert!(props(row(1), &shared) == props(row(1), &shared));
+        assert!(props(row(1), &shared) != props(row(2), &shared));
+        // Callbacks made afresh on every render would defeat the memoization.
+        assert!(props(row(1), &shared) != props(row(1), &callbacks()));
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    mod wasm {
+        use std::cell::Cell;
+
+        use wasm_bindgen_test::*;
+        use yew::prelude::*;
+
+        use super::{row, BUBBLE_RENDERS};
+        use crate::components::message_list::{MessageList, MessageRow};
+
+        wasm_bindgen_test_configure!(run_in_browser);
+
+        enum Msg {
+            /// Re-render the list without touching its messages, as an unrelated
+            /// frame (say, a presence change) would.
+            Unrelated,
+            Push,
+        }
+
+        struct Host {
+            rows: Vec<MessageRow>,
+            unread: Option<usize>,
+        }
+
+        impl Component for Host {
+            type Message = Msg;
+            type Properties = ();
+
+            fn create(_ctx: &Context<Self>) -> Self {
+                Self {
+                    rows: (1..=20).map(row).collect(),
+                    unread: None,
+                }
+            }
+
+            fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+                match msg {
+                    Msg::Unrelated => self.unread = Some(self.unread.unwrap_or(0) + 1),
+                    Msg::Push => self.rows.push(row(self.rows.len() as u64 + 1)),
+                }
+                true
+            }
+
+            fn view(&self, _ctx: &Context<Self>) -> Html {
+                html! {
+                    <MessageList
+                        list_ref={NodeRef::default()}
+                        rows={self.rows.clone()}
+                        pending={vec![]}
+                        notices={vec![]}
+                        empty={self.rows.is_empty()}
+                        pad_top={0}
+                        pad_bottom={0}
+                        unread={self.unread}
+                        new_below={0}
+                        onscroll={Callback::noop()}
+                        onjumpunread={Callback::noop()}
+                        onmarkread={Callback::noop()}
+                        onscrollbottom={Callback::noop()}
+                        onshowprofile={Callback::noop()}
+                        onretry={Callback::noop()}
+                        ontogglepin={Callback::noop()}
+                        ondelete={Callback::noop()}
+                        ondismissnotice={Callback::noop()}
+                    />
+                }
+            }
+        }
+
+        #[wasm_bindgen_test]
+        fn unchanged_bubbles_do_not_rerender() {
+            let document = web_sys::window().unwrap().document().unwrap();
+            let root = document.create_element("div").unwrap();
+            document.body().unwrap().append_child(&root).unwrap();
+            BUBBLE_RENDERS.with(|count| count.set(0));
+
+            let app = yew::start_app_in_element::<Host>(root);
+            assert_eq!(BUBBLE_RENDERS.with(Cell::get), 20);
+
+            // The list itself re-renders for the new pill, but none of its bubbles do.
+            app.send_message(Msg::Unrelated);
+            assert_eq!(BUBBLE_RENDERS.with(Cell::get), 20);
+
+            // A new message renders just its own bubble.
+            app.send_message(Msg::Push);
+            assert_eq!(BUBBLE_RENDERS.with(Cell::get), 21);
+        }
+    }
+}
@@ -1,3 +1,17 @@
 pub mod chat;
+pub mod commands;
+pub mod composer;
+pub mod connection_banner;
 pub mod login;
+pub mod mentions;
+pub mod message_list;
+pub mod notifier;
+pub mod pins;
+pub mod profile;
+pub mod room_header;
+pub mod search;
+pub mod settings;
 pub mod theme_toggle;
+pub mod topic;
+pub mod user_list;
+pub mod virtual_list;
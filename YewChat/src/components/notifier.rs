@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use yew::prelude::*;
+use yew_agent::use_bridge;
+
+use crate::components::settings::{NotifyMode, Settings};
+use crate::services::avatar;
+use crate::services::event_bus::{Event, EventBus, Request, Topic};
+use crate::services::notifications::{self, document_hidden};
+use crate::services::protocol::ServerEvent;
+use crate::User;
+
+/// Raises a desktop notification for new messages while the tab is hidden, as far
+/// as the user's settings ask for it. Renders nothing.
+#[function_component(Notifier)]
+pub fn notifier() -> Html {
+    let user = use_context::<User>().expect("No context found.");
+    // Avatars by nick, from the latest user list.
+    let avatars = use_mut_ref(HashMap::<String, String>::new);
+    // The newest message we have considered, so repeated echoes stay quiet.
+    let newest = use_mut_ref(|| 0u64);
+    let bridge = use_bridge::<EventBus, _>(move |event: Rc<Event>| match &*event {
+        Event::Server(ServerEvent::Users(users)) => {
+            *avatars.borrow_mut() = users
+                .iter()
+                .map(|u| (u.nick.clone(), avatar::resolve(&u.nick, u.avatar.as_deref())))
+                .collect();
+        }
+        Event::Server(ServerEvent::Message(message)) => {
+            if message.id != 0 {
+                if message.id <= *newest.borrow() {
+                    return;
+                }
+                *newest.borrow_mut() = message.id;
+            }
+            let me = user.username.borrow();
+            if message.from == *me {
+                return;
+            }
+            let wanted = match Settings::load().notifications {
+                NotifyMode::Off => false,
+                NotifyMode::Mentions => message.mentions.contains(&me),
+                NotifyMode::All => true,
+            };
+            if !wanted || !document_hidden() {
+                return;
+            }
+            // The message's own avatar if it carries one, otherwise its sender's.
+            let icon = match (&message.avatar, avatars.borrow().get(&message.from)) {
+                (None, Some(avatar)) => avatar.clone(),
+                _ => avatar::resolve(&message.from, message.avatar.as_deref()),
+            };
+            notifications::notify(&message.from, &message.message, &icon);
+        }
+        _ => {}
+    });
+    use_effect_with_deps(
+        move |_| {
+            bridge.send(Request::Subscribe(vec![Topic::Notifications, Topic::Presence]));
+            || ()
+        },
+        (),
+    );
+    html! {}
+}
@@ -0,0 +1,118 @@
+use std::rc::Rc;
+
+use yew::prelude::*;
+
+use crate::services::protocol::{MsgTypes, Pin, WebSocketMessage};
+
+/// What the server says when a member tries to unpin someone else's pin.
+pub const UNPIN_DENIED: &str = "Only moderators and admins can unpin messages pinned by someone else.";
+
+/// The room's pinned messages, newest pin last, and whether the drawer is open.
+#[derive(Default)]
+pub struct Pins {
+    pins: Rc<Vec<Pin>>,
+    pub open: bool,
+}
+
+impl Pins {
+    pub fn set(&mut self, pins: Vec<Pin>) {
+        self.pins = Rc::new(pins);
+    }
+
+    pub fn list(&self) -> Rc<Vec<Pin>> {
+        self.pins.clone()
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.pins.iter().any(|p| p.message.id == id)
+    }
+
+    /// Whether the server lets `me` take down pin `id`: their own pins, or any if
+    /// they are a moderator.
+    pub fn may_unpin(&self, id: u64, me: &str, privileged: bool) -> bool {
+        privileged || self.pins.iter().any(|p| p.message.id == id && p.pinned_by == me)
+    }
+
+    /// Asks the server to pin message `id`, or to unpin it if it already is.
+    pub fn toggle_frame(&self, id: u64) -> WebSocketMessage {
+        WebSocketMessage {
+            message_type: if self.contains(id) { MsgTypes::Unpin } else { MsgTypes::Pin },
+            data: Some(id.to_string()),
+            data_array: None,
+            client_id: None,
+        }
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct PinnedDrawerProps {
+    pub pins: Rc<Vec<Pin>>,
+    /// Jumps to a message, by ID.
+    pub onselect: Callback<u64>,
+    pub onunpin: Callback<u64>,
+    pub onclose: Callback<()>,
+    /// Our nick; members only get to unpin what they pinned.
+    pub me: String,
+    pub privileged: bool,
+}
+
+#[function_component(PinnedDrawer)]
+pub fn pinned_drawer(props: &PinnedDrawerProps) -> Html {
+    let onclose = props.onclose.reform(|_: MouseEvent| ());
+
+    html! {
+        <div class="flex-none w-full md:w-80 h-auto md:h-screen flex flex-col bg-white dark:bg-gray-800 border-l border-gray-200 dark:border-gray-700 shadow-lg">
+            <div class="h-16 flex-none flex items-center justify-between px-4 border-b border-gray-200 dark:border-gray-700">
+                <div class="text-sm font-semibold text-gray-800 dark:text-gray-100">
+                    {format!("📌 Pinned ({})", props.pins.len())}
+                </div>
+                <button
+                    onclick={onclose}
+                    class="p-1 rounded-full text-gray-500 hover:bg-gray-100 dark:hover:bg-gray-700"
+                    aria-label="Close pinned messages"
+                >
+                    {"✕"}
+                </button>
+            </div>
+            <div class="grow overflow-y-auto">
+                if props.pins.is_empty() {
+                    <p class="p-4 text-sm text-gray-500 dark:text-gray-400 italic">
+                        {"Nothing is pinned yet. Hover over a message and press 📌 to pin it."}
+                    </p>
+                }
+                {
+                    // Most recently pinned first.
+                    props.pins.iter().rev().map(|pin| {
+                        let id = pin.message.id;
+                        let unpinnable = props.privileged || pin.pinned_by == props.me;
+                        let onclick = props.onselect.reform(move |_: MouseEvent| id);
+                        let onunpin = {
+                            let onunpin = props.onunpin.clone();
+                            Callback::from(move |e: MouseEvent| {
+                                // Don't also jump to the message.
+                                e.stop_propagation();
+                                onunpin.emit(id);
+                            })
+                        };
+                        html! {
+                            <div
+                                key={id}
+                                {onclick}
+                                class="px-4 py-3 border-b border-gray-100 dark:border-gray-700 cursor-pointer hover:bg-gray-50 dark:hover:bg-gray-700"
+                            >
+                                <div class="flex items-baseline justify-between text-xs">
+                                    <span class="font-medium text-gray-700 dark:text-gray-200">{&pin.message.from}</span>
+                                    if unpinnable {
+                                        <button onclick={onunpin} class="ml-2 text-gray-400 hover:text-red-500" title="Unpin">{"Unpin"}</button>
+                                    }
+                                </div>
+                                <p class="mt-0.5 text-sm text-gray-600 dark:text-gray-300 break-words line-clamp-3">{&pin.message.message}</p>
+                                <div class="mt-1 text-[10px] text-gray-400">{format!("Pinned by {}", pin.pinned_by)}</div>
+                            </div>
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+use crate::services::protocol::Presence;
+
+const PROFILE_STORAGE_KEY: &str = "profile";
+
+/// The user-editable part of a profile, as stored and broadcast by the server.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Profile {
+    pub status_text: String,
+    pub status_emoji: String,
+    pub pronouns: String,
+    pub title: String,
+    pub timezone: String,
+}
+
+impl Profile {
+    /// Loads the profile saved by the editor, if any.
+    pub fn load() -> Self {
+        web_sys::window()
+            .and_then(|w| w.local_storage().ok().flatten())
+            .and_then(|s| s.get_item(PROFILE_STORAGE_KEY).ok().flatten())
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Some(storage) = web_sys::window().and_then(|w| w.local_storage().ok().flatten()) {
+            let _ = storage.set_item(PROFILE_STORAGE_KEY, &serde_json::to_string(self).unwrap());
+        }
+    }
+
+    /// The status line shown under a name, e.g. "🌴 On holiday".
+    pub fn status(&self) -> Option<String> {
+        let status = format!("{} {}", self.status_emoji, self.status_text);
+        let status = status.trim();
+        (!status.is_empty()).then(|| status.to_string())
+    }
+}
+
+/// The browser's own IANA timezone, used as the editor default.
+fn browser_timezone() -> String {
+    let format = js_sys::Intl::DateTimeFormat::new(&js_sys::Array::new(), &js_sys::Object::new());
+    js_sys::Reflect::get(&format.resolved_options(), &"timeZone".into())
+        .ok()
+        .and_then(|tz| tz.as_string())
+        .unwrap_or_default()
+}
+
+/// Current wall-clock time in `timezone`, or `None` if the browser doesn't know it.
+fn local_time_in(timezone: &str) -> Option<String> {
+    let options = js_sys::Object::new();
+    for (key, value) in [("timeZone", timezone), ("hour", "2-digit"), ("minute", "2-digit")] {
+        js_sys::Reflect::set(&options, &key.into(), &value.into()).ok()?;
+    }
+    let intl = js_sys::Reflect::get(&js_sys::global(), &"Intl".into()).ok()?;
+    let constructor: js_sys::Function = js_sys::Reflect::get(&intl, &"DateTimeFormat".into())
+        .ok()?
+        .dyn_into()
+        .ok()?;
+    // `Reflect.construct` lets us catch the RangeError thrown for unknown timezones.
+    let format: js_sys::Intl::DateTimeFormat =
+        js_sys::Reflect::construct(&constructor, &js_sys::Array::of2(&JsValue::UNDEFINED, &options))
+            .ok()?
+            .unchecked_into();
+    format
+        .format()
+        .call1(&JsValue::UNDEFINED, &js_sys::Date::new_0())
+        .ok()?
+        .as_string()
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ProfileEditorProps {
+    pub profile: Profile,
+    pub avatar: String,
+    pub avatar_error: Option<String>,
+    /// Whether the server takes avatar uploads.
+    pub uploads: bool,
+    pub onsave: Callback<Profile>,
+    pub oncancel: Callback<()>,
+    pub onupload: Callback<web_sys::File>,
+    pub onremoveavatar: Callback<()>,
+}
+
+#[function_component(ProfileEditor)]
+pub fn profile_editor(props: &ProfileEditorProps) -> Html {
+    let draft = use_state(|| {
+        let mut profile = props.profile.clone();
+        if profile.timezone.is_empty() {
+            profile.timezone = browser_timezone();
+        }
+        profile
+    });
+
+    let field = |label: &'static str, placeholder: &'static str, value: String, setter: fn(&mut Profile, String)| {
+        let draft = draft.clone();
+        let oninput = Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut profile = (*draft).clone();
+            setter(&mut profile, input.value());
+            draft.set(profile);
+        });
+        html! {
+            <label class="block">
+                <span class="text-xs font-medium text-gray-600 dark:text-gray-300">{label}</span>
+                <input
+                    {oninput}
+                    type="text"
+                    value={value}
+                    placeholder={placeholder}
+                    maxlength="80"
+                    class="mt-1 block w-full px-3 py-2 bg-gray-100 dark:bg-gray-700 rounded-lg outline-none focus:ring-2 focus:ring-violet-500 text-gray-800 dark:text-gray-200"
+                />
+            </label>
+        }
+    };
+
+    let onsave = {
+        let draft = draft.clone();
+        let onsave = props.onsave.clone();
+        Callback::from(move |_| onsave.emit((*draft).clone()))
+    };
+    let oncancel = {
+        let oncancel = props.oncancel.clone();
+        Callback::from(move |_| oncancel.emit(()))
+    };
+    let onchoosefile = {
+        let onupload = props.onupload.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            if let Some(file) = input.files().and_then(|files| files.get(0)) {
+                onupload.emit(file);
+            }
+            input.set_value("");
+        })
+    };
+    let onremoveavatar = {
+        let onremoveavatar = props.onremoveavatar.clone();
+        Callback::from(move |_| onremoveavatar.emit(()))
+    };
+
+    html! {
+        <div class="fixed inset-0 z-20 flex items-center justify-center bg-black/40">
+            <div class="w-full max-w-sm p-6 space-y-4 bg-white dark:bg-gray-800 rounded-lg shadow-xl">
+                <h2 class="text-lg font-semibold text-gray-800 dark:text-gray-100">{"Edit profile"}</h2>
+                <div class="flex items-center space-x-3">
+                    <img class="w-16 h-16 rounded-full border-2 border-gray-200 dark:border-gray-600" src={props.avatar.clone()} alt="avatar"/>
+                    <div class="space-y-1">
+                        if props.uploads {
+                            <label class="block px-3 py-1.5 text-sm rounded-lg cursor-pointer bg-gray-100 dark:bg-gray-700 text-gray-700 dark:text-gray-200 hover:bg-gray-200 dark:hover:bg-gray-600">
+                                {"Upload image"}
+                                <input type="file" accept="image/png,image/jpeg,image/gif,image/webp" class="hidden" onchange={onchoosefile}/>
+                            </label>
+                        }
+                        <button onclick={onremoveavatar} class="block text-xs text-gray-500 dark:text-gray-400 hover:underline">
+                            {"Use generated avatar"}
+                        </button>
+                    </div>
+                </div>
+                if let Some(error) = &props.avatar_error {
+                    <div class="text-xs text-red-500">{error}</div>
+                }
+                <div class="flex space-x-3">
+                    <div class="w-20">
+                        {field("Emoji", "💬", draft.status_emoji.clone(), |p, v| p.status_emoji = v)}
+                    </div>
+                    <div class="grow">
+                        {field("Status", "What are you up to?", draft.status_text.clone(), |p, v| p.status_text = v)}
+                    </div>
+                </div>
+                {field("Pronouns", "they/them", draft.pronouns.clone(), |p, v| p.pronouns = v)}
+                {field("Title", "Rustacean", draft.title.clone(), |p, v| p.title = v)}
+                {field("Timezone", "Asia/Jakarta", draft.timezone.clone(), |p, v| p.timezone = v)}
+                <div class="flex justify-end space-x-2 pt-2">
+                    <button onclick={oncancel} class="px-4 py-2 text-sm rounded-lg text-gray-600 dark:text-gray-300 hover:bg-gray-100 dark:hover:bg-gray-700">
+                        {"Cancel"}
+                    </button>
+                    <button onclick={onsave} class="px-4 py-2 text-sm rounded-lg bg-violet-600 hover:bg-violet-700 text-white">
+                        {"Save"}
+                    </button>
+                </div>
+            </div>
+        </div>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ProfileCardProps {
+    pub name: String,
+    pub avatar: String,
+    pub presence: Presence,
+    pub last_seen: Option<f64>,
+    pub profile: Profile,
+    pub onclose: Callback<()>,
+}
+
+#[function_component(ProfileCard)]
+pub fn profile_card(props: &ProfileCardProps) -> Html {
+    let onclose = {
+        let onclose = props.onclose.clone();
+        Callback::from(move |_| onclose.emit(()))
+    };
+    let profile = &props.profile;
+    let local_time = (!profile.timezone.is_empty())
+        .then(|| local_time_in(&profile.timezone))
+        .flatten();
+
+    html! {
+        <div class="fixed inset-0 z-20 flex items-center justify-center bg-black/20" onclick={onclose}>
+            <div
+                class="w-72 p-5 bg-white dark:bg-gray-800 rounded-lg shadow-xl"
+                onclick={Callback::from(|e: MouseEvent| e.stop_propagation())}
+            >
+                <div class="flex items-center">
+                    <div class="relative">
+                        <img class="w-16 h-16 rounded-full border-2 border-gray-200 dark:border-gray-600" src={props.avatar.clone()} alt="avatar"/>
+                        <div class={classes!("absolute", "bottom-0", "right-0", "w-4", "h-4", props.presence.dot_class(), "border-2", "border-white", "dark:border-gray-800", "rounded-full")}></div>
+                    </div>
+                    <div class="ml-3">
+                        <div class="font-semibold text-gray-800 dark:text-gray-100">
+                            {props.name.clone()}
+                            if !profile.pronouns.is_empty() {
+                                <span class="ml-1 text-xs font-normal text-gray-500 dark:text-gray-400">{format!("({})", profile.pronouns)}</span>
+                            }
+                        </div>
+                        if !profile.title.is_empty() {
+                            <div class="text-sm text-gray-600 dark:text-gray-300">{profile.title.clone()}</div>
+                        }
+                        <div class="text-xs text-gray-500 dark:text-gray-400">{props.presence.label(props.last_seen)}</div>
+                    </div>
+                </div>
+                if let Some(status) = profile.status() {
+                    <div class="mt-4 px-3 py-2 text-sm rounded-lg bg-gray-100 dark:bg-gray-700 text-gray-700 dark:text-gray-200">
+                        {status}
+                    </div>
+                }
+                if let Some(time) = local_time {
+                    <div class="mt-3 text-xs text-gray-500 dark:text-gray-400">
+                        {format!("🕒 {} local time ({})", time, profile.timezone)}
+                    </div>
+                }
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,118 @@
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+use crate::components::topic::TopicLine;
+
+#[derive(Properties, PartialEq)]
+pub struct RoomHeaderProps {
+    /// What the server calls itself, once it has said.
+    pub server_name: Option<String>,
+    /// Whether we may change the topic.
+    pub moderator: bool,
+    pub onsettopic: Callback<String>,
+    /// Whether the server searches messages; without it there is no search box.
+    pub searchable: bool,
+    pub query: String,
+    pub onsearch: Callback<String>,
+    pub onclosesearch: Callback<()>,
+    pub pin_count: usize,
+    pub pins_open: bool,
+    pub ontogglepins: Callback<()>,
+    /// Mentions of us that came in out of sight.
+    pub mentions: usize,
+    pub onjumpmention: Callback<()>,
+    /// Our status, shown on the profile button.
+    pub status: Option<String>,
+    pub oneditprofile: Callback<()>,
+    pub onopensettings: Callback<()>,
+}
+
+/// The bar above the messages: room name and topic, search, pins and our menus.
+#[function_component(RoomHeader)]
+pub fn room_header(props: &RoomHeaderProps) -> Html {
+    let oninput = props.onsearch.reform(|e: InputEvent| {
+        let input: HtmlInputElement = e.target_unchecked_into();
+        input.value()
+    });
+    let onkeydown = {
+        let onclosesearch = props.onclosesearch.clone();
+        Callback::from(move |e: KeyboardEvent| {
+            if e.key() == "Escape" {
+                onclosesearch.emit(());
+            }
+        })
+    };
+
+    html! {
+        <div class="w-full h-16 border-b border-gray-200 dark:border-gray-700 bg-white dark:bg-gray-800 shadow-sm flex items-center justify-between px-5">
+            <div class="flex items-center">
+                <svg class="w-6 h-6 text-violet-600 dark:text-violet-400" fill="currentColor" viewBox="0 0 20 20" xmlns="http://www.w3.org/2000/svg">
+                    <path fill-rule="evenodd" d="M18 10c0 3.866-3.582 7-8 7a8.841 8.841 0 01-4.083-.98L2 17l1.338-3.123C2.493 12.767 2 11.434 2 10c0-3.866 3.582-7 8-7s8 3.134 8 7zM7 9H5v2h2V9zm8 0h-2v2h2V9zM9 9h2v2H9V9z" clip-rule="evenodd"></path>
+                </svg>
+                <div class="ml-2 min-w-0">
+                    <h1 class="text-lg font-semibold leading-tight text-gray-800 dark:text-gray-100">
+                        {"#general"}
+                        if let Some(name) = &props.server_name {
+                            <span class="ml-2 text-xs font-normal text-gray-400">{name}</span>
+                        }
+                    </h1>
+                    <TopicLine onchange={props.onsettopic.clone()} editable={props.moderator} />
+                </div>
+            </div>
+            <div class="flex items-center">
+                if props.searchable {
+                    <input
+                        type="search"
+                        value={props.query.clone()}
+                        {oninput}
+                        {onkeydown}
+                        placeholder="Search messages"
+                        title="Filters: from:name in:#room before:YYYY-MM-DD after:YYYY-MM-DD has:image"
+                        class="mr-2 w-40 md:w-56 px-3 py-1.5 text-sm rounded-full bg-gray-100 dark:bg-gray-700 text-gray-800 dark:text-gray-100 placeholder-gray-400 focus:outline-none focus:ring-2 focus:ring-violet-500"
+                    />
+                }
+                <button
+                    onclick={props.ontogglepins.reform(|_: MouseEvent| ())}
+                    class={classes!(
+                        "mr-2", "px-2.5", "py-1", "text-xs", "font-medium", "rounded-full", "transition-colors",
+                        if props.pins_open {
+                            "bg-violet-600 text-white"
+                        } else {
+                            "text-gray-700 dark:text-gray-200 hover:bg-gray-100 dark:hover:bg-gray-700"
+                        }
+                    )}
+                    title="Pinned messages"
+                >
+                    {format!("📌 {}", props.pin_count)}
+                </button>
+                if props.mentions > 0 {
+                    <button
+                        onclick={props.onjumpmention.reform(|_: MouseEvent| ())}
+                        class="mr-2 px-2.5 py-1 text-xs font-semibold rounded-full bg-amber-400 text-gray-900 hover:bg-amber-300 transition-colors"
+                        title="Jump to mention"
+                    >
+                        {format!("@ {}", props.mentions)}
+                    </button>
+                }
+                <button
+                    onclick={props.oneditprofile.reform(|_: MouseEvent| ())}
+                    class="flex items-center px-3 py-1.5 text-sm rounded-full text-gray-700 dark:text-gray-200 hover:bg-gray-100 dark:hover:bg-gray-700 transition-colors"
+                >
+                    if let Some(status) = &props.status {
+                        <span class="mr-2 max-w-[12rem] truncate text-gray-500 dark:text-gray-400">{status}</span>
+                    }
+                    {"Edit profile"}
+                </button>
+                <button
+                    onclick={props.onopensettings.reform(|_: MouseEvent| ())}
+                    class="ml-1 p-2 rounded-full text-gray-600 dark:text-gray-300 hover:bg-gray-100 dark:hover:bg-gray-700 transition-colors"
+                    aria-label="Settings"
+                >
+                    <svg class="w-5 h-5" fill="currentColor" viewBox="0 0 20 20" xmlns="http://www.w3.org/2000/svg">
+                        <path fill-rule="evenodd" d="M11.49 3.17c-.38-1.56-2.6-1.56-2.98 0a1.532 1.532 0 01-2.286.948c-1.372-.836-2.942.734-2.106 2.106.54.886.061 2.042-.947 2.287-1.561.379-1.561 2.6 0 2.978a1.532 1.532 0 01.947 2.287c-.836 1.372.734 2.942 2.106 2.106a1.532 1.532 0 012.287.947c.379 1.561 2.6 1.561 2.978 0a1.533 1.533 0 012.287-.947c1.372.836 2.942-.734 2.106-2.106a1.533 1.533 0 01.947-2.287c1.561-.379 1.561-2.6 0-2.978a1.532 1.532 0 01-.947-2.287c.836-1.372-.734-2.942-2.106-2.106a1.532 1.532 0 01-2.287-.947zM10 13a3 3 0 100-6 3 3 0 000 6z" clip-rule="evenodd"></path>
+                    </svg>
+                </button>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,165 @@
+use std::rc::Rc;
+
+use gloo_timers::callback::Timeout;
+use wasm_bindgen::JsValue;
+use yew::prelude::*;
+
+use crate::services::protocol::{MessageData, MsgTypes, SearchResults, WebSocketMessage};
+use crate::services::search::Query;
+
+/// How long typing has to pause before the server is asked to search.
+const DEBOUNCE_MS: u32 = 300;
+const MAX_RESULTS: usize = 50;
+
+/// What the search box holds and what it has found.
+pub struct Search {
+    query: String,
+    /// Matches among the messages we hold, found as the query is typed.
+    local: Vec<Rc<MessageData>>,
+    /// The server's matches, once they arrive.
+    remote: Option<Vec<Rc<MessageData>>>,
+    _debounce: Timeout,
+}
+
+impl Search {
+    /// Looks through `messages` for `query` straight away, and calls `ask_server` once
+    /// typing pauses. `None` if there is nothing to search for.
+    pub fn start(query: String, messages: &[Rc<MessageData>], ask_server: Callback<()>) -> Option<Self> {
+        let parsed = Query::parse(&query);
+        if parsed.is_empty() {
+            return None;
+        }
+        let local = messages
+            .iter()
+            .rev()
+            .filter(|m| parsed.matches(m))
+            .take(MAX_RESULTS)
+            .cloned()
+            .collect();
+        Some(Self {
+            query,
+            local,
+            remote: None,
+            _debounce: Timeout::new(DEBOUNCE_MS, move || ask_server.emit(())),
+        })
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Asks the server to search everything it has stored.
+    pub fn frame(&self) -> WebSocketMessage {
+        WebSocketMessage {
+            message_type: MsgTypes::Search,
+            data: Some(self.query.clone()),
+            data_array: None,
+            client_id: None,
+        }
+    }
+
+    /// Takes the server's answer; returns whether it was for this query.
+    pub fn receive(&mut self, found: &SearchResults) -> bool {
+        if found.query != self.query {
+            return false;
+        }
+        self.remote = Some(found.results.iter().cloned().map(Rc::new).collect());
+        true
+    }
+
+    /// Drops a deleted message from the results.
+    pub fn forget(&mut self, id: u64) {
+        self.local.retain(|m| m.id != id);
+        if let Some(remote) = &mut self.remote {
+            remote.retain(|m| m.id != id);
+        }
+    }
+
+    /// Whether the server's answer is still on its way.
+    pub fn searching(&self) -> bool {
+        self.remote.is_none()
+    }
+
+    /// Local and server matches together, newest first.
+    pub fn results(&self) -> Vec<Rc<MessageData>> {
+        let mut results: Vec<Rc<MessageData>> = self.local.iter().chain(self.remote.iter().flatten()).cloned().collect();
+        results.sort_by_key(|m| std::cmp::Reverse(m.id));
+        results.dedup_by_key(|m| m.id);
+        results.truncate(MAX_RESULTS);
+        results
+    }
+}
+
+fn sent_at(time: f64) -> String {
+    js_sys::Date::new(&JsValue::from_f64(time))
+        .to_locale_string("default", &JsValue::UNDEFINED)
+        .into()
+}
+
+#[derive(Properties, PartialEq)]
+pub struct SearchPanelProps {
+    pub query: String,
+    /// Matches so far, newest first.
+    pub results: Vec<Rc<MessageData>>,
+    /// Whether the server's answer is still on its way.
+    pub searching: bool,
+    /// Jumps to a message, by ID.
+    pub onselect: Callback<u64>,
+    pub onclose: Callback<()>,
+}
+
+#[function_component(SearchPanel)]
+pub fn search_panel(props: &SearchPanelProps) -> Html {
+    let onclose = props.onclose.reform(|_: MouseEvent| ());
+
+    html! {
+        <div class="flex-none w-full md:w-80 h-auto md:h-screen flex flex-col bg-white dark:bg-gray-800 border-l border-gray-200 dark:border-gray-700 shadow-lg">
+            <div class="h-16 flex-none flex items-center justify-between px-4 border-b border-gray-200 dark:border-gray-700">
+                <div class="text-sm font-semibold text-gray-800 dark:text-gray-100">
+                    {format!("{} result{}", props.results.len(), if props.results.len() == 1 { "" } else { "s" })}
+                    if props.searching {
+                        <span class="ml-2 font-normal text-gray-400">{"Searching…"}</span>
+                    }
+                </div>
+                <button
+                    onclick={onclose}
+                    class="p-1 rounded-full text-gray-500 hover:bg-gray-100 dark:hover:bg-gray-700"
+                    aria-label="Close search"
+                >
+                    {"✕"}
+                </button>
+            </div>
+            <div class="grow overflow-y-auto">
+                if props.results.is_empty() && !props.searching {
+                    <p class="p-4 text-sm text-gray-500 dark:text-gray-400 italic">
+                        {format!("Nothing matches “{}”.", props.query)}
+                    </p>
+                }
+                {
+                    props.results.iter().map(|m| {
+                        let onclick = {
+                            let id = m.id;
+                            let onselect = props.onselect.clone();
+                            Callback::from(move |_| onselect.emit(id))
+                        };
+                        html! {
+                            <button
+                                key={m.id}
+                                {onclick}
+                                class="block w-full px-4 py-3 text-left border-b border-gray-100 dark:border-gray-700 hover:bg-gray-50 dark:hover:bg-gray-700"
+                            >
+                                <div class="flex items-baseline justify-between text-xs">
+                                    <span class="font-medium text-gray-700 dark:text-gray-200">{&m.from}</span>
+                                    if m.time > 0.0 {
+                                        <span class="ml-2 text-gray-400">{sent_at(m.time)}</span>
+                                    }
+                                </div>
+                                <p class="mt-0.5 text-sm text-gray-600 dark:text-gray-300 truncate">{&m.message}</p>
+                            </button>
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use yew::prelude::*;
+
+const SETTINGS_STORAGE_KEY: &str = "settings";
+
+/// Which incoming messages raise a desktop notification while the tab is hidden.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyMode {
+    #[default]
+    Off,
+    Mentions,
+    All,
+}
+
+/// Per-browser preferences, kept in local storage.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub notifications: NotifyMode,
+    /// Share how far we have read with the server, so other devices pick it up.
+    pub sync_read_markers: bool,
+    /// Tell others when we have seen their messages.
+    pub read_receipts: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            notifications: NotifyMode::default(),
+            sync_read_markers: true,
+            read_receipts: true,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        web_sys::window()
+            .and_then(|w| w.local_storage().ok().flatten())
+            .and_then(|s| s.get_item(SETTINGS_STORAGE_KEY).ok().flatten())
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Some(storage) = web_sys::window().and_then(|w| w.local_storage().ok().flatten()) {
+            let _ = storage.set_item(SETTINGS_STORAGE_KEY, &serde_json::to_string(self).unwrap());
+        }
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct SettingsPanelProps {
+    pub settings: Settings,
+    /// Set when the browser refused notification permission.
+    pub notifications_blocked: bool,
+    pub onchange: Callback<Settings>,
+    pub onclose: Callback<()>,
+}
+
+#[function_component(SettingsPanel)]
+pub fn settings_panel(props: &SettingsPanelProps) -> Html {
+    let notify_option = |mode: NotifyMode, label: &'static str| {
+        let selected = props.settings.notifications == mode;
+        let onclick = {
+            let mut settings = props.settings.clone();
+            settings.notifications = mode;
+            let onchange = props.onchange.clone();
+            Callback::from(move |_| onchange.emit(settings.clone()))
+        };
+        html! {
+            <button
+                {onclick}
+                class={classes!(
+                    "px-3", "py-1.5", "text-sm", "rounded-lg", "transition-colors",
+                    if selected {
+                        "bg-violet-600 text-white"
+                    } else {
+                        "bg-gray-100 dark:bg-gray-700 text-gray-700 dark:text-gray-200 hover:bg-gray-200 dark:hover:bg-gray-600"
+                    }
+                )}
+            >
+                {label}
+            </button>
+        }
+    };
+    let toggle_sync = {
+        let mut settings = props.settings.clone();
+        settings.sync_read_markers = !settings.sync_read_markers;
+        let onchange = props.onchange.clone();
+        Callback::from(move |_| onchange.emit(settings.clone()))
+    };
+    let toggle_receipts = {
+        let mut settings = props.settings.clone();
+        settings.read_receipts = !settings.read_receipts;
+        let onchange = props.onchange.clone();
+        Callback::from(move |_| onchange.emit(settings.clone()))
+    };
+    let onclose = {
+        let onclose = props.onclose.clone();
+        Callback::from(move |_| onclose.emit(()))
+    };
+
+    html! {
+        <div class="fixed inset-0 z-20 flex items-center justify-center bg-black/40">
+            <div class="w-full max-w-sm p-6 space-y-4 bg-white dark:bg-gray-800 rounded-lg shadow-xl">
+                <h2 class="text-lg font-semibold text-gray-800 dark:text-gray-100">{"Settings"}</h2>
+                <div class="space-y-2">
+                    <div class="text-sm font-medium text-gray-700 dark:text-gray-200">{"Desktop notifications"}</div>
+                    <p class="text-xs text-gray-500 dark:text-gray-400">{"Shown while this tab is in the background."}</p>
+                    <div class="flex space-x-2">
+                        {notify_option(NotifyMode::Off, "Off")}
+                        {notify_option(NotifyMode::Mentions, "Mentions & DMs")}
+                        {notify_option(NotifyMode::All, "All messages")}
+                    </div>
+                    if props.notifications_blocked {
+                        <p class="text-xs text-red-500">{"Notifications are blocked by your browser for this site."}</p>
+                    }
+                </div>
+                <label class="flex items-center justify-between">
+                    <div>
+                        <div class="text-sm font-medium text-gray-700 dark:text-gray-200">{"Sync read position"}</div>
+                        <p class="text-xs text-gray-500 dark:text-gray-400">{"Remember what you have read on the server too."}</p>
+                    </div>
+                    <input type="checkbox" checked={props.settings.sync_read_markers} onchange={toggle_sync} class="w-4 h-4 accent-violet-600"/>
+                </label>
+                <label class="flex items-center justify-between">
+                    <div>
+                        <div class="text-sm font-medium text-gray-700 dark:text-gray-200">{"Send read receipts"}</div>
+                        <p class="text-xs text-gray-500 dark:text-gray-400">{"Let others see when you have read their messages."}</p>
+                    </div>
+                    <input type="checkbox" checked={props.settings.read_receipts} onchange={toggle_receipts} class="w-4 h-4 accent-violet-600"/>
+                </label>
+                <div class="flex justify-end pt-2">
+                    <button onclick={onclose} class="px-4 py-2 text-sm rounded-lg bg-violet-600 hover:bg-violet-700 text-white">
+                        {"Done"}
+                    </button>
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,135 @@
+use std::rc::Rc;
+
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_agent::use_bridge;
+
+use crate::services::event_bus::{Event, EventBus, Request, Topic};
+use crate::services::protocol::{MsgTypes, RoomTopic, ServerEvent, WebSocketMessage};
+
+/// Longest topic the server keeps.
+pub const MAX_TOPIC_LENGTH: usize = 250;
+
+/// What the server says when a member tries to change the topic.
+pub const DENIED: &str = "Only moderators and admins can change the topic.";
+
+/// Asks the server to set the room's topic; empty clears it.
+pub fn frame(text: String) -> WebSocketMessage {
+    WebSocketMessage {
+        message_type: MsgTypes::Topic,
+        data: Some(text),
+        data_array: None,
+        client_id: None,
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct TopicLineProps {
+    /// Sets a new topic; empty clears it. Only called when the text changed.
+    pub onchange: Callback<String>,
+    /// Whether we may change it; only moderators and admins can.
+    pub editable: bool,
+}
+
+/// The room's topic under its name, as the server last announced it; click it to
+/// change it.
+#[function_component(TopicLine)]
+pub fn topic_line(props: &TopicLineProps) -> Html {
+    let topic = use_state(RoomTopic::default);
+    let editing = use_state(|| false);
+    let input_ref = use_node_ref();
+
+    let bridge = {
+        let topic = topic.clone();
+        use_bridge::<EventBus, _>(move |event: Rc<Event>| {
+            if let Event::Server(ServerEvent::Topic(announced)) = &*event {
+                topic.set(announced.clone());
+            }
+        })
+    };
+    use_effect_with_deps(
+        move |_| {
+            bridge.send(Request::Subscribe(vec![Topic::Chat]));
+            || ()
+        },
+        (),
+    );
+
+    {
+        // Focus the input as soon as it appears.
+        let input_ref = input_ref.clone();
+        use_effect_with_deps(
+            move |editing| {
+                if **editing {
+                    if let Some(input) = input_ref.cast::<HtmlInputElement>() {
+                        let _ = input.focus();
+                        input.select();
+                    }
+                }
+                || ()
+            },
+            editing.clone(),
+        );
+    }
+
+    if *editing {
+        let onkeydown = {
+            let editing = editing.clone();
+            let onchange = props.onchange.clone();
+            let current = topic.text.clone();
+            Callback::from(move |e: KeyboardEvent| match e.key().as_str() {
+                "Enter" => {
+                    let input: HtmlInputElement = e.target_unchecked_into();
+                    let text = input.value().trim().to_string();
+                    if text != current {
+                        onchange.emit(text);
+                    }
+                    editing.set(false);
+                }
+                "Escape" => editing.set(false),
+                _ => {}
+            })
+        };
+        let onblur = {
+            let editing = editing.clone();
+            Callback::from(move |_: FocusEvent| editing.set(false))
+        };
+        return html! {
+            <input
+                ref={input_ref}
+                value={topic.text.clone()}
+                maxlength={MAX_TOPIC_LENGTH.to_string()}
+                placeholder="What's this room about?"
+                {onkeydown}
+                {onblur}
+                class="w-full max-w-md px-2 py-0.5 text-xs rounded bg-gray-100 dark:bg-gray-700 text-gray-700 dark:text-gray-200 focus:outline-none focus:ring-2 focus:ring-violet-500"
+            />
+        };
+    }
+
+    if !props.editable {
+        return html! {
+            <p title={topic.set_by.as_ref().map(|nick| format!("Set by {}", nick))} class="max-w-md text-xs truncate text-gray-500 dark:text-gray-400">
+                {&topic.text}
+            </p>
+        };
+    }
+
+    let onclick = {
+        let editing = editing.clone();
+        Callback::from(move |_| editing.set(true))
+    };
+    let title = match &topic.set_by {
+        Some(nick) => format!("Set by {} · click to change", nick),
+        None => "Click to set a topic".to_string(),
+    };
+    html! {
+        <button {onclick} {title} class="block max-w-md text-left text-xs truncate text-gray-500 dark:text-gray-400 hover:text-gray-700 dark:hover:text-gray-200">
+            if topic.text.is_empty() {
+                <span class="italic">{"Add a topic"}</span>
+            } else {
+                {&topic.text}
+            }
+        </button>
+    }
+}
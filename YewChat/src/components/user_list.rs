@@ -0,0 +1,302 @@
+use std::rc::Rc;
+
+use yew::prelude::*;
+
+use crate::components::profile::Profile;
+use crate::components::theme_toggle::ThemeToggle;
+use crate::services::avatar;
+use crate::services::protocol::{ModAction, Presence, Role, UserData};
+
+#[derive(Clone, PartialEq)]
+pub struct UserProfile {
+    pub name: String,
+    pub avatar: String,
+    pub presence: Presence,
+    pub last_seen: Option<f64>,
+    pub profile: Profile,
+    pub bot: bool,
+    pub role: Role,
+    /// When their mute runs out, while they are muted.
+    pub muted_until: Option<f64>,
+}
+
+impl From<&UserData> for UserProfile {
+    fn from(user: &UserData) -> Self {
+        Self {
+            avatar: avatar::resolve(&user.nick, user.avatar.as_deref()),
+            name: user.nick.clone(),
+            presence: user.presence,
+            last_seen: user.last_seen,
+            profile: user.profile.clone(),
+            bot: user.bot,
+            role: user.role,
+            muted_until: user.muted_until,
+        }
+    }
+}
+
+impl Presence {
+    pub fn dot_class(&self) -> &'static str {
+        match self {
+            Presence::Online => "bg-green-500",
+            Presence::Away => "bg-yellow-400",
+            Presence::Dnd => "bg-red-500",
+            Presence::Offline => "bg-gray-400",
+        }
+    }
+
+    pub fn label(&self, last_seen: Option<f64>) -> String {
+        match self {
+            Presence::Online => "Active now".into(),
+            Presence::Away => "Away".into(),
+            Presence::Dnd => "Do not disturb".into(),
+            Presence::Offline => match last_seen {
+                Some(ts) => format!("Last seen {}", time_ago(ts)),
+                None => "Offline".into(),
+            },
+        }
+    }
+}
+
+/// How long without input before we report ourselves as away.
+const AWAY_AFTER_MS: f64 = 5.0 * 60.0 * 1000.0;
+
+/// Our own presence: what the user picked, and what auto-away has made of it.
+pub struct Availability {
+    /// What we last reported to the server.
+    pub reported: Presence,
+    /// What the user picked; auto-away only kicks in when this is `Online`.
+    pub chosen: Presence,
+    last_activity: f64,
+}
+
+impl Availability {
+    pub fn new(now: f64) -> Self {
+        Self {
+            reported: Presence::Online,
+            chosen: Presence::Online,
+            last_activity: now,
+        }
+    }
+
+    /// Notes input from the user; returns what to report if they were away.
+    pub fn activity(&mut self, now: f64) -> Option<Presence> {
+        self.last_activity = now;
+        (self.reported == Presence::Away).then_some(self.chosen)
+    }
+
+    /// `Away`, to report, once there has been no input for a while.
+    pub fn check_idle(&self, now: f64) -> Option<Presence> {
+        let idle = now - self.last_activity >= AWAY_AFTER_MS;
+        (idle && self.reported == Presence::Online).then_some(Presence::Away)
+    }
+
+    pub fn choose(&mut self, presence: Presence, now: f64) {
+        self.chosen = presence;
+        self.last_activity = now;
+    }
+}
+
+fn time_ago(ts: f64) -> String {
+    let minutes = ((js_sys::Date::now() - ts) / 60_000.0).max(0.0) as u64;
+    match minutes {
+        0 => "just now".into(),
+        1..=59 => format!("{}m ago", minutes),
+        60..=1439 => format!("{}h ago", minutes / 60),
+        _ => format!("{}d ago", minutes / 1440),
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct UserListHeaderProps {
+    /// Users who aren't offline.
+    pub online: usize,
+    /// What we last reported for ourselves.
+    pub presence: Presence,
+    /// Whether we picked do not disturb.
+    pub dnd: bool,
+    pub ontogglednd: Callback<()>,
+}
+
+/// The bar above the user list, with the online count and our own availability.
+#[function_component(UserListHeader)]
+pub fn user_list_header(props: &UserListHeaderProps) -> Html {
+    html! {
+        <div class="sticky top-0 bg-violet-600 dark:bg-violet-800 text-white p-4 font-bold text-lg flex justify-between items-center">
+            <div class="flex items-center">
+                <svg class="w-6 h-6 mr-2" fill="currentColor" viewBox="0 0 20 20" xmlns="http://www.w3.org/2000/svg">
+                    <path d="M13 6a3 3 0 11-6 0 3 3 0 016 0zM18 8a2 2 0 11-4 0 2 2 0 014 0zM14 15a4 4 0 00-8 0v3h8v-3zM6 8a2 2 0 11-4 0 2 2 0 014 0zM16 18v-3a5.972 5.972 0 00-.75-2.906A3.005 3.005 0 0119 15v3h-3zM4.75 12.094A5.973 5.973 0 004 15v3H1v-3a3 3 0 013.75-2.906z"></path>
+                </svg>
+                {format!("Online Users ({})", props.online)}
+            </div>
+            <div class="flex items-center">
+                <button
+                    onclick={props.ontogglednd.reform(|_: MouseEvent| ())}
+                    class="flex items-center px-2 py-1 mr-1 text-xs font-medium rounded-full hover:bg-violet-500 dark:hover:bg-violet-700 transition-colors"
+                    title="Toggle do not disturb"
+                >
+                    <span class={classes!("w-2.5", "h-2.5", "mr-1", "rounded-full", props.presence.dot_class())}></span>
+                    {if props.dnd { "Busy" } else { "Available" }}
+                </button>
+                <ThemeToggle />
+            </div>
+        </div>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct UserListProps {
+    pub users: Rc<Vec<UserProfile>>,
+    pub current_user: String,
+    /// Our own role; moderators and admins get a menu of actions on each user.
+    pub own_role: Role,
+    /// Bumped by the idle check so "last seen" labels stay fresh.
+    pub tick: u32,
+    pub onselect: Callback<String>,
+    pub onmoderate: Callback<ModAction>,
+}
+
+fn role_badge(role: Role) -> Html {
+    let label = match role {
+        Role::Admin => "ADMIN",
+        Role::Moderator => "MOD",
+        Role::Member => return html! {},
+    };
+    html! {
+        <span class="ml-1.5 px-1 rounded text-[10px] font-semibold bg-amber-400 text-gray-900">{label}</span>
+    }
+}
+
+/// What a moderator can do to `user`, as (label, action) pairs.
+fn actions(user: &UserProfile, own_role: Role) -> Vec<(&'static str, ModAction)> {
+    let target = || user.name.clone();
+    let mut actions = vec![("Kick", ModAction::Kick { target: target() })];
+    if user.muted_until.is_some() {
+        actions.push(("Unmute", ModAction::Unmute { target: target() }));
+    } else {
+        actions.push(("Mute for 10 minutes", ModAction::Mute { target: target(), minutes: 10 }));
+        actions.push(("Mute for 1 hour", ModAction::Mute { target: target(), minutes: 60 }));
+    }
+    actions.push(("Ban", ModAction::Ban { target: target(), by_ip: false }));
+    actions.push(("Ban everyone on their IP address", ModAction::Ban { target: target(), by_ip: true }));
+    if own_role == Role::Admin {
+        actions.push(match user.role {
+            Role::Member => ("Make moderator", ModAction::Role { target: target(), role: Role::Moderator }),
+            _ => ("Remove moderator", ModAction::Role { target: target(), role: Role::Member }),
+        });
+    }
+    actions
+}
+
+#[function_component(UserList)]
+pub fn user_list(props: &UserListProps) -> Html {
+    // Whose moderation menu is open.
+    let menu_for = use_state(|| None::<String>);
+
+    html! {
+        <div class="p-3 space-y-3">
+            if props.users.is_empty() {
+                <div class="flex items-center justify-center h-20 text-gray-500 dark:text-gray-400 italic">
+                    {"No users online"}
+                </div>
+            }
+            {
+                props.users.iter().map(|u| {
+                    let is_current_user = u.name == props.current_user;
+                    let can_moderate = !is_current_user && props.own_role.outranks(u.role);
+                    let onclick = {
+                        let name = u.name.clone();
+                        let onselect = props.onselect.clone();
+                        Callback::from(move |_| onselect.emit(name.clone()))
+                    };
+                    let oncontextmenu = {
+                        let name = u.name.clone();
+                        let menu_for = menu_for.clone();
+                        Callback::from(move |e: MouseEvent| {
+                            if can_moderate {
+                                e.prevent_default();
+                                menu_for.set(Some(name.clone()));
+                            }
+                        })
+                    };
+                    let menu_open = can_moderate && menu_for.as_deref() == Some(u.name.as_str());
+                    let close_menu = {
+                        let menu_for = menu_for.clone();
+                        Callback::from(move |_| menu_for.set(None))
+                    };
+                    html!{
+                        <div class="relative">
+                            <div {onclick} {oncontextmenu} class={format!("flex items-center p-3 rounded-lg cursor-pointer {} {} {}",
+                                if is_current_user {
+                                    "bg-violet-100 dark:bg-violet-900 border-l-4 border-violet-500"
+                                } else {
+                                    "bg-white dark:bg-gray-800 hover:bg-gray-50 dark:hover:bg-gray-700"
+                                },
+                                if u.presence == Presence::Offline { "opacity-60" } else { "" },
+                                "transition-all duration-200 transform hover:translate-x-1 shadow-sm"
+                            )}>
+                                <div class="relative">
+                                    <img class="w-12 h-12 rounded-full border-2 border-gray-200 dark:border-gray-600" src={u.avatar.clone()} alt="avatar"/>
+                                    <div class={classes!("absolute", "bottom-0", "right-0", "w-3.5", "h-3.5", u.presence.dot_class(), "border-2", "border-white", "dark:border-gray-700", "rounded-full")}></div>
+                                </div>
+                                <div class="flex-grow ml-3">
+                                    <div class="flex justify-between items-center">
+                                        <div class={format!("font-medium {}",
+                                            if is_current_user {
+                                                "text-violet-700 dark:text-violet-300"
+                                            } else {
+                                                "text-gray-700 dark:text-gray-300"
+                                            }
+                                        )}>
+                                            {u.name.clone()}{if is_current_user { " (You)" } else { "" }}
+                                            if u.bot {
+                                                <span class="ml-1.5 px-1 rounded text-[10px] font-semibold bg-violet-600 text-white">{"BOT"}</span>
+                                            }
+                                            {role_badge(u.role)}
+                                        </div>
+                                        if u.muted_until.is_some() {
+                                            <span class="text-xs" title="Muted">{"🔇"}</span>
+                                        }
+                                    </div>
+                                    <div class="text-xs text-gray-500 dark:text-gray-400">
+                                        {u.presence.label(u.last_seen)}
+                                    </div>
+                                    if let Some(status) = u.profile.status() {
+                                        <div class="text-xs text-gray-600 dark:text-gray-300 truncate">{status}</div>
+                                    }
+                                </div>
+                            </div>
+                            if menu_open {
+                                <div class="absolute right-2 top-full z-20 mt-1 w-52 py-1 bg-white dark:bg-gray-700 rounded-lg shadow-xl border border-gray-200 dark:border-gray-600">
+                                    {
+                                        actions(u, props.own_role).into_iter().map(|(label, action)| {
+                                            let onclick = {
+                                                let menu_for = menu_for.clone();
+                                                let onmoderate = props.onmoderate.clone();
+                                                Callback::from(move |_| {
+                                                    menu_for.set(None);
+                                                    onmoderate.emit(action.clone());
+                                                })
+                                            };
+                                            html! {
+                                                <button {onclick} class="block w-full px-3 py-1.5 text-left text-sm text-gray-700 dark:text-gray-200 hover:bg-gray-100 dark:hover:bg-gray-600">
+                                                    {label}
+                                                </button>
+                                            }
+                                        }).collect::<Html>()
+                                    }
+                                    <button
+                                        onclick={close_menu}
+                                        class="block w-full px-3 py-1.5 text-left text-sm text-gray-400 hover:bg-gray-100 dark:hover:bg-gray-600"
+                                    >
+                                        {"Cancel"}
+                                    </button>
+                                </div>
+                            }
+                        </div>
+                    }
+                }).collect::<Html>()
+            }
+        </div>
+    }
+}
@@ -0,0 +1,374 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use wasm_bindgen::JsCast;
+use web_sys::{Element, HtmlElement};
+use yew::NodeRef;
+
+/// Height assumed for rows we haven't measured yet.
+const ESTIMATED_ROW_HEIGHT: i32 = 96;
+/// Extra pixels rendered above and below the viewport so fast scrolling doesn't
+/// show blank space.
+const OVERSCAN_PX: i32 = 600;
+/// How close to the bottom (in pixels) still counts as "at the bottom".
+const STICK_THRESHOLD_PX: i32 = 40;
+/// How close to the top (in pixels) counts as near it, time to fetch older rows.
+const NEAR_TOP_PX: i32 = 200;
+
+/// The slice of rows to render, plus the space to leave for the rest.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Window {
+    pub start: usize,
+    pub end: usize,
+    pub pad_top: i32,
+    pub pad_bottom: i32,
+}
+
+/// Windowing state for a scrollable list of variable-height rows.
+///
+/// Rows are identified by a key. Rendered rows carry a `data-row-key` attribute so
+/// `measure` can record their real height after each render; everything else uses
+/// an estimate.
+pub struct VirtualList {
+    heights: HashMap<u64, i32>,
+    scroll_top: i32,
+    viewport_height: i32,
+}
+
+impl VirtualList {
+    pub fn new() -> Self {
+        Self {
+            heights: HashMap::new(),
+            scroll_top: 0,
+            viewport_height: 1000,
+        }
+    }
+
+    fn height(&self, key: u64) -> i32 {
+        self.heights.get(&key).copied().unwrap_or(ESTIMATED_ROW_HEIGHT)
+    }
+
+    pub fn scroll_top(&self) -> i32 {
+        self.scroll_top
+    }
+
+    pub fn set_scroll_top(&mut self, top: i32) {
+        self.scroll_top = top;
+    }
+
+    /// Offset of row `index` from the top of the list.
+    pub fn offset_of(&self, keys: &[u64], index: usize) -> i32 {
+        keys[..index.min(keys.len())].iter().map(|k| self.height(*k)).sum()
+    }
+
+    /// The rows overlapping the viewport. With `pin_to_end` the viewport is assumed
+    /// to be scrolled all the way down, whatever the last known position was.
+    pub fn window(&self, keys: &[u64], pin_to_end: bool) -> Window {
+        let total = self.offset_of(keys, keys.len());
+        let top = if pin_to_end {
+            (total - self.viewport_height).max(0)
+        } else {
+            self.scroll_top
+        };
+        let (from, to) = (top - OVERSCAN_PX, top + self.viewport_height + OVERSCAN_PX);
+
+        let mut window = Window {
+            start: keys.len(),
+            end: keys.len(),
+            ..Window::default()
+        };
+        let mut y = 0;
+        for (i, key) in keys.iter().enumerate() {
+            let h = self.height(*key);
+            if window.start == keys.len() && y + h > from {
+                window.start = i;
+                window.pad_top = y;
+            }
+            if y >= to {
+                window.end = i;
+                break;
+            }
+            y += h;
+        }
+        if window.start == keys.len() {
+            window.pad_top = total;
+        }
+        window.pad_bottom = total - self.offset_of(keys, window.end);
+        window
+    }
+
+    /// Picks up the scroll position and size of `container`.
+    pub fn update_viewport(&mut self, container: &Element) {
+        self.scroll_top = container.scroll_top();
+        self.viewport_height = container.client_height();
+    }
+
+    /// Records the height of every rendered row; returns whether any changed.
+    pub fn measure(&mut self, container: &Element) -> bool {
+        let rows = match container.query_selector_all("[data-row-key]") {
+            Ok(rows) => rows,
+            Err(_) => return false,
+        };
+        let mut changed = false;
+        for i in 0..rows.length() {
+            let row = match rows.item(i).and_then(|n| n.dyn_into::<HtmlElement>().ok()) {
+                Some(row) => row,
+                None => continue,
+            };
+            let key = row.get_attribute("data-row-key").and_then(|k| k.parse().ok());
+            if let Some(key) = key {
+                let height = row.offset_height();
+                if self.heights.insert(key, height) != Some(height) {
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Drops the measurement for a row that is gone for good.
+    pub fn forget(&mut self, key: u64) {
+        self.heights.remove(&key);
+    }
+}
+
+/// What `Viewport::rendered` should do with the scroll position.
+enum ScrollAction {
+    None,
+    Bottom,
+    /// Keep the same rows on screen after older ones are inserted above, given the
+    /// list's scroll height and offset before the insert.
+    KeepAnchor { height: i32, top: i32 },
+}
+
+/// What a scroll event changed.
+pub struct Scrolled {
+    /// Whether the list started or stopped following new rows.
+    pub changed: bool,
+    pub at_bottom: bool,
+    /// Close enough to the top to fetch older rows.
+    pub near_top: bool,
+}
+
+/// A scrolling list that follows new rows while it is at the bottom, keeps the
+/// reader's place when rows are inserted above, and renders only its window.
+pub struct Viewport {
+    node: NodeRef,
+    rows: VirtualList,
+    action: ScrollAction,
+    /// Whether the list is scrolled to the end, so new rows should follow.
+    at_bottom: bool,
+    /// Rows that arrived below the fold while scrolled up.
+    new_below: usize,
+    /// The window the last `window` call handed out.
+    rendered: Cell<(usize, usize)>,
+}
+
+impl Viewport {
+    pub fn new() -> Self {
+        Self {
+            node: NodeRef::default(),
+            rows: VirtualList::new(),
+            action: ScrollAction::Bottom,
+            at_bottom: true,
+            new_below: 0,
+            rendered: Cell::new((0, 0)),
+        }
+    }
+
+    /// The scrolling element, to be rendered with this ref.
+    pub fn node(&self) -> NodeRef {
+        self.node.clone()
+    }
+
+    pub fn at_bottom(&self) -> bool {
+        self.at_bottom
+    }
+
+    /// Rows that arrived below the fold, while it is out of sight.
+    pub fn new_below(&self) -> usize {
+        if self.at_bottom { 0 } else { self.new_below }
+    }
+
+    /// Scrolls to the end after the next render.
+    pub fn follow(&mut self) {
+        self.action = ScrollAction::Bottom;
+    }
+
+    /// Stops following new rows, so they don't pull the list away from something.
+    pub fn stop_following(&mut self) {
+        self.at_bottom = false;
+    }
+
+    /// Counts a row that came in while we are reading further up.
+    pub fn arrived_below(&mut self) {
+        self.new_below += 1;
+    }
+
+    pub fn clear_below(&mut self) {
+        self.new_below = 0;
+    }
+
+    /// Records where the list is before rows are inserted above, so `rendered` can
+    /// keep the reader's place.
+    pub fn anchor(&mut self) {
+        self.action = match self.node.cast::<Element>() {
+            Some(list) if !self.at_bottom => ScrollAction::KeepAnchor {
+                height: list.scroll_height(),
+                top: list.scroll_top(),
+            },
+            _ => ScrollAction::Bottom,
+        };
+    }
+
+    /// Keeps the window on the same rows after rows were inserted above `first`,
+    /// until `rendered` fixes up the real scroll position.
+    pub fn shift_past(&mut self, keys: &[u64], first: u64) {
+        if self.at_bottom {
+            return;
+        }
+        let index = keys.iter().position(|k| *k == first).unwrap_or(0);
+        self.rows.set_scroll_top(self.rows.scroll_top() + self.rows.offset_of(keys, index));
+    }
+
+    /// Scrolls row `key` to the top of the list, whether or not it is rendered.
+    pub fn scroll_to(&self, keys: &[u64], key: u64) {
+        let index = keys.iter().position(|k| *k == key);
+        if let (Some(list), Some(index)) = (self.node.cast::<Element>(), index) {
+            list.set_scroll_top(self.rows.offset_of(keys, index));
+        }
+    }
+
+    /// The rows to render next, remembered to tell later whether they moved.
+    pub fn window(&self, keys: &[u64]) -> Window {
+        let window = self.rows.window(keys, matches!(self.action, ScrollAction::Bottom));
+        self.rendered.set((window.start, window.end));
+        window
+    }
+
+    /// Whether the rows on screen are no longer the ones rendered.
+    pub fn moved(&self, keys: &[u64]) -> bool {
+        let window = self.rows.window(keys, false);
+        (window.start, window.end) != self.rendered.get()
+    }
+
+    /// Picks up a scroll event.
+    pub fn scrolled(&mut self) -> Option<Scrolled> {
+        let list = self.node.cast::<Element>()?;
+        self.rows.update_viewport(&list);
+        let at_bottom = list.scroll_height() - list.scroll_top() - list.client_height() <= STICK_THRESHOLD_PX;
+        let changed = at_bottom != self.at_bottom || (at_bottom && self.new_below > 0);
+        self.at_bottom = at_bottom;
+        if at_bottom {
+            self.new_below = 0;
+        }
+        Some(Scrolled {
+            changed,
+            at_bottom,
+            near_top: list.scroll_top() < NEAR_TOP_PX,
+        })
+    }
+
+    /// Measures what was rendered and scrolls where it was asked to. `reveal` is a
+    /// row to bring into view, cleared once it is. Returns whether the window moved,
+    /// which takes another render to settle.
+    pub fn rendered(&mut self, keys: &[u64], reveal: &mut Option<u64>) -> bool {
+        let list = match self.node.cast::<Element>() {
+            Some(list) => list,
+            None => return false,
+        };
+        let remeasured = self.rows.measure(&list);
+        let pinned = matches!(self.action, ScrollAction::Bottom);
+        match std::mem::replace(&mut self.action, ScrollAction::None) {
+            ScrollAction::None => {}
+            ScrollAction::Bottom => list.set_scroll_top(list.scroll_height()),
+            ScrollAction::KeepAnchor { height, top } => list.set_scroll_top(top + list.scroll_height() - height),
+        }
+        if remeasured && (pinned || self.at_bottom) {
+            list.set_scroll_top(list.scroll_height());
+        }
+        if let Some(key) = *reveal {
+            // Scrolled into view once rendered; until then, the window moves to it.
+            match list.query_selector(&format!("[data-row-key=\"{}\"]", key)).ok().flatten() {
+                Some(row) => {
+                    row.scroll_into_view_with_bool(true);
+                    *reveal = None;
+                }
+                None => self.scroll_to(keys, key),
+            }
+        }
+        self.rows.update_viewport(&list);
+        self.moved(keys)
+    }
+
+    /// Drops the measurement for a row that is gone for good.
+    pub fn forget(&mut self, key: u64) {
+        self.rows.forget(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unmeasured rows are 96px, the viewport 1000px and the overscan 600px each way.
+
+    fn keys(count: u64) -> Vec<u64> {
+        (0..count).collect()
+    }
+
+    fn scrolled_to(top: i32) -> VirtualList {
+        let mut list = VirtualList::new();
+        list.set_scroll_top(top);
+        list
+    }
+
+    #[test]
+    fn the_window_covers_the_viewport_and_overscan() {
+        let keys = keys(100);
+        let window = scrolled_to(0).window(&keys, false);
+        // Down to the first row starting below 1600px.
+        assert_eq!(window, Window { start: 0, end: 17, pad_top: 0, pad_bottom: 83 * 96 });
+
+        let window = scrolled_to(4800).window(&keys, false);
+        // From the first row reaching past 4200px to the first starting below 6400px.
+        assert_eq!(window, Window { start: 43, end: 67, pad_top: 43 * 96, pad_bottom: 33 * 96 });
+    }
+
+    #[test]
+    fn the_overscan_stops_at_either_end() {
+        let keys = keys(100);
+        let window = scrolled_to(300).window(&keys, false);
+        assert_eq!((window.start, window.pad_top), (0, 0));
+
+        // Pinned to the end, the viewport shows the last 1000px whatever the scroll position.
+        let window = scrolled_to(0).window(&keys, true);
+        assert_eq!(window, Window { start: 83, end: 100, pad_top: 83 * 96, pad_bottom: 0 });
+
+        // A list shorter than the viewport is shown whole.
+        let window = scrolled_to(0).window(&keys[..5], true);
+        assert_eq!(window, Window { start: 0, end: 5, pad_top: 0, pad_bottom: 0 });
+    }
+
+    #[test]
+    fn an_empty_list_renders_nothing() {
+        assert_eq!(scrolled_to(0).window(&[], false), Window::default());
+        assert_eq!(scrolled_to(500).window(&[], true), Window::default());
+    }
+
+    #[test]
+    fn scrolling_past_the_end_leaves_only_padding() {
+        let keys = keys(10);
+        let window = scrolled_to(5000).window(&keys, false);
+        assert_eq!(window, Window { start: 10, end: 10, pad_top: 10 * 96, pad_bottom: 0 });
+    }
+
+    #[test]
+    fn measured_rows_move_the_window() {
+        let keys = keys(100);
+        let mut list = scrolled_to(0);
+        list.heights.insert(0, 1600);
+        // The first row fills the viewport and overscan by itself.
+        assert_eq!(list.window(&keys, false), Window { start: 0, end: 1, pad_top: 0, pad_bottom: 99 * 96 });
+    }
+}
@@ -39,6 +39,8 @@ pub type ThemeContext = Rc<ThemeContextInner>;
 #[derive(Debug, PartialEq)]
 pub struct UserInner {
     pub username: RefCell<String>,
+    /// Claims the role the server has configured for `username`; usually empty.
+    pub key: RefCell<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -51,6 +53,7 @@ fn main() -> Html {
     let user_ctx = use_state(|| {
         Rc::new(UserInner {
             username: RefCell::new("initial".into()),
+            key: RefCell::new(String::new()),
         })
     });
 
@@ -0,0 +1,82 @@
+use crate::services::websocket::SERVER_HOST;
+
+/// FNV-1a, so the same name gets the same colour in every browser.
+fn hash(name: &str) -> u32 {
+    name.bytes().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+/// Up to two letters: the first of each of the first two words, or the first two
+/// characters of a single word.
+fn initials(name: &str) -> String {
+    let words: Vec<&str> = name.split_whitespace().collect();
+    let initials: String = match words.as_slice() {
+        [] => "?".into(),
+        [word] => word.chars().take(2).collect(),
+        [first, second, ..] => first.chars().take(1).chain(second.chars().take(1)).collect(),
+    };
+    initials.to_uppercase()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Percent-encodes everything that isn't safe to put in a `data:` URL as-is.
+fn encode_uri_component(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'!' | b'~' | b'*' | b'\'' | b'(' | b')' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// An SVG avatar showing `name`'s initials on a colour derived from the name, as a
+/// `data:` URL that can be used anywhere an image URL is expected.
+pub fn generated(name: &str) -> String {
+    let hue = hash(name) % 360;
+    let svg = format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 64 64">"#,
+            r#"<rect width="64" height="64" fill="hsl({hue},65%,55%)"/>"#,
+            r#"<text x="50%" y="50%" dy=".35em" text-anchor="middle" fill="white" "#,
+            r#"font-family="Poppins,sans-serif" font-size="26" font-weight="600">{initials}</text>"#,
+            r#"</svg>"#
+        ),
+        hue = hue,
+        initials = escape_xml(&initials(name)),
+    );
+    format!("data:image/svg+xml,{}", encode_uri_component(&svg))
+}
+
+/// The avatar to show for `name`: their uploaded image if they have one, otherwise
+/// a generated one. Only paths on the chat server are loaded, never other URLs.
+pub fn resolve(name: &str, uploaded: Option<&str>) -> String {
+    match uploaded {
+        Some(path) if path.starts_with('/') => format!("http://{}{}", SERVER_HOST, path),
+        _ => generated(name),
+    }
+}
+
+/// Uploads an avatar image and returns the server path to announce with an
+/// `avatar` message.
+pub async fn upload(file: web_sys::File) -> Result<String, String> {
+    let response = reqwasm::http::Request::post(&format!("http://{}/avatars", SERVER_HOST))
+        .header("Content-Type", &file.type_())
+        .body(file)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.ok() {
+        let reason = response.text().await.unwrap_or_default();
+        return Err(format!("{} {}", response.status(), reason));
+    }
+    response.text().await.map_err(|e| e.to_string())
+}
@@ -1,44 +1,110 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use yew_agent::{Agent, AgentLink, Context, HandlerId};
+use std::collections::HashMap;
+use std::rc::Rc;
+use yew::Callback;
+use yew_agent::{Agent, AgentLink, Bridge, Bridged, Context, HandlerId};
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::services::protocol::ServerEvent;
+
+/// What a subscriber can ask to hear about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Topic {
+    /// The WebSocket opening and closing.
+    Connection,
+    /// Messages, history, read markers and receipts.
+    Chat,
+    /// Who is around and what they are up to.
+    Presence,
+    /// Events that may warrant a notification: new messages.
+    Notifications,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Open,
+    Closed,
+}
+
+#[derive(Debug)]
+pub enum Event {
+    Connection(ConnectionState),
+    Server(ServerEvent),
+}
+
+impl Event {
+    fn topics(&self) -> &'static [Topic] {
+        match self {
+            Event::Connection(_) => &[Topic::Connection],
+            Event::Server(ServerEvent::Users(_)) => &[Topic::Presence],
+            Event::Server(ServerEvent::Message(_)) => &[Topic::Chat, Topic::Notifications],
+            Event::Server(_) => &[Topic::Chat],
+        }
+    }
+}
+
+#[derive(Debug)]
 pub enum Request {
-    EventBusMsg(String),
+    /// Sets the topics the sending bridge receives.
+    Subscribe(Vec<Topic>),
+    Publish(Event),
 }
 
 pub struct EventBus {
     link: AgentLink<EventBus>,
-    subscribers: HashSet<HandlerId>,
+    subscribers: HashMap<HandlerId, Vec<Topic>>,
+    /// The latest connection event, replayed to new `Connection` subscribers.
+    connection: Option<Rc<Event>>,
+}
+
+impl EventBus {
+    /// Bridges to the bus with `callback` receiving events on `topics`.
+    pub fn subscribe(topics: &[Topic], callback: Callback<Rc<Event>>) -> Box<dyn Bridge<EventBus>> {
+        let mut bridge = EventBus::bridge(callback);
+        bridge.send(Request::Subscribe(topics.to_vec()));
+        bridge
+    }
 }
 
 impl Agent for EventBus {
     type Reach = Context<Self>;
     type Message = ();
     type Input = Request;
-    type Output = String;
+    type Output = Rc<Event>;
 
     fn create(link: AgentLink<Self>) -> Self {
         Self {
             link,
-            subscribers: HashSet::new(),
+            subscribers: HashMap::new(),
+            connection: None,
         }
     }
 
     fn update(&mut self, _msg: Self::Message) {}
 
-    fn handle_input(&mut self, msg: Self::Input, _id: HandlerId) {
+    fn handle_input(&mut self, msg: Self::Input, id: HandlerId) {
         match msg {
-            Request::EventBusMsg(s) => {
-                for sub in self.subscribers.iter() {
-                    self.link.respond(*sub, s.clone())
+            Request::Subscribe(topics) => {
+                if let (Some(event), true) = (&self.connection, topics.contains(&Topic::Connection)) {
+                    self.link.respond(id, event.clone());
+                }
+                self.subscribers.insert(id, topics);
+            }
+            Request::Publish(event) => {
+                let event = Rc::new(event);
+                if let Event::Connection(_) = *event {
+                    self.connection = Some(event.clone());
+                }
+                for (sub, topics) in self.subscribers.iter() {
+                    if event.topics().iter().any(|t| topics.contains(t)) {
+                        self.link.respond(*sub, event.clone())
+                    }
                 }
             }
         }
     }
 
     fn connected(&mut self, id: HandlerId) {
-        self.subscribers.insert(id);
+        self.subscribers.entry(id).or_default();
     }
 
     fn disconnected(&mut self, id: HandlerId) {
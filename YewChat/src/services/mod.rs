@@ -1,2 +1,11 @@
+pub mod avatar;
+pub mod moderation;
+pub mod notifications;
+pub mod offline;
+pub mod outbox;
+pub mod protocol;
+pub mod read_markers;
+pub mod search;
+pub mod timeline;
 pub mod websocket;
 pub mod event_bus;
@@ -0,0 +1,56 @@
+//! What moderation notices mean for us, and how the moderation log reads.
+
+use wasm_bindgen::JsValue;
+
+use crate::services::protocol::{AuditEntry, ModNotice};
+
+/// What we do about a moderation notice.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// We are out of the room for good, for this reason.
+    Leave(String),
+    /// Tell the user. `failed` is a message of ours the server turned away.
+    Notice { text: String, failed: Option<String> },
+}
+
+pub fn outcome(notice: &ModNotice) -> Outcome {
+    let notice_only = |text: String| Outcome::Notice { text, failed: None };
+    match notice {
+        ModNotice::Kicked { by } => Outcome::Leave(format!("{} removed you from the room.", by)),
+        ModNotice::Banned { by } => Outcome::Leave(match by {
+            Some(by) => format!("{} banned you from the room.", by),
+            None => "You are banned from this room.".into(),
+        }),
+        ModNotice::Muted { by, until, client_id } => Outcome::Notice {
+            text: match by {
+                Some(by) => format!("{} muted you until {}.", by, clock_time(*until)),
+                None => format!("You are muted until {}.", clock_time(*until)),
+            },
+            failed: client_id.clone(),
+        },
+        ModNotice::Unmuted { by } => notice_only(format!("{} unmuted you.", by)),
+        ModNotice::Denied { reason } => notice_only(reason.clone()),
+    }
+}
+
+pub fn clock_time(ts: f64) -> String {
+    js_sys::Date::new(&JsValue::from_f64(ts)).to_locale_time_string("default").into()
+}
+
+/// The `/audit` output, oldest action first.
+pub fn audit_report(entries: &[AuditEntry]) -> String {
+    let mut lines = vec!["Recent moderator actions:".to_string()];
+    lines.extend(entries.iter().map(audit_line));
+    if entries.is_empty() {
+        lines.push("Nothing yet.".into());
+    }
+    lines.join("\n")
+}
+
+fn audit_line(entry: &AuditEntry) -> String {
+    let mut line = format!("{} · {} {} {}", clock_time(entry.time), entry.actor, entry.action, entry.target);
+    if let Some(detail) = &entry.detail {
+        line.push_str(&format!(" ({})", detail));
+    }
+    line
+}
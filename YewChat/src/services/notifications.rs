@@ -0,0 +1,68 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Notification, NotificationOptions, NotificationPermission};
+
+fn supported() -> bool {
+    web_sys::window()
+        .map(|w| js_sys::Reflect::has(&w, &"Notification".into()).unwrap_or(false))
+        .unwrap_or(false)
+}
+
+/// Whether the chat tab is in the background, where new messages go unseen.
+pub fn document_hidden() -> bool {
+    web_sys::window()
+        .and_then(|w| w.document())
+        .is_some_and(|d| d.hidden())
+}
+
+pub fn permission_granted() -> bool {
+    supported() && Notification::permission() == NotificationPermission::Granted
+}
+
+/// Asks the browser for permission to show notifications; resolves to whether we
+/// got it.
+pub async fn request_permission() -> bool {
+    if !supported() {
+        return false;
+    }
+    if permission_granted() {
+        return true;
+    }
+    match Notification::request_permission() {
+        Ok(promise) => JsFuture::from(promise)
+            .await
+            .ok()
+            .and_then(|result| result.as_string())
+            .is_some_and(|result| result == "granted"),
+        Err(_) => false,
+    }
+}
+
+/// Shows a desktop notification that brings the chat tab to the front when clicked.
+pub fn notify(title: &str, body: &str, icon: &str) {
+    if !permission_granted() {
+        return;
+    }
+    let options = NotificationOptions::new();
+    options.set_body(body);
+    options.set_icon(icon);
+    // Later messages from the same sender replace the previous notification.
+    options.set_tag(title);
+    let notification = match Notification::new_with_options(title, &options) {
+        Ok(notification) => notification,
+        Err(e) => {
+            log::debug!("could not show notification: {:?}", e);
+            return;
+        }
+    };
+    let onclick = {
+        let notification = notification.clone();
+        Closure::once_into_js(move || {
+            if let Some(window) = web_sys::window() {
+                let _ = window.focus();
+            }
+            notification.close();
+        })
+    };
+    notification.set_onclick(Some(onclick.unchecked_ref()));
+}
@@ -0,0 +1,135 @@
+use std::rc::Rc;
+
+use js_sys::Promise;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbRequest, IdbTransactionMode};
+
+use crate::services::outbox::Outgoing;
+use crate::services::protocol::MessageData;
+use crate::services::timeline;
+
+const DB_NAME: &str = "yewchat";
+const DB_VERSION: u32 = 1;
+/// One object store of JSON values, keyed like the localStorage entries.
+const STORE: &str = "cache";
+/// How many of the newest messages are kept for the next visit.
+const CACHED_MESSAGES: usize = 200;
+
+fn outbox_key(username: &str) -> String {
+    format!("outbox:{}", username)
+}
+
+fn messages_key(username: &str) -> String {
+    format!("messages:{}", username)
+}
+
+fn describe(e: JsValue) -> String {
+    e.as_string().unwrap_or_else(|| format!("{:?}", e))
+}
+
+/// Waits for an IndexedDB request to finish and returns its result.
+async fn settle(request: &IdbRequest) -> Result<JsValue, String> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    JsFuture::from(promise).await.map_err(|_| {
+        request
+            .error()
+            .ok()
+            .flatten()
+            .map_or_else(|| "request failed".into(), |e| e.message())
+    })?;
+    request.result().map_err(describe)
+}
+
+/// What the last visit left behind.
+pub struct Snapshot {
+    /// Messages that were still waiting to go out.
+    pub outbox: Vec<Outgoing>,
+    /// The newest messages we had, oldest first.
+    pub messages: Vec<MessageData>,
+}
+
+/// Keeps our unsent messages and the latest history in IndexedDB, so neither is
+/// lost with the tab.
+#[derive(Clone)]
+pub struct OfflineStore {
+    db: IdbDatabase,
+    username: String,
+}
+
+impl OfflineStore {
+    /// Opens the database and reads back what was saved for `username`.
+    pub async fn restore(username: &str) -> Result<(Self, Snapshot), String> {
+        let factory = web_sys::window()
+            .and_then(|w| w.indexed_db().ok().flatten())
+            .ok_or("IndexedDB is not available")?;
+        let request = factory.open_with_u32(DB_NAME, DB_VERSION).map_err(describe)?;
+        let upgrade = {
+            let request = request.clone();
+            Closure::wrap(Box::new(move || {
+                if let Ok(db) = request.result() {
+                    if let Err(e) = db.unchecked_into::<IdbDatabase>().create_object_store(STORE) {
+                        log::error!("creating the {} store: {:?}", STORE, e);
+                    }
+                }
+            }) as Box<dyn FnMut()>)
+        };
+        request.set_onupgradeneeded(Some(upgrade.as_ref().unchecked_ref()));
+        let db: IdbDatabase = settle(&request).await?.unchecked_into();
+
+        let store = Self {
+            db,
+            username: username.to_string(),
+        };
+        let snapshot = Snapshot {
+            outbox: store.load(&outbox_key(username)).await?.unwrap_or_default(),
+            messages: store.load(&messages_key(username)).await?.unwrap_or_default(),
+        };
+        Ok((store, snapshot))
+    }
+
+    async fn load<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, String> {
+        let request = self
+            .db
+            .transaction_with_str(STORE)
+            .and_then(|tx| tx.object_store(STORE))
+            .and_then(|store| store.get(&JsValue::from_str(key)))
+            .map_err(describe)?;
+        match settle(&request).await?.as_string() {
+            Some(raw) => serde_json::from_str(&raw).map(Some).map_err(|e| e.to_string()),
+            None => Ok(None),
+        }
+    }
+
+    /// Writes `value` under `key`; the transaction finishes on its own.
+    fn save<T: Serialize + ?Sized>(&self, key: &str, value: &T) {
+        let raw = JsValue::from_str(&serde_json::to_string(value).unwrap());
+        let written = self
+            .db
+            .transaction_with_str_and_mode(STORE, IdbTransactionMode::Readwrite)
+            .and_then(|tx| tx.object_store(STORE))
+            .and_then(|store| store.put_with_key(&raw, &JsValue::from_str(key)));
+        if let Err(e) = written {
+            log::error!("saving {}: {:?}", key, e);
+        }
+    }
+
+    pub fn save_outbox(&self, entries: &[Outgoing]) {
+        self.save(&outbox_key(&self.username), entries);
+    }
+
+    /// Keeps the newest messages the server has stored; pending ones live in the outbox.
+    pub fn save_messages(&self, messages: &[Rc<MessageData>]) {
+        let confirmed = timeline::confirmed(messages);
+        let newest: Vec<&MessageData> = confirmed[confirmed.len().saturating_sub(CACHED_MESSAGES)..]
+            .iter()
+            .map(|m| m.as_ref())
+            .collect();
+        self.save(&messages_key(&self.username), &newest);
+    }
+}
@@ -0,0 +1,241 @@
+use serde::{Deserialize, Serialize};
+
+use crate::services::protocol::MessageData;
+
+/// Where an outgoing message is on its way to the server.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Delivery {
+    /// Sent or waiting for the connection, not acknowledged yet.
+    Pending,
+    /// Not acknowledged in time; only goes out again when the user retries.
+    Failed,
+}
+
+/// A message of ours the server hasn't acknowledged.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Outgoing {
+    pub client_id: String,
+    pub text: String,
+    pub mentions: Vec<String>,
+    pub delivery: Delivery,
+    /// When it last went out over an open socket, if it has.
+    pub sent_at: Option<f64>,
+}
+
+impl Outgoing {
+    /// How it shows in the list, from `from`, until the server has stored it.
+    pub fn pending_copy(&self, from: &str) -> MessageData {
+        MessageData {
+            id: 0,
+            from: from.to_string(),
+            message: self.text.clone(),
+            mentions: self.mentions.clone(),
+            client_id: Some(self.client_id.clone()),
+            edited: false,
+            time: 0.0,
+            bot: false,
+            avatar: None,
+        }
+    }
+}
+
+/// When to send each of `count` queued messages, in milliseconds from now: `burst` of
+/// them straight away, the rest spaced out at `per_second`.
+pub fn pace(count: usize, burst: usize, per_second: f64) -> Vec<u32> {
+    (0..count)
+        .map(|i| match i.checked_sub(burst) {
+            None => 0,
+            Some(behind) => ((behind + 1) as f64 * 1000.0 / per_second).ceil() as u32,
+        })
+        .collect()
+}
+
+/// Our unacknowledged messages, oldest first.
+#[derive(Default)]
+pub struct Outbox {
+    entries: Vec<Outgoing>,
+    next_seq: u32,
+}
+
+impl Outbox {
+    /// Queues a new message, written at `now`, and returns its client ID.
+    pub fn push(&mut self, text: String, mentions: Vec<String>, now: f64) -> String {
+        // The clock keeps IDs unique across reloads, the counter within a millisecond.
+        let client_id = format!("{:x}-{:x}", now as u64, self.next_seq);
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.entries.push(Outgoing {
+            client_id: client_id.clone(),
+            text,
+            mentions,
+            delivery: Delivery::Pending,
+            sent_at: None,
+        });
+        client_id
+    }
+
+    /// Takes back messages queued during an earlier visit, ahead of anything newer.
+    /// They go out with the rest on the next connection; returns the ones added.
+    pub fn restore(&mut self, entries: Vec<Outgoing>) -> Vec<Outgoing> {
+        let restored: Vec<Outgoing> = entries
+            .into_iter()
+            .filter(|e| self.get(&e.client_id).is_none())
+            .map(|e| Outgoing {
+                delivery: Delivery::Pending,
+                sent_at: None,
+                ..e
+            })
+            .collect();
+        self.entries.splice(0..0, restored.iter().cloned());
+        restored
+    }
+
+    pub fn get(&self, client_id: &str) -> Option<&Outgoing> {
+        self.entries.iter().find(|e| e.client_id == client_id)
+    }
+
+    fn get_mut(&mut self, client_id: &str) -> Option<&mut Outgoing> {
+        self.entries.iter_mut().find(|e| e.client_id == client_id)
+    }
+
+    pub fn mark_sent(&mut self, client_id: &str, now: f64) {
+        if let Some(entry) = self.get_mut(client_id) {
+            entry.sent_at = Some(now);
+        }
+    }
+
+    /// Drops a message the server has stored; returns whether we were waiting on it.
+    pub fn acknowledge(&mut self, client_id: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.client_id != client_id);
+        self.entries.len() != before
+    }
+
+    pub fn fail(&mut self, client_id: &str) -> bool {
+        match self.get_mut(client_id) {
+            Some(entry) if entry.delivery == Delivery::Pending => {
+                entry.delivery = Delivery::Failed;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Puts a failed message back in the queue.
+    pub fn retry(&mut self, client_id: &str) -> bool {
+        match self.get_mut(client_id) {
+            Some(entry) if entry.delivery == Delivery::Failed => {
+                entry.delivery = Delivery::Pending;
+                entry.sent_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Client IDs of everything still to be (re)sent.
+    pub fn pending(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|e| e.delivery == Delivery::Pending)
+            .map(|e| e.client_id.clone())
+            .collect()
+    }
+
+    pub fn entries(&self) -> &[Outgoing] {
+        &self.entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: f64 = 1_700_000_000_000.0;
+
+    fn outbox(texts: &[&str]) -> (Outbox, Vec<String>) {
+        let mut outbox = Outbox::default();
+        let ids = texts.iter().map(|text| outbox.push(text.to_string(), vec![], NOW)).collect();
+        (outbox, ids)
+    }
+
+    fn texts(outbox: &Outbox) -> Vec<&str> {
+        outbox.entries().iter().map(|e| e.text.as_str()).collect()
+    }
+
+    #[test]
+    fn push_gives_each_message_its_own_id() {
+        let (outbox, ids) = outbox(&["one", "two"]);
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(outbox.pending(), ids);
+        let entry = outbox.get(&ids[0]).unwrap();
+        assert_eq!(entry.delivery, Delivery::Pending);
+        assert_eq!(entry.sent_at, None);
+    }
+
+    #[test]
+    fn acknowledge_drops_only_the_matching_message() {
+        let (mut outbox, ids) = outbox(&["one", "two", "three"]);
+        assert!(outbox.acknowledge(&ids[1]));
+        assert_eq!(texts(&outbox), ["one", "three"]);
+        // A second ack, or one for a message we never sent, changes nothing.
+        assert!(!outbox.acknowledge(&ids[1]));
+        assert!(!outbox.acknowledge("someone-else"));
+        assert_eq!(texts(&outbox), ["one", "three"]);
+    }
+
+    #[test]
+    fn unacknowledged_messages_go_out_again_on_reconnect() {
+        let (mut outbox, ids) = outbox(&["one", "two"]);
+        outbox.mark_sent(&ids[0], NOW + 5.0);
+        assert_eq!(outbox.get(&ids[0]).unwrap().sent_at, Some(NOW + 5.0));
+        // The connection dropped before either was acknowledged: both are resent.
+        assert_eq!(outbox.pending(), ids);
+    }
+
+    #[test]
+    fn failed_messages_wait_for_a_retry() {
+        let (mut outbox, ids) = outbox(&["one", "two"]);
+        outbox.mark_sent(&ids[0], NOW);
+        assert!(outbox.fail(&ids[0]));
+        assert!(!outbox.fail(&ids[0]));
+        assert_eq!(outbox.get(&ids[0]).unwrap().delivery, Delivery::Failed);
+        assert_eq!(outbox.pending(), [ids[1].clone()]);
+        // Still in the outbox, so it can be retried or acknowledged late.
+        assert_eq!(texts(&outbox), ["one", "two"]);
+
+        assert!(outbox.retry(&ids[0]));
+        assert!(!outbox.retry(&ids[0]));
+        let entry = outbox.get(&ids[0]).unwrap();
+        assert_eq!(entry.delivery, Delivery::Pending);
+        assert_eq!(entry.sent_at, None);
+        assert_eq!(outbox.pending(), ids);
+    }
+
+    #[test]
+    fn only_pending_messages_can_fail_and_only_failed_ones_be_retried() {
+        let (mut outbox, ids) = outbox(&["one"]);
+        assert!(!outbox.retry(&ids[0]));
+        assert!(!outbox.fail("missing"));
+        assert!(!outbox.retry("missing"));
+    }
+
+    #[test]
+    fn restore_queues_earlier_messages_first_and_skips_known_ones() {
+        let (earlier, earlier_ids) = outbox(&["old one", "old two"]);
+        let mut saved = earlier.entries().to_vec();
+        saved[1].delivery = Delivery::Failed;
+        saved[1].sent_at = Some(NOW);
+
+        let mut outbox = Outbox::default();
+        let id = outbox.push("new".into(), vec![], NOW + 1000.0);
+        let restored = outbox.restore(saved.clone());
+        assert_eq!(restored.len(), 2);
+        assert_eq!(texts(&outbox), ["old one", "old two", "new"]);
+        // Restored messages are tried again, whatever state they were saved in.
+        assert_eq!(outbox.pending(), [earlier_ids[0].clone(), earlier_ids[1].clone(), id]);
+        assert_eq!(outbox.get(&earlier_ids[1]).unwrap().sent_at, None);
+
+        assert!(outbox.restore(saved).is_empty());
+        assert_eq!(texts(&outbox), ["old one", "old two", "new"]);
+    }
+}
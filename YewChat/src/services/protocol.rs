@@ -0,0 +1,110 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+pub use chat_protocol::{
+    Ack, AuditEntry, Beat, ErrorCode, Features, Heartbeat, Hello, MessageData, MessageEdit, ModAction, ModNotice, MsgTypes, Pin,
+    ReadMarker, Receipt, Role, RoomTopic, SearchResults, ServerError, WebSocketMessage, CLOSE_REMOVED, CLOSE_TOO_MANY_CONNECTIONS,
+    KEY_PREFIX,
+    PING_INTERVAL_MS, PROTOCOL_VERSION, SILENCE_TIMEOUT_MS,
+};
+
+use crate::components::profile::Profile;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Presence {
+    Online,
+    Away,
+    Dnd,
+    Offline,
+}
+
+impl Presence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Presence::Online => "online",
+            Presence::Away => "away",
+            Presence::Dnd => "dnd",
+            Presence::Offline => "offline",
+        }
+    }
+}
+
+/// An entry of the `users` broadcast.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserData {
+    pub nick: String,
+    pub presence: Presence,
+    pub last_seen: Option<f64>,
+    #[serde(default)]
+    pub profile: Profile,
+    /// Server path of an uploaded avatar.
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub bot: bool,
+    #[serde(default)]
+    pub role: Role,
+    /// When their mute ends, if they are muted.
+    pub muted_until: Option<f64>,
+}
+
+/// A frame from the server, decoded.
+#[derive(Debug)]
+pub enum ServerEvent {
+    Users(Vec<UserData>),
+    Message(MessageData),
+    History(Vec<MessageData>),
+    Read(Receipt),
+    Receipts(Vec<Receipt>),
+    Marker(ReadMarker),
+    Ack(Ack),
+    /// A message as it reads after its sender edited it.
+    Edit(MessageData),
+    Search(SearchResults),
+    /// Every pinned message, newest pin last.
+    Pins(Vec<Pin>),
+    Topic(RoomTopic),
+    Moderation(ModNotice),
+    /// A message was deleted, by ID.
+    Delete(u64),
+    /// Recent moderator actions, oldest first.
+    Audit(Vec<AuditEntry>),
+    /// A frame of ours the server turned away, such as one over its rate limit.
+    Error(ServerError),
+    /// Who the server is and what it allows, first thing on every connection.
+    Hello(Hello),
+    /// The answer to a heartbeat `ping`; `WebsocketService` keeps it to itself.
+    Pong,
+}
+
+fn parse<T: DeserializeOwned>(data: &str) -> Result<T, String> {
+    serde_json::from_str(data).map_err(|e| e.to_string())
+}
+
+impl ServerEvent {
+    pub fn decode(frame: &str) -> Result<Self, String> {
+        let frame: WebSocketMessage = parse(frame)?;
+        let data = frame.data.unwrap_or_default();
+        Ok(match frame.message_type {
+            MsgTypes::Users => ServerEvent::Users(parse(&data)?),
+            MsgTypes::Message => ServerEvent::Message(parse(&data)?),
+            MsgTypes::History => ServerEvent::History(parse(&data)?),
+            MsgTypes::Read => ServerEvent::Read(parse(&data)?),
+            MsgTypes::Receipts => ServerEvent::Receipts(parse(&data)?),
+            MsgTypes::Marker => ServerEvent::Marker(parse(&data)?),
+            MsgTypes::Ack => ServerEvent::Ack(parse(&data)?),
+            MsgTypes::Edit => ServerEvent::Edit(parse(&data)?),
+            MsgTypes::Search => ServerEvent::Search(parse(&data)?),
+            MsgTypes::Pins => ServerEvent::Pins(parse(&data)?),
+            MsgTypes::Topic => ServerEvent::Topic(parse(&data)?),
+            MsgTypes::Moderation => ServerEvent::Moderation(parse(&data)?),
+            MsgTypes::Delete => ServerEvent::Delete(parse(&data)?),
+            MsgTypes::Audit => ServerEvent::Audit(parse(&data)?),
+            MsgTypes::Error => ServerEvent::Error(parse(&data)?),
+            MsgTypes::Hello => ServerEvent::Hello(parse(&data)?),
+            MsgTypes::Pong => ServerEvent::Pong,
+            other => return Err(format!("unexpected {:?} frame from the server", other)),
+        })
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::services::protocol::{MessageData, ReadMarker};
+
+/// The conversation everyone is in until there are rooms.
+pub const DEFAULT_CONVERSATION: &str = "general";
+
+fn storage_key(username: &str) -> String {
+    format!("read-markers:{}", username)
+}
+
+fn load_all(username: &str) -> HashMap<String, u64> {
+    web_sys::window()
+        .and_then(|w| w.local_storage().ok().flatten())
+        .and_then(|s| s.get_item(&storage_key(username)).ok().flatten())
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+/// ID of the newest message `username` has seen in `conversation`.
+pub fn load(username: &str, conversation: &str) -> Option<u64> {
+    load_all(username).get(conversation).copied()
+}
+
+pub fn save(username: &str, conversation: &str, last_read: u64) {
+    let mut markers = load_all(username);
+    markers.insert(conversation.to_string(), last_read);
+    if let Some(storage) = web_sys::window().and_then(|w| w.local_storage().ok().flatten()) {
+        let _ = storage.set_item(&storage_key(username), &serde_json::to_string(&markers).unwrap());
+    }
+}
+
+/// How far we have read in the conversation, and what has come in since.
+pub struct ReadState {
+    /// ID of the newest message we have seen.
+    pub last_read: Option<u64>,
+    /// Where the "New messages" divider goes: after the message with this ID.
+    pub divider_after: Option<u64>,
+    /// IDs of mentions of us that came in out of sight, oldest first.
+    pub unread_mentions: Vec<u64>,
+    /// The document title without an unread count.
+    page_title: String,
+}
+
+impl ReadState {
+    pub fn load(username: &str, page_title: String) -> Self {
+        Self {
+            last_read: load(username, DEFAULT_CONVERSATION),
+            divider_after: None,
+            unread_mentions: vec![],
+            page_title,
+        }
+    }
+
+    /// Messages from others we haven't seen yet.
+    pub fn unread_count(&self, messages: &[Rc<MessageData>], me: &str) -> usize {
+        messages
+            .iter()
+            .filter(|m| m.from != me && self.last_read.is_none_or(|read| m.id > read))
+            .count()
+    }
+
+    /// Whether anything after our marker is still to be read.
+    pub fn behind(&self, newest: Option<u64>) -> bool {
+        newest.is_some_and(|newest| self.last_read.is_none_or(|read| newest > read))
+    }
+
+    /// Moves our marker up to `newest` and forgets the mentions before it. Returns
+    /// the new marker if it moved, to be shared with the server.
+    pub fn mark_read(&mut self, username: &str, newest: Option<u64>) -> Option<u64> {
+        self.unread_mentions.clear();
+        let newest = newest.filter(|_| self.behind(newest))?;
+        self.last_read = Some(newest);
+        save(username, DEFAULT_CONVERSATION, newest);
+        Some(newest)
+    }
+
+    /// Takes a marker another of our devices moved, if it is further along.
+    pub fn sync(&mut self, username: &str, marker: &ReadMarker) {
+        if marker.conversation == DEFAULT_CONVERSATION && self.behind(Some(marker.last_read)) {
+            self.last_read = Some(marker.last_read);
+            save(username, DEFAULT_CONVERSATION, marker.last_read);
+        }
+    }
+
+    /// Notes a message from someone else that came in while we weren't looking.
+    pub fn missed(&mut self, message: &MessageData, me: &str) {
+        if message.mentions.iter().any(|m| m == me) {
+            self.unread_mentions.push(message.id);
+        }
+    }
+
+    /// Puts the "New messages" divider after our marker, unless it is already up.
+    pub fn hold_divider(&mut self) {
+        if self.divider_after.is_none() {
+            self.divider_after = self.last_read;
+        }
+    }
+
+    /// Puts the "New messages" divider after our marker if anything past it is from
+    /// someone else.
+    pub fn place_divider(&mut self, messages: &[Rc<MessageData>], me: &str) {
+        if let Some(read) = self.last_read {
+            if messages.iter().any(|m| m.id > read && m.from != me) {
+                self.divider_after = Some(read);
+            }
+        }
+    }
+
+    /// The first message from someone else after the "New messages" divider.
+    pub fn first_unread(&self, messages: &[Rc<MessageData>], me: &str) -> Option<u64> {
+        self.divider_after
+            .and_then(|read| messages.iter().find(|m| m.id > read && m.from != me).map(|m| m.id))
+    }
+
+    /// Takes down the divider and the mention badge.
+    pub fn clear(&mut self) {
+        self.divider_after = None;
+        self.unread_mentions.clear();
+    }
+
+    /// Shows `unread` in the tab title, or just the title when there is nothing.
+    pub fn show_unread(&self, unread: usize) {
+        if let Some(document) = web_sys::window().and_then(|w| w.document()) {
+            if unread > 0 {
+                document.set_title(&format!("({}) {}", unread, self.page_title));
+            } else {
+                document.set_title(&self.page_title);
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Search queries: words to look for plus `from:`, `in:`, `before:`, `after:` and
+//! `has:image` filters. The server reads them the same way, so searching the local
+//! cache finds what the server would.
+
+use crate::services::protocol::MessageData;
+
+/// The room everyone is in, the only one `in:` can match for now.
+pub const ROOM: &str = "general";
+
+const DAY_MS: f64 = 86_400_000.0;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Query {
+    /// Lowercased words that must all appear in the text.
+    pub words: Vec<String>,
+    /// Lowercased nick of the sender.
+    pub from: Option<String>,
+    pub room: Option<String>,
+    /// Only messages from before this time, in milliseconds since the epoch.
+    pub before: Option<f64>,
+    /// Only messages from this time on.
+    pub after: Option<f64>,
+    pub has_image: bool,
+}
+
+impl Query {
+    /// Filters are `key:value` tokens; anything else, including a filter we can't
+    /// read, is a word to look for.
+    pub fn parse(input: &str) -> Self {
+        let mut query = Self::default();
+        for token in input.split_whitespace() {
+            match token.split_once(':') {
+                Some(("from", nick)) if !nick.is_empty() => {
+                    query.from = Some(nick.trim_start_matches('@').to_lowercase())
+                }
+                Some(("in", room)) if !room.is_empty() => {
+                    query.room = Some(room.trim_start_matches('#').to_lowercase())
+                }
+                Some(("before", date)) if parse_day(date).is_some() => query.before = parse_day(date),
+                // `after:` a day means from the start of the next one.
+                Some(("after", date)) if parse_day(date).is_some() => {
+                    query.after = parse_day(date).map(|day| day + DAY_MS)
+                }
+                Some(("has", "image")) => query.has_image = true,
+                _ => query.words.push(token.to_lowercase()),
+            }
+        }
+        query
+    }
+
+    /// Whether there is nothing to search for.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn matches(&self, message: &MessageData) -> bool {
+        let text = message.message.to_lowercase();
+        self.words.iter().all(|word| text.contains(word.as_str()))
+            && self.from.as_ref().is_none_or(|from| message.from.to_lowercase() == *from)
+            && self.room.as_ref().is_none_or(|room| room == ROOM)
+            && self.before.is_none_or(|before| message.time < before)
+            && self.after.is_none_or(|after| message.time >= after)
+            && (!self.has_image || is_image(&message.message))
+    }
+}
+
+/// Whether the message list shows `text` as an image.
+pub fn is_image(text: &str) -> bool {
+    text.ends_with(".gif")
+}
+
+/// Midnight UTC at the start of a `YYYY-MM-DD` day, in milliseconds since the epoch.
+fn parse_day(date: &str) -> Option<f64> {
+    let shape = date.len() == 10
+        && date
+            .bytes()
+            .enumerate()
+            .all(|(i, b)| if i == 4 || i == 7 { b == b'-' } else { b.is_ascii_digit() });
+    if !shape {
+        return None;
+    }
+    let year: i64 = date[..4].parse().ok()?;
+    let month: i64 = date[5..7].parse().ok()?;
+    let day: i64 = date[8..].parse().ok()?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+    // Days since 1970-01-01, counting years from March so leap days come last.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some((era * 146_097 + day_of_era - 719_468) as f64 * DAY_MS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(from: &str, text: &str, time: f64) -> MessageData {
+        MessageData {
+            id: 1,
+            from: from.into(),
+            message: text.into(),
+            mentions: vec![],
+            client_id: None,
+            edited: false,
+            time,
+            bot: false,
+            avatar: None,
+        }
+    }
+
+    fn words(query: &Query) -> Vec<&str> {
+        query.words.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn free_terms_are_lowercased_words() {
+        let query = Query::parse("  Deploy   FRIDAY ");
+        assert_eq!(words(&query), ["deploy", "friday"]);
+        assert!(query.matches(&message("alice", "deploy on friday?", 0.0)));
+        assert!(!query.matches(&message("alice", "deploy on monday", 0.0)));
+        assert!(Query::parse("   ").is_empty());
+    }
+
+    #[test]
+    fn quotes_do_not_group_words() {
+        // Each word has to appear, but not necessarily together or in order.
+        let query = Query::parse("\"release notes\"");
+        assert_eq!(words(&query), ["\"release", "notes\""]);
+        assert!(!query.matches(&message("alice", "release notes", 0.0)));
+        assert!(query.matches(&message("alice", "the \"release notes\" are up", 0.0)));
+    }
+
+    #[test]
+    fn from_matches_the_sender_with_or_without_an_at() {
+        assert_eq!(Query::parse("from:@Alice").from.as_deref(), Some("alice"));
+        let query = Query::parse("from:alice hi");
+        assert!(query.matches(&message("ALICE", "hi there", 0.0)));
+        assert!(!query.matches(&message("bob", "hi there", 0.0)));
+    }
+
+    #[test]
+    fn in_only_matches_the_room_we_have() {
+        assert!(Query::parse("in:#General").matches(&message("alice", "hi", 0.0)));
+        assert!(!Query::parse("in:random").matches(&message("alice", "hi", 0.0)));
+    }
+
+    #[test]
+    fn before_and_after_bound_the_day() {
+        let query = Query::parse("after:2024-03-01 before:2024-03-03");
+        assert_eq!(query.after, parse_day("2024-03-02"));
+        assert_eq!(query.before, parse_day("2024-03-03"));
+        let day = parse_day("2024-03-02").unwrap();
+        assert!(query.matches(&message("alice", "hi", day)));
+        assert!(query.matches(&message("alice", "hi", day + DAY_MS - 1.0)));
+        assert!(!query.matches(&message("alice", "hi", day - 1.0)));
+        assert!(!query.matches(&message("alice", "hi", day + DAY_MS)));
+    }
+
+    #[test]
+    fn has_image_matches_images_only() {
+        let query = Query::parse("has:image");
+        assert!(query.has_image);
+        assert!(query.words.is_empty());
+        assert!(query.matches(&message("alice", "https://example.com/cat.gif", 0.0)));
+        assert!(!query.matches(&message("alice", "a cat", 0.0)));
+        assert_eq!(words(&Query::parse("has:file")), ["has:file"]);
+    }
+
+    #[test]
+    fn filters_we_cannot_read_are_words() {
+        let query = Query::parse("from: before:yesterday after:2024-13-01 before:2023-02-29");
+        assert_eq!(words(&query), ["from:", "before:yesterday", "after:2024-13-01", "before:2023-02-29"]);
+        assert_eq!((query.from, query.before, query.after), (None, None, None));
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        for date in ["2024-1-01", "2024/01/01", "24-01-01", "2024-00-10", "2024-01-00", "2024-01-32", "2024-04-31", "2023-02-29", "+024-01-01", "２０２４-01-01"] {
+            assert_eq!(parse_day(date), None, "{}", date);
+        }
+    }
+
+    #[test]
+    fn days_count_from_the_epoch_across_leap_days() {
+        assert_eq!(parse_day("1970-01-01"), Some(0.0));
+        assert_eq!(parse_day("1969-12-31"), Some(-DAY_MS));
+        assert_eq!(parse_day("2000-02-29"), Some(951_782_400_000.0));
+        assert_eq!(parse_day("2024-02-29"), Some(1_709_164_800_000.0));
+        assert_eq!(parse_day("2024-03-01"), Some(1_709_251_200_000.0));
+        assert_eq!(parse_day("1900-02-29"), None);
+    }
+}
@@ -0,0 +1,523 @@
+use std::rc::Rc;
+
+use crate::services::protocol::{MessageData, MsgTypes, WebSocketMessage};
+
+// The message list holds the server's messages in ID order, followed by our own
+// messages that the server hasn't stored yet. Those "pending" copies have no ID
+// (`id == 0`) and are swapped for the real thing when its ack or echo arrives,
+// whichever comes first.
+
+pub fn is_pending(message: &MessageData) -> bool {
+    message.id == 0
+}
+
+fn confirmed_len(messages: &[Rc<MessageData>]) -> usize {
+    messages.iter().position(|m| is_pending(m)).unwrap_or(messages.len())
+}
+
+/// The messages the server has stored, oldest first.
+pub fn confirmed(messages: &[Rc<MessageData>]) -> &[Rc<MessageData>] {
+    &messages[..confirmed_len(messages)]
+}
+
+/// Our messages the server hasn't stored yet, in the order we sent them.
+pub fn pending(messages: &[Rc<MessageData>]) -> &[Rc<MessageData>] {
+    &messages[confirmed_len(messages)..]
+}
+
+/// ID of the newest message the server has stored.
+pub fn newest_id(messages: &[Rc<MessageData>]) -> Option<u64> {
+    confirmed(messages).last().map(|m| m.id)
+}
+
+/// ID of the oldest message the server has stored.
+pub fn oldest_id(messages: &[Rc<MessageData>]) -> Option<u64> {
+    messages.first().filter(|m| !is_pending(m)).map(|m| m.id)
+}
+
+/// Shows one of our messages before the server has it.
+pub fn push_pending(messages: &mut Vec<Rc<MessageData>>, message: MessageData) {
+    debug_assert!(is_pending(&message) && message.client_id.is_some());
+    messages.push(Rc::new(message));
+}
+
+fn take_pending(messages: &mut Vec<Rc<MessageData>>, from: &str, client_id: &str) -> Option<Rc<MessageData>> {
+    let index = messages
+        .iter()
+        .position(|m| is_pending(m) && m.from == from && m.client_id.as_deref() == Some(client_id))?;
+    Some(messages.remove(index))
+}
+
+/// Adds a message from the server in ID order, replacing our pending copy of it if
+/// there is one. Returns false if we already had it.
+pub fn insert(messages: &mut Vec<Rc<MessageData>>, message: MessageData) -> bool {
+    let replaced = match &message.client_id {
+        Some(client_id) => take_pending(messages, &message.from, client_id).is_some(),
+        None => false,
+    };
+    let confirmed = confirmed_len(messages);
+    match messages[..confirmed].binary_search_by_key(&message.id, |m| m.id) {
+        Ok(_) => replaced,
+        Err(at) => {
+            messages.insert(at, Rc::new(message));
+            true
+        }
+    }
+}
+
+/// The server stored our pending message `client_id` as `id`. Returns false if it
+/// was no longer pending, say because its echo beat the ack.
+pub fn confirm(messages: &mut Vec<Rc<MessageData>>, from: &str, client_id: &str, id: u64) -> bool {
+    match take_pending(messages, from, client_id) {
+        Some(pending) => {
+            let mut message = (*pending).clone();
+            message.id = id;
+            insert(messages, message);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Whether the newest page of history, as sent when we register, joins up with the
+/// messages we hold. It doesn't if we missed more than a page, or if the server
+/// started over and is handing out IDs from scratch.
+pub fn continues(messages: &[Rc<MessageData>], page: &[MessageData]) -> bool {
+    match (newest_id(messages), page.first(), page.last()) {
+        (None, _, _) => true,
+        (Some(newest), Some(first), Some(last)) => first.id <= newest + 1 && last.id >= newest,
+        (Some(_), _, _) => false,
+    }
+}
+
+/// Whether messages from the offline cache join up with the oldest of the messages
+/// we hold, so restoring them leaves no gap. A cache reaching past our newest message
+/// is from some other run of the server.
+pub fn joins(messages: &[Rc<MessageData>], cached: &[MessageData]) -> bool {
+    let ids = cached.iter().filter(|m| !is_pending(m)).map(|m| m.id);
+    match (oldest_id(messages), newest_id(messages), ids.clone().min(), ids.max()) {
+        (None, _, _, _) | (_, _, None, _) => true,
+        (Some(oldest), Some(newest), Some(first), Some(last)) => first <= oldest && last + 1 >= oldest && last <= newest,
+        _ => false,
+    }
+}
+
+/// Drops everything but our pending messages.
+pub fn clear_confirmed(messages: &mut Vec<Rc<MessageData>>) {
+    messages.retain(|m| is_pending(m));
+}
+
+/// Swaps in a newer version of a message we hold, such as an edit. Returns false if
+/// we don't hold it.
+pub fn replace(messages: &mut [Rc<MessageData>], message: MessageData) -> bool {
+    let confirmed = confirmed_len(messages);
+    match messages[..confirmed].binary_search_by_key(&message.id, |m| m.id) {
+        Ok(index) => {
+            messages[index] = Rc::new(message);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Drops a message the server deleted. Returns false if we don't hold it.
+pub fn remove(messages: &mut Vec<Rc<MessageData>>, id: u64) -> bool {
+    let confirmed = confirmed_len(messages);
+    match messages[..confirmed].binary_search_by_key(&id, |m| m.id) {
+        Ok(index) => {
+            messages.remove(index);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Merges a page of history.
+pub fn merge(messages: &mut Vec<Rc<MessageData>>, page: Vec<MessageData>) {
+    for message in page {
+        insert(messages, message);
+    }
+}
+
+/// What a page of history did to the timeline.
+pub struct Page {
+    /// ID of our oldest message before the page came in, if we had any.
+    pub old_first: Option<u64>,
+    /// Whether the page held anything older than that.
+    pub prepends: bool,
+}
+
+/// The messages we hold, and how far back the server has more.
+pub struct Timeline {
+    messages: Vec<Rc<MessageData>>,
+    /// Whether the server may have messages older than the first one we hold.
+    has_older: bool,
+    loading_older: bool,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            messages: vec![],
+            has_older: true,
+            loading_older: false,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn messages(&self) -> &[Rc<MessageData>] {
+        &self.messages
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn newest_id(&self) -> Option<u64> {
+        newest_id(&self.messages)
+    }
+
+    /// IDs of the messages the server has stored, oldest first.
+    pub fn keys(&self) -> Vec<u64> {
+        confirmed(&self.messages).iter().map(|m| m.id).collect()
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        confirmed(&self.messages).binary_search_by_key(&id, |m| m.id).is_ok()
+    }
+
+    /// Our newest message the server has stored.
+    pub fn last_from(&self, me: &str) -> Option<Rc<MessageData>> {
+        confirmed(&self.messages).iter().rev().find(|m| m.from == me).cloned()
+    }
+
+    /// A `message` frame. Returns false if we already had it.
+    pub fn receive(&mut self, message: MessageData) -> bool {
+        insert(&mut self.messages, message)
+    }
+
+    /// A `history` frame: the newest page after we register, or an older one we
+    /// asked for.
+    pub fn receive_page(&mut self, page: Vec<MessageData>) -> Page {
+        if !self.loading_older && !continues(&self.messages, &page) {
+            // What we had cached can't be joined up with the server's messages, so
+            // start over from them.
+            clear_confirmed(&mut self.messages);
+            self.has_older = true;
+        }
+        let old_first = oldest_id(&self.messages);
+        let prepends = old_first.is_none_or(|first| page.iter().any(|m| m.id < first));
+        if self.loading_older && !prepends {
+            // Nothing older than what we hold: we've reached the start.
+            self.has_older = false;
+        }
+        self.loading_older = false;
+        // Merge by ID, keeping anything that arrived live before the history did.
+        merge(&mut self.messages, page);
+        Page { old_first, prepends }
+    }
+
+    /// Merges what the offline cache held, unless the server's messages got here first
+    /// and the cache doesn't join up with them. Returns whether anything was merged.
+    pub fn restore(&mut self, cached: Vec<MessageData>) -> bool {
+        if !joins(&self.messages, &cached) {
+            return false;
+        }
+        merge(&mut self.messages, cached);
+        true
+    }
+
+    /// An `ack` frame for one of our pending messages.
+    pub fn confirm(&mut self, from: &str, client_id: &str, id: u64) -> bool {
+        confirm(&mut self.messages, from, client_id, id)
+    }
+
+    /// An `edit` frame.
+    pub fn edit(&mut self, message: MessageData) -> bool {
+        replace(&mut self.messages, message)
+    }
+
+    /// A `delete` frame.
+    pub fn delete(&mut self, id: u64) -> bool {
+        remove(&mut self.messages, id)
+    }
+
+    pub fn push_pending(&mut self, message: MessageData) {
+        push_pending(&mut self.messages, message);
+    }
+
+    /// Drops everything the server has stored, as `/clear` does.
+    pub fn clear(&mut self) {
+        clear_confirmed(&mut self.messages);
+        self.has_older = false;
+    }
+
+    /// Drops the oldest messages once we hold more than `keep`, returning their
+    /// IDs; scrolling back up fetches them again.
+    pub fn evict(&mut self, keep: usize) -> Vec<u64> {
+        if self.messages.len() <= keep {
+            return vec![];
+        }
+        let excess = self.messages.len() - keep;
+        self.has_older = true;
+        self.messages.drain(..excess).map(|m| m.id).collect()
+    }
+
+    /// Asks for the page of history before our oldest message, unless the server
+    /// has nothing older or we are already waiting on it.
+    pub fn load_older(&mut self) -> Option<WebSocketMessage> {
+        if self.loading_older || !self.has_older {
+            return None;
+        }
+        let first = oldest_id(&self.messages)?;
+        self.loading_older = true;
+        Some(history_frame(first, None))
+    }
+
+    /// Asks for everything between our oldest message and `id`, so we can jump to it.
+    pub fn load_back_to(&mut self, id: u64) -> Option<WebSocketMessage> {
+        match oldest_id(&self.messages) {
+            Some(first) if id < first && !self.loading_older => {
+                self.loading_older = true;
+                Some(history_frame(first, Some(id)))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn history_frame(before: u64, back_to: Option<u64>) -> WebSocketMessage {
+    WebSocketMessage {
+        message_type: MsgTypes::History,
+        data: Some(before.to_string()),
+        data_array: back_to.map(|id| vec![id.to_string()]),
+        client_id: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: u64, from: &str, client_id: Option<&str>) -> MessageData {
+        MessageData {
+            id,
+            from: from.into(),
+            message: format!("message {}", id),
+            mentions: vec![],
+            client_id: client_id.map(String::from),
+            edited: false,
+            time: 0.0,
+            bot: false,
+            avatar: None,
+        }
+    }
+
+    fn pending(from: &str, client_id: &str) -> MessageData {
+        message(0, from, Some(client_id))
+    }
+
+    /// (id, client ID) of every message, in order.
+    fn summary(messages: &[Rc<MessageData>]) -> Vec<(u64, Option<String>)> {
+        messages.iter().map(|m| (m.id, m.client_id.clone())).collect()
+    }
+
+    #[test]
+    fn echo_replaces_the_pending_copy() {
+        let mut messages = vec![];
+        insert(&mut messages, message(1, "bob", None));
+        push_pending(&mut messages, pending("me", "a"));
+
+        assert!(insert(&mut messages, message(2, "me", Some("a"))));
+        assert_eq!(summary(&messages), vec![(1, None), (2, Some("a".into()))]);
+    }
+
+    #[test]
+    fn duplicate_echo_is_ignored() {
+        let mut messages = vec![];
+        push_pending(&mut messages, pending("me", "a"));
+        insert(&mut messages, message(2, "me", Some("a")));
+
+        assert!(!insert(&mut messages, message(2, "me", Some("a"))));
+        assert_eq!(summary(&messages), vec![(2, Some("a".into()))]);
+    }
+
+    #[test]
+    fn ack_then_echo_keeps_one_copy() {
+        let mut messages = vec![];
+        push_pending(&mut messages, pending("me", "a"));
+
+        assert!(confirm(&mut messages, "me", "a", 5));
+        assert!(!insert(&mut messages, message(5, "me", Some("a"))));
+        assert_eq!(summary(&messages), vec![(5, Some("a".into()))]);
+    }
+
+    #[test]
+    fn echo_then_ack_keeps_one_copy() {
+        let mut messages = vec![];
+        push_pending(&mut messages, pending("me", "a"));
+
+        insert(&mut messages, message(5, "me", Some("a")));
+        assert!(!confirm(&mut messages, "me", "a", 5));
+        assert_eq!(summary(&messages), vec![(5, Some("a".into()))]);
+    }
+
+    #[test]
+    fn out_of_order_echoes_end_up_in_id_order() {
+        let mut messages = vec![];
+        push_pending(&mut messages, pending("me", "a"));
+        push_pending(&mut messages, pending("me", "b"));
+
+        insert(&mut messages, message(11, "me", Some("b")));
+        // `a` is still pending, so it stays after everything the server has stored.
+        assert_eq!(summary(&messages), vec![(11, Some("b".into())), (0, Some("a".into()))]);
+
+        insert(&mut messages, message(10, "me", Some("a")));
+        assert_eq!(summary(&messages), vec![(10, Some("a".into())), (11, Some("b".into()))]);
+    }
+
+    #[test]
+    fn messages_from_others_go_before_pending_ones() {
+        let mut messages = vec![];
+        insert(&mut messages, message(1, "bob", None));
+        push_pending(&mut messages, pending("me", "a"));
+
+        insert(&mut messages, message(2, "bob", None));
+        assert_eq!(summary(&messages), vec![(1, None), (2, None), (0, Some("a".into()))]);
+        assert_eq!(newest_id(&messages), Some(2));
+        assert_eq!(summary(super::pending(&messages)), vec![(0, Some("a".into()))]);
+    }
+
+    #[test]
+    fn someone_elses_client_id_does_not_match_ours() {
+        let mut messages = vec![];
+        push_pending(&mut messages, pending("me", "a"));
+
+        insert(&mut messages, message(3, "bob", Some("a")));
+        assert_eq!(summary(&messages), vec![(3, Some("a".into())), (0, Some("a".into()))]);
+    }
+
+    #[test]
+    fn history_merges_around_pending_messages() {
+        let mut messages = vec![];
+        insert(&mut messages, message(20, "bob", None));
+        push_pending(&mut messages, pending("me", "a"));
+
+        merge(&mut messages, vec![message(10, "bob", None), message(20, "bob", None), message(15, "me", Some("x"))]);
+        assert_eq!(
+            summary(&messages),
+            vec![(10, None), (15, Some("x".into())), (20, None), (0, Some("a".into()))]
+        );
+        assert_eq!(oldest_id(&messages), Some(10));
+    }
+
+    #[test]
+    fn first_page_must_join_up_with_cached_messages() {
+        let mut messages = vec![];
+        merge(&mut messages, (1..=5).map(|id| message(id, "bob", None)).collect());
+
+        let page = |ids: std::ops::RangeInclusive<u64>| ids.map(|id| message(id, "bob", None)).collect::<Vec<_>>();
+        assert!(continues(&messages, &page(3..=5)));
+        assert!(continues(&messages, &page(6..=9)));
+        // Missed message 6.
+        assert!(!continues(&messages, &page(7..=9)));
+        // The server restarted.
+        assert!(!continues(&messages, &page(1..=2)));
+        assert!(!continues(&messages, &[]));
+        assert!(continues(&[], &[]));
+    }
+
+    #[test]
+    fn the_cache_only_fills_in_behind_what_we_hold() {
+        let mut messages = vec![];
+        assert!(joins(&messages, &page(1..=5)));
+        merge(&mut messages, page(6..=9));
+
+        assert!(joins(&messages, &page(1..=5)));
+        assert!(joins(&messages, &page(3..=7)));
+        assert!(joins(&messages, &[]));
+        // Messages 5 to 7 went by while the tab was closed.
+        assert!(!joins(&messages, &page(1..=4)));
+        // From before the server restarted and started its IDs over.
+        assert!(!joins(&messages, &page(1..=12)));
+    }
+
+    #[test]
+    fn restoring_after_history_drops_a_cache_that_leaves_a_gap() {
+        let mut timeline = Timeline::default();
+        timeline.receive_page(page(11..=20));
+
+        assert!(!timeline.restore(page(1..=5)));
+        assert_eq!(timeline.keys(), (11..=20).collect::<Vec<_>>());
+        assert!(timeline.restore(page(6..=12)));
+        assert_eq!(timeline.keys(), (6..=20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn remove_leaves_pending_messages_alone() {
+        let mut messages = vec![];
+        merge(&mut messages, (1..=3).map(|id| message(id, "bob", None)).collect());
+        push_pending(&mut messages, pending("me", "a"));
+
+        assert!(remove(&mut messages, 2));
+        assert!(!remove(&mut messages, 2));
+        // A pending copy has no ID to delete it by.
+        assert!(!remove(&mut messages, 0));
+        assert_eq!(summary(&messages), vec![(1, None), (3, None), (0, Some("a".into()))]);
+    }
+
+    fn page(ids: std::ops::RangeInclusive<u64>) -> Vec<MessageData> {
+        ids.map(|id| message(id, "bob", None)).collect()
+    }
+
+    #[test]
+    fn paging_back_stops_at_the_start() {
+        let mut timeline = Timeline::default();
+        timeline.receive_page(page(11..=20));
+
+        let frame = timeline.load_older().unwrap();
+        assert_eq!(frame.data.as_deref(), Some("11"));
+        // Only one request at a time.
+        assert!(timeline.load_older().is_none());
+
+        let older = timeline.receive_page(page(1..=10));
+        assert_eq!(older.old_first, Some(11));
+        assert!(older.prepends);
+
+        timeline.load_older().unwrap();
+        let nothing = timeline.receive_page(vec![]);
+        assert!(!nothing.prepends);
+        assert!(timeline.load_older().is_none());
+        assert_eq!(timeline.keys(), (1..=20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn a_gap_after_reconnecting_starts_over() {
+        let mut timeline = Timeline::default();
+        timeline.restore(page(1..=5));
+        timeline.push_pending(pending("me", "a"));
+
+        let fresh = timeline.receive_page(page(8..=9));
+        assert_eq!(fresh.old_first, None);
+        assert_eq!(summary(timeline.messages()), vec![(8, None), (9, None), (0, Some("a".into()))]);
+    }
+
+    #[test]
+    fn evicting_drops_the_oldest_and_allows_paging_back() {
+        let mut timeline = Timeline::default();
+        timeline.receive_page(page(1..=5));
+        timeline.load_older().unwrap();
+        timeline.receive_page(vec![]);
+        assert!(timeline.load_older().is_none());
+
+        assert_eq!(timeline.evict(3), vec![1, 2]);
+        assert!(timeline.evict(3).is_empty());
+        assert_eq!(timeline.keys(), vec![3, 4, 5]);
+        assert_eq!(timeline.load_older().unwrap().data.as_deref(), Some("3"));
+    }
+}
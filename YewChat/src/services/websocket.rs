@@ -1,53 +1,232 @@
-use futures::{channel::mpsc::Sender, SinkExt, StreamExt};
-use reqwasm::websocket::{futures::WebSocket, Message};
+use futures::{
+    channel::mpsc::{Receiver, Sender},
+    FutureExt, SinkExt, StreamExt,
+};
+use gloo_timers::future::TimeoutFuture;
+use reqwasm::websocket::{futures::WebSocket, Message, WebSocketError};
 
 use wasm_bindgen_futures::spawn_local;
-use yew_agent::Dispatched;
+use yew_agent::{Dispatched, Dispatcher};
 
-use crate::services::event_bus::{EventBus, Request};
+use crate::services::event_bus::{ConnectionState, Event, EventBus, Request};
+use crate::services::protocol::{
+    Beat, Heartbeat, MsgTypes, ServerEvent, WebSocketMessage, CLOSE_REMOVED, CLOSE_TOO_MANY_CONNECTIONS,
+    PING_INTERVAL_MS, PROTOCOL_VERSION, SILENCE_TIMEOUT_MS,
+};
 
+/// Where the chat server lives; it serves both the WebSocket and plain HTTP.
+pub const SERVER_HOST: &str = "127.0.0.1:8080";
+
+/// How long to wait before the first reconnect attempt; doubles up to the max.
+const INITIAL_RETRY_MS: u32 = 1000;
+const MAX_RETRY_MS: u32 = 30 * 1000;
+/// How long to wait after being turned away for having too many connections open.
+/// Closing another tab frees one up; trying again any sooner only adds to the pile.
+const CROWDED_RETRY_MS: u32 = 5 * 60 * 1000;
+/// How long a new connection waits for the server's `hello` before carrying on
+/// without it.
+const HELLO_TIMEOUT_MS: u32 = 2000;
+
+fn publish(event_bus: &mut Dispatcher<EventBus>, frame: &str) {
+    match ServerEvent::decode(frame) {
+        Ok(ServerEvent::Pong) => {}
+        Ok(event) => {
+            if let ServerEvent::Hello(hello) = &event {
+                if hello.protocol_version != PROTOCOL_VERSION {
+                    log::warn!(
+                        "{} speaks protocol version {}, we speak {}",
+                        hello.server_name,
+                        hello.protocol_version,
+                        PROTOCOL_VERSION
+                    );
+                }
+            }
+            event_bus.send(Request::Publish(Event::Server(event)))
+        }
+        Err(e) => log::error!("dropping frame {}: {}", frame, e),
+    }
+}
+
+fn receive(event_bus: &mut Dispatcher<EventBus>, message: Message) {
+    match message {
+        Message::Text(data) => {
+            log::debug!("from websocket: {}", data);
+            publish(event_bus, &data);
+        }
+        Message::Bytes(b) => {
+            if let Ok(val) = std::str::from_utf8(&b) {
+                log::debug!("from websocket: {}", val);
+                publish(event_bus, val);
+            }
+        }
+    }
+}
+
+fn publish_state(event_bus: &mut Dispatcher<EventBus>, state: ConnectionState) {
+    event_bus.send(Request::Publish(Event::Connection(state)));
+}
+
+/// How a connection attempt ended.
+enum Session {
+    /// The socket never opened.
+    Failed,
+    /// The socket opened and was closed later.
+    Closed,
+    /// The server closed it because we were kicked or banned; coming back would
+    /// only be turned away, or undo a kick.
+    Removed,
+    /// The server closed it because too many connections are open from our address.
+    Crowded,
+    /// The service was dropped; stop for good.
+    Finished,
+}
+
+/// How the session ended, if the server closed the socket to keep us out. It reports
+/// the close just before the stream ends.
+fn turned_away(error: &WebSocketError) -> Option<Session> {
+    match error {
+        WebSocketError::ConnectionClose(close) if close.code == CLOSE_REMOVED => Some(Session::Removed),
+        WebSocketError::ConnectionClose(close) if close.code == CLOSE_TOO_MANY_CONNECTIONS => Some(Session::Crowded),
+        _ => None,
+    }
+}
+
+fn ping_frame() -> String {
+    serde_json::to_string(&WebSocketMessage {
+        message_type: MsgTypes::Ping,
+        data: None,
+        data_array: None,
+        client_id: None,
+    })
+    .unwrap()
+}
+
+/// Frames written to `tx` go out once the socket is open; frames from the server are
+/// decoded and published on the `EventBus`. The connection is re-established with
+/// backoff whenever it drops, or when the server goes quiet and stops answering our
+/// pings, with `ConnectionState` events to match.
 pub struct WebsocketService {
     pub tx: Sender<String>,
 }
 
 impl WebsocketService {
     pub fn new() -> Self {
-        let ws = WebSocket::open("ws://127.0.0.1:8080").unwrap();
+        let (in_tx, in_rx) = futures::channel::mpsc::channel::<String>(1000);
+        let mut event_bus = EventBus::dispatcher();
+        // Published before anyone can subscribe, so a state left over from an
+        // earlier service is never replayed to new subscribers.
+        publish_state(&mut event_bus, ConnectionState::Connecting);
 
-        let (mut write, mut read) = ws.split();
+        spawn_local(run(in_rx, event_bus));
 
-        let (in_tx, mut in_rx) = futures::channel::mpsc::channel::<String>(1000);
-        let mut event_bus = EventBus::dispatcher();
+        Self { tx: in_tx }
+    }
+}
 
-        spawn_local(async move {
-            while let Some(s) = in_rx.next().await {
-                log::debug!("got event from channel! {}", s);
-                write.send(Message::Text(s)).await.unwrap();
+async fn run(mut in_rx: Receiver<String>, mut event_bus: Dispatcher<EventBus>) {
+    let mut retry_ms = INITIAL_RETRY_MS;
+    loop {
+        let delay = match connect(&mut in_rx, &mut event_bus).await {
+            Session::Finished => return,
+            Session::Removed => {
+                publish_state(&mut event_bus, ConnectionState::Closed);
+                return;
             }
-        });
-
-        spawn_local(async move {
-            while let Some(msg) = read.next().await {
-                match msg {
-                    Ok(Message::Text(data)) => {
-                        log::debug!("from websocket: {}", data);
-                        event_bus.send(Request::EventBusMsg(data));
-                    }
-                    Ok(Message::Bytes(b)) => {
-                        let decoded = std::str::from_utf8(&b);
-                        if let Ok(val) = decoded {
-                            log::debug!("from websocket: {}", val);
-                            event_bus.send(Request::EventBusMsg(val.into()));
-                        }
+            Session::Crowded => CROWDED_RETRY_MS,
+            Session::Closed => {
+                retry_ms = INITIAL_RETRY_MS;
+                retry_ms
+            }
+            Session::Failed => retry_ms,
+        };
+        publish_state(&mut event_bus, ConnectionState::Closed);
+        TimeoutFuture::new(delay).await;
+        retry_ms = (retry_ms * 2).min(MAX_RETRY_MS);
+        publish_state(&mut event_bus, ConnectionState::Connecting);
+    }
+}
+
+async fn connect(in_rx: &mut Receiver<String>, event_bus: &mut Dispatcher<EventBus>) -> Session {
+    let ws = match WebSocket::open(&format!("ws://{}", SERVER_HOST)) {
+        Ok(ws) => ws,
+        Err(e) => {
+            log::error!("ws: {:?}", e);
+            return Session::Failed;
+        }
+    };
+    let (mut write, mut read) = ws.split();
+
+    // The sink turns ready once the socket opens; the stream ends if it fails first.
+    let opened = futures::select! {
+        ready = futures::future::poll_fn(|cx| write.poll_ready_unpin(cx)).fuse() => ready.is_ok(),
+        _ = read.next().fuse() => false,
+    };
+    if !opened {
+        return Session::Failed;
+    }
+    // The server's `hello` goes out ahead of `Open`, so its limits are known before
+    // anything is sent. Servers from before it say nothing until spoken to.
+    let greeting = futures::select! {
+        msg = read.next().fuse() => Some(msg),
+        _ = TimeoutFuture::new(HELLO_TIMEOUT_MS).fuse() => None,
+    };
+    match greeting {
+        Some(Some(Ok(msg))) => receive(event_bus, msg),
+        Some(Some(Err(e))) => match turned_away(&e) {
+            Some(session) => return session,
+            None => log::error!("ws: {:?}", e),
+        },
+        Some(None) => return Session::Closed,
+        None => log::warn!("no hello from the server; carrying on without it"),
+    }
+    publish_state(event_bus, ConnectionState::Open);
+
+    let mut heartbeat = Heartbeat::new(PING_INTERVAL_MS, SILENCE_TIMEOUT_MS, js_sys::Date::now());
+    loop {
+        let wait = match heartbeat.poll(js_sys::Date::now()) {
+            Beat::Wait(ms) => ms.ceil() as u32,
+            Beat::Ping => {
+                if let Err(e) = write.send(Message::Text(ping_frame())).await {
+                    log::error!("ws: {:?}", e);
+                    return Session::Closed;
+                }
+                continue;
+            }
+            Beat::Dead => {
+                // A half-open connection never closes by itself; give it up.
+                log::warn!("the server has stopped answering; reconnecting");
+                return Session::Closed;
+            }
+        };
+        futures::select! {
+            _ = TimeoutFuture::new(wait).fuse() => {}
+            msg = read.next().fuse() => match msg {
+                Some(Ok(msg)) => {
+                    heartbeat.heard(js_sys::Date::now());
+                    receive(event_bus, msg);
+                }
+                Some(Err(e)) => match turned_away(&e) {
+                    Some(session) => {
+                        log::debug!("turned away by the server: {:?}", e);
+                        return session;
                     }
-                    Err(e) => {
-                        log::error!("ws: {:?}", e)
+                    None => log::error!("ws: {:?}", e),
+                },
+                None => {
+                    log::debug!("WebSocket Closed");
+                    return Session::Closed;
+                }
+            },
+            s = in_rx.next() => match s {
+                Some(s) => {
+                    log::debug!("got event from channel! {}", s);
+                    if let Err(e) = write.send(Message::Text(s)).await {
+                        log::error!("ws: {:?}", e);
+                        return Session::Closed;
                     }
                 }
-            }
-            log::debug!("WebSocket Closed");
-        });
-
-        Self { tx: in_tx }
+                None => return Session::Finished,
+            },
+        }
     }
 }